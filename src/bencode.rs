@@ -1,43 +1,361 @@
-pub fn bencode_bytes(bytes: &[u8]) -> Vec<u8> {
-    let mut bcode: Vec<u8> = Vec::new();
-    let len = bytes.len();
-    bcode.extend(len.to_string().as_bytes());
-    bcode.push(b':');
-    bcode.extend(bytes);
-    bcode
-}
-
-pub fn bencode_string(s: &str) -> Vec<u8> {
-    let mut bcode: Vec<u8> = Vec::new();
-    let len = s.len();
-    bcode.extend(len.to_string().as_bytes());
-    bcode.push(b':');
-    bcode.extend(s.as_bytes());
-    bcode
-}
-
-pub fn bencode_uint(i: usize) -> Vec<u8> {
-    let mut bcode: Vec<u8> = Vec::new();
-    bcode.push(b'i');
-    bcode.extend(i.to_string().as_bytes());
-    bcode.push(b'e');
-    bcode
-}
-
-pub fn bencode_int(i: i64) -> Vec<u8> {
-    let mut bcode: Vec<u8> = Vec::new();
-    bcode.push(b'i');
-    bcode.extend(i.to_string().as_bytes());
-    bcode.push(b'e');
-    bcode
-}
-
-pub fn bencode_string_list(list: &[String]) -> Vec<u8> {
-    let mut bcode: Vec<u8> = Vec::new();
-    bcode.push(b'l');
+use std::collections::{BTreeMap, HashMap, btree_map};
+use std::fmt::{Display, Formatter, Result as fmtResult};
+use std::io::{Result as ioResult, Write};
+use std::iter::Peekable;
+
+use crate::utils::{TrError, TrResult};
+
+/// A parsed bencode value borrowing its byte strings from the input.
+pub enum Bencode<'a> {
+    Int(usize),
+    UInt(i64),
+    Bytes(&'a [u8]),
+    List(Vec<Bencode<'a>>),
+    Dict(HashMap<String, Bencode<'a>>),
+}
+
+// far deeper than any torrent, shallow enough that crafted input cannot overflow the stack
+const MAX_DEPTH: usize = 256;
+const MAX_SHOWN_BYTES: usize = 32; // of binary strings in `write_tree`
+
+/// Parses the value starting at `pos` and leaves `pos` just past it.
+pub fn parse_bencode<'a>(data: &'a [u8], pos: &mut usize) -> TrResult<Bencode<'a>> {
+    parse_value(data, pos, 0)
+}
+
+/// Raw bytes of the values of a dict, by key.
+pub type RawValues<'a> = HashMap<&'a [u8], &'a [u8]>;
+
+/// Parses a dict like `parse_bencode`, and in the same pass records the byte span of
+/// each value, so a value can be kept exactly as stored, e.g. the info dict whose bytes
+/// give the info-hash.
+pub fn parse_dict_spans<'a>(
+    data: &'a [u8],
+    pos: &mut usize,
+) -> TrResult<(HashMap<String, Bencode<'a>>, RawValues<'a>)> {
+    if data.get(*pos) != Some(&b'd') {
+        return Err(TrError::InvalidTorrent(String::from("not a dictionary")));
+    }
+    *pos += 1;
+    let mut map = HashMap::new();
+    let mut raw_values = HashMap::new();
+    while data.get(*pos) != Some(&b'e') {
+        let key = match parse_value(data, pos, 1)? {
+            Bencode::Bytes(b) => b,
+            _ => {
+                return Err(TrError::InvalidTorrent(String::from("dict key not string")));
+            }
+        };
+        let start = *pos;
+        let val = parse_value(data, pos, 1)?;
+        raw_values.insert(key, &data[start..*pos]);
+        map.insert(key_string(key), val);
+    }
+    *pos += 1;
+    Ok((map, raw_values))
+}
+
+/// Dict key as text, invalid UTF-8 bytes written as `\xNN` so that keys differing in
+/// them are shown, and kept, apart.
+fn key_string(key: &[u8]) -> String {
+    let mut out = String::with_capacity(key.len());
+    for chunk in key.utf8_chunks() {
+        out.push_str(chunk.valid());
+        for byte in chunk.invalid() {
+            out.push_str(&format!("\\x{byte:02x}"));
+        }
+    }
+    out
+}
+
+/// Returns the bencoded dict `raw` with the value of `key` replaced by the bencoded
+/// `value`, or removed for `None`. Other keys keep their bytes and order, a new key goes
+/// before the first larger one.
+pub fn patch_dict(raw: &[u8], key: &str, value: Option<&[u8]>) -> TrResult<Vec<u8>> {
+    if raw.first() != Some(&b'd') {
+        return Err(TrError::InvalidTorrent(String::from("not a dictionary")));
+    }
+    let mut out = vec![b'd'];
+    let mut done = false;
+    let mut pos = 1;
+    while raw.get(pos) != Some(&b'e') {
+        let start = pos;
+        let entry_key = match parse_value(raw, &mut pos, 1)? {
+            Bencode::Bytes(b) => b,
+            _ => {
+                return Err(TrError::InvalidTorrent(String::from("dict key not string")));
+            }
+        };
+        parse_value(raw, &mut pos, 1)?;
+        if !done && entry_key >= key.as_bytes() {
+            if let Some(value) = value {
+                write_string(&mut out, key)?;
+                out.extend_from_slice(value);
+            }
+            done = true;
+            if entry_key == key.as_bytes() {
+                continue;
+            }
+        }
+        out.extend_from_slice(&raw[start..pos]);
+    }
+    if !done && let Some(value) = value {
+        write_string(&mut out, key)?;
+        out.extend_from_slice(value);
+    }
+    out.push(b'e');
+    Ok(out)
+}
+
+fn parse_value<'a>(data: &'a [u8], pos: &mut usize, depth: usize) -> TrResult<Bencode<'a>> {
+    if depth > MAX_DEPTH {
+        return Err(TrError::InvalidTorrent(format!(
+            "lists and dicts nested deeper than {MAX_DEPTH}"
+        )));
+    }
+    match data.get(*pos) {
+        Some(b'i') => {
+            *pos += 1;
+            let start = *pos;
+            while *pos < data.len() && data[*pos] != b'e' {
+                *pos += 1;
+            }
+            if *pos >= data.len() {
+                return Err("unterminated integer".into());
+            }
+            let num_str =
+                std::str::from_utf8(&data[start..*pos]).map_err(|_| "invalid utf8 in int")?;
+            *pos += 1;
+            if num_str.starts_with("-") {
+                let val = num_str.parse::<i64>().map_err(|_| "invalid int")?;
+                Ok(Bencode::UInt(val))
+            } else {
+                let val = num_str.parse::<usize>().map_err(|_| "invalid int")?;
+                Ok(Bencode::Int(val))
+            }
+        }
+        Some(b'l') => {
+            *pos += 1;
+            let mut items = Vec::new();
+            while data.get(*pos) != Some(&b'e') {
+                items.push(parse_value(data, pos, depth + 1)?);
+            }
+            *pos += 1;
+            Ok(Bencode::List(items))
+        }
+        Some(b'd') => {
+            *pos += 1;
+            let mut map = HashMap::new();
+            while data.get(*pos) != Some(&b'e') {
+                // binary keys, e.g. the merkle roots of `piece layers`, are only ever used
+                // through the raw bytes, an escaped name keeps them apart
+                let key = match parse_value(data, pos, depth + 1)? {
+                    Bencode::Bytes(b) => key_string(b),
+                    _ => {
+                        return Err(TrError::InvalidTorrent(String::from("dict key not string")));
+                    }
+                };
+                let val = parse_value(data, pos, depth + 1)?;
+                map.insert(key, val);
+            }
+            *pos += 1;
+            Ok(Bencode::Dict(map))
+        }
+        Some(b'0'..=b'9') => {
+            let start = *pos;
+            while *pos < data.len() && data[*pos] != b':' {
+                *pos += 1;
+            }
+            if *pos >= data.len() {
+                return Err(TrError::InvalidTorrent(String::from(
+                    "truncated string length",
+                )));
+            }
+            let len_str =
+                std::str::from_utf8(&data[start..*pos]).map_err(|_| "invalid utf8 length")?;
+            let len = len_str.parse::<usize>().map_err(|_| "bad string length")?;
+            *pos += 1;
+            let end = pos
+                .checked_add(len)
+                .filter(|&end| end <= data.len())
+                .ok_or_else(|| TrError::InvalidTorrent(String::from("truncated string")))?;
+            let slice = &data[*pos..end];
+            *pos = end;
+            Ok(Bencode::Bytes(slice))
+        }
+        Some(_) => Err("unknown token".into()),
+        None => Err("unexpected EOF".into()),
+    }
+}
+
+/// Writes `value` as an indented tree, for bencoded files other than torrents such as
+/// DHT state, client settings or `nodes.dat`. Dict keys are sorted, strings that are
+/// printable UTF-8 are quoted, others are shown as their length and first bytes in hex.
+/// Keys are shown as parsed, with bytes that are not UTF-8 escaped.
+pub fn write_tree<W: Write>(out: &mut W, value: &Bencode) -> ioResult<()> {
+    write_tree_entry(out, "", value, 0)
+}
+
+fn write_tree_entry<W: Write>(
+    out: &mut W,
+    label: &str,
+    value: &Bencode,
+    depth: usize,
+) -> ioResult<()> {
+    let indent = "  ".repeat(depth);
+    match value {
+        Bencode::Int(i) => writeln!(out, "{indent}{label}{i}"),
+        Bencode::UInt(i) => writeln!(out, "{indent}{label}{i}"),
+        Bencode::Bytes(b) => match std::str::from_utf8(b) {
+            Ok(s) if !s.chars().any(char::is_control) => {
+                writeln!(out, "{indent}{label}\"{s}\"")
+            }
+            _ => writeln!(
+                out,
+                "{indent}{label}{} bytes, 0x{}{}",
+                b.len(),
+                hex::encode(&b[..b.len().min(MAX_SHOWN_BYTES)]),
+                if b.len() > MAX_SHOWN_BYTES { "..." } else { "" }
+            ),
+        },
+        Bencode::List(items) => {
+            writeln!(out, "{indent}{label}list of {}", items.len())?;
+            for item in items {
+                write_tree_entry(out, "- ", item, depth + 1)?;
+            }
+            Ok(())
+        }
+        Bencode::Dict(map) => {
+            writeln!(out, "{indent}{label}dict of {}", map.len())?;
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            for key in keys {
+                write_tree_entry(out, &format!("{key}: "), &map[key], depth + 1)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Keys in bencode order, as they have to be written.
+pub type ExtraKeys = BTreeMap<String, ExtraValue>;
+
+/// Value of a key this tool has no field for, set with `--info-key`/`--root-key` or read
+/// from a torrent.
+#[derive(Clone)]
+pub enum ExtraValue {
+    Int(i64),
+    Str(String),
+    Bytes(Vec<u8>),
+    /// A list or dict kept as its bencoded bytes, e.g. `piece layers` or `url-list`.
+    Raw(Vec<u8>),
+}
+
+impl ExtraValue {
+    /// Integers and strings only, lists and dicts are kept as `Raw` by the caller.
+    pub fn from_bencode(value: &Bencode) -> Option<Self> {
+        match value {
+            Bencode::Int(i) => i64::try_from(*i).ok().map(ExtraValue::Int),
+            Bencode::UInt(i) => Some(ExtraValue::Int(*i)),
+            Bencode::Bytes(b) => Some(match std::str::from_utf8(b) {
+                Ok(s) => ExtraValue::Str(s.to_string()),
+                Err(_) => ExtraValue::Bytes(b.to_vec()),
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn encode<W: Write>(&self, out: &mut W) -> ioResult<()> {
+        match self {
+            ExtraValue::Int(i) => write_int(out, *i),
+            ExtraValue::Str(s) => write_string(out, s),
+            ExtraValue::Bytes(b) => write_bytes(out, b),
+            ExtraValue::Raw(b) => out.write_all(b),
+        }
+    }
+}
+
+impl Display for ExtraValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        match self {
+            ExtraValue::Int(i) => write!(f, "{i}"),
+            ExtraValue::Str(s) => write!(f, "{s}"),
+            ExtraValue::Bytes(b) => write!(f, "{} bytes, 0x{}", b.len(), hex::encode(b)),
+            ExtraValue::Raw(b) => write!(f, "[bencoded {} bytes]", b.len()),
+        }
+    }
+}
+
+/// Parses `key=value`, values that are integers are stored as bencode integers.
+pub fn parse_extra_key(s: &str) -> Result<(String, ExtraValue), String> {
+    let (key, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("invalid key '{s}', expected key=value"))?;
+    if key.is_empty() {
+        return Err(format!("invalid key '{s}', the key is empty"));
+    }
+    let value = match value.parse::<i64>() {
+        Ok(i) => ExtraValue::Int(i),
+        Err(_) => ExtraValue::Str(value.to_string()),
+    };
+    Ok((key.to_string(), value))
+}
+
+/// Writes the extra keys sorting before `next_key`, so they end up in bencode key order
+/// between the known ones. Pass `None` to write the rest.
+pub fn write_extra_before<'a, W: Write>(
+    out: &mut W,
+    extra: &mut Peekable<btree_map::Iter<'a, String, ExtraValue>>,
+    next_key: Option<&str>,
+) -> ioResult<()> {
+    while let Some((key, value)) =
+        extra.next_if(|(key, _)| next_key.is_none_or(|next| key.as_str() < next))
+    {
+        write_string(out, key)?;
+        value.encode(out)?;
+    }
+    Ok(())
+}
+
+// Encoders write straight into `out`, so large `pieces` blobs are never copied into
+// intermediate buffers; use a `BufWriter` for files.
+
+pub fn write_bytes<W: Write>(out: &mut W, bytes: &[u8]) -> ioResult<()> {
+    write!(out, "{}:", bytes.len())?;
+    out.write_all(bytes)
+}
+
+pub fn write_string<W: Write>(out: &mut W, s: &str) -> ioResult<()> {
+    write_bytes(out, s.as_bytes())
+}
+
+pub fn write_uint<W: Write>(out: &mut W, i: usize) -> ioResult<()> {
+    write!(out, "i{i}e")
+}
+
+pub fn write_int<W: Write>(out: &mut W, i: i64) -> ioResult<()> {
+    write!(out, "i{i}e")
+}
+
+pub fn write_string_list<W: Write>(out: &mut W, list: &[String]) -> ioResult<()> {
+    out.write_all(b"l")?;
     for item in list {
-        bcode.extend(bencode_string(item));
+        write_string(out, item)?;
+    }
+    out.write_all(b"e")
+}
+
+/// Writer that only counts bytes, for sizing an encoding without building it.
+#[derive(Default)]
+pub struct ByteCounter(pub usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> ioResult<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> ioResult<()> {
+        Ok(())
     }
-    bcode.push(b'e');
-    bcode
 }
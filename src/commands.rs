@@ -0,0 +1,2182 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::write;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use argh::FromArgs;
+use chrono::Local;
+use serde::Serialize;
+
+use crate::bencode::{self, Bencode, parse_bencode};
+use crate::crash;
+use crate::datetime;
+use crate::fingerprint::Fingerprint;
+use crate::fixture::{self, SizeDistribution};
+use crate::lint::{self, DEF_MAX_PATH_LENGTH, Finding, PathLimits, Policy, Severity, check_paths};
+use crate::magnet::{self, InfoHash, Magnet};
+use crate::output::{self, Format, OutputFormat, Table};
+use crate::overlap;
+use crate::piece_cache::PieceCache;
+use crate::report::{InfoReport, InfoReports};
+use crate::resume::ResumeData;
+use crate::state::{self, StateEntry};
+use crate::torrent::{Torrent, TorrentBuilder, check_piece_length};
+use crate::tr_file::TrFile;
+use crate::tr_info::{
+    LinkPolicy, MetaVersion, OfflinePolicy, SHA1_HASH_SIZE, SortLocale, TrConfig, TrInfo, WalkMode,
+};
+use crate::tracker_health::{self, HealthCache, TrackerHealth};
+use crate::utils::{
+    TrError, TrResult, check_write, fill_template, human_size, parse_duration,
+    parse_duration_or_zero, parse_piece_size, parse_size,
+};
+use crate::warnings::{WarningKind, warn};
+use crate::wizard;
+use crate::{
+    Config, DEF_PIECE_SIZE, MAX_PIECE_SIZE, MIN_PIECE_SIZE, NAME_VERSION, announce_tiers,
+    canonical_output_path, page, print_cache_reuse, print_info_hashes,
+};
+
+const MAX_DISPLAYED_PIECES: usize = 100;
+const DEF_TRACKER_TTL: Duration = Duration::from_secs(60 * 60);
+const MAX_TABLE_CELL: usize = 48;
+const TABLE_LABELS: [&str; 10] = [
+    "Torrent",
+    "Name",
+    "Size",
+    "Files",
+    "Piece size",
+    "Private",
+    "Source",
+    "Trackers",
+    "Info hash",
+    "Info hash v2",
+];
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+pub enum SubCommand {
+    Info(InfoArgs),
+    Lint(LintArgs),
+    CompareData(CompareDataArgs),
+    MigrateCheck(MigrateCheckArgs),
+    Overlap(OverlapArgs),
+    Fingerprint(FingerprintArgs),
+    CheckTrackers(CheckTrackersArgs),
+    Retrack(RetrackArgs),
+    Edit(EditArgs),
+    CrossSeed(CrossSeedArgs),
+    ResumeInfo(ResumeInfoArgs),
+    SizeCheck(SizeCheckArgs),
+    Update(UpdateArgs),
+    Split(SplitArgs),
+    Join(JoinArgs),
+    Magnet(MagnetArgs),
+    GenFixture(GenFixtureArgs),
+    Wizard(WizardArgs),
+    State(StateArgs),
+    Version(VersionArgs),
+    #[cfg(feature = "self-update")]
+    SelfUpdate(SelfUpdateArgs),
+}
+
+/// print information of one or more torrents, or the tree of other bencoded files
+#[derive(FromArgs)]
+#[argh(subcommand, name = "info")]
+pub struct InfoArgs {
+    /// torrent files
+    #[argh(positional)]
+    torrents: Vec<String>,
+
+    /// print a side-by-side comparison table, rows that differ are marked with *
+    #[argh(switch)]
+    table: bool,
+
+    /// print torrent file tree
+    #[argh(switch, short = 't')]
+    print_tree: bool,
+}
+
+/// check torrents for problems that break clients
+#[derive(FromArgs)]
+#[argh(subcommand, name = "lint")]
+pub struct LintArgs {
+    /// torrent files
+    #[argh(positional)]
+    torrents: Vec<String>,
+
+    /// maximum path length including the torrent name [default: 240]
+    #[argh(option, default = "DEF_MAX_PATH_LENGTH")]
+    max_path_length: usize,
+
+    /// maximum directory depth including the torrent name
+    #[argh(option)]
+    max_path_depth: Option<usize>,
+
+    /// output format: human, json, csv or markdown [default: --format, else human]
+    #[argh(option)]
+    format: Option<Format>,
+
+    /// treat findings of this rule as errors, multiple allowed
+    #[argh(option)]
+    deny: Vec<String>,
+
+    /// ignore findings of this rule, multiple allowed
+    #[argh(option)]
+    allow: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct LintReport<'a> {
+    torrent: &'a str,
+    findings: &'a [Finding],
+}
+
+#[derive(Serialize)]
+#[serde(transparent)]
+struct LintReports<'a>(Vec<LintReport<'a>>);
+
+impl OutputFormat for LintReports<'_> {
+    fn write_human(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for report in &self.0 {
+            for finding in report.findings {
+                let level = match finding.severity {
+                    Severity::Error => "E",
+                    Severity::Warning => "W",
+                };
+                writeln!(out, "{level}: {}: {finding}", report.torrent)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn table(&self) -> Table {
+        let mut table = Table::new(vec!["torrent", "rule", "severity", "message"]);
+        for report in &self.0 {
+            for finding in report.findings {
+                let severity = match finding.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                };
+                table.rows.push(vec![
+                    report.torrent.to_string(),
+                    finding.rule.to_string(),
+                    severity.to_string(),
+                    finding.message.clone(),
+                ]);
+            }
+        }
+        table
+    }
+}
+
+/// hash two copies of the data in one pass and report where they diverge
+#[derive(FromArgs)]
+#[argh(subcommand, name = "compare-data")]
+pub struct CompareDataArgs {
+    /// torrent file
+    #[argh(positional)]
+    torrent: String,
+
+    /// first copy of the data (A)
+    #[argh(positional)]
+    copy_a: String,
+
+    /// second copy of the data (B)
+    #[argh(positional)]
+    copy_b: String,
+}
+
+/// check a migrated copy against the torrent before deleting the source
+#[derive(FromArgs)]
+#[argh(subcommand, name = "migrate-check")]
+pub struct MigrateCheckArgs {
+    /// torrent file
+    #[argh(positional)]
+    torrent: String,
+
+    /// source copy, only read where the new copy fails
+    #[argh(positional)]
+    old_root: String,
+
+    /// migrated copy
+    #[argh(positional)]
+    new_root: String,
+}
+
+/// estimate how much of torrent A the data of torrent B could provide
+#[derive(FromArgs)]
+#[argh(subcommand, name = "overlap")]
+pub struct OverlapArgs {
+    /// torrent to download (A)
+    #[argh(positional)]
+    torrent_a: String,
+
+    /// torrent whose data you have (B)
+    #[argh(positional)]
+    torrent_b: String,
+}
+
+/// print a fingerprint of the content for matching the same release across trackers
+#[derive(FromArgs)]
+#[argh(subcommand, name = "fingerprint")]
+pub struct FingerprintArgs {
+    /// torrent files
+    #[argh(positional)]
+    torrents: Vec<String>,
+
+    /// include the hashes of the first n pieces, matching only the same piece size [default: 0]
+    #[argh(option, default = "0")]
+    head_pieces: usize,
+
+    /// output format: human, json, csv or markdown [default: --format, else human]
+    #[argh(option)]
+    format: Option<Format>,
+}
+
+#[derive(Serialize)]
+struct FingerprintReport<'a> {
+    torrent: &'a str,
+    #[serde(flatten)]
+    fingerprint: &'a Fingerprint,
+}
+
+#[derive(Serialize)]
+#[serde(transparent)]
+struct FingerprintReports<'a>(Vec<FingerprintReport<'a>>);
+
+impl OutputFormat for FingerprintReports<'_> {
+    fn write_human(&self, out: &mut dyn Write) -> std::io::Result<()> {
+        for report in &self.0 {
+            writeln!(
+                out,
+                "{}  {}",
+                report.fingerprint.fingerprint, report.torrent
+            )?;
+        }
+        Ok(())
+    }
+
+    fn table(&self) -> Table {
+        let mut table = Table::new(vec!["torrent", "fingerprint", "total_size", "piece_length"]);
+        for report in &self.0 {
+            table.rows.push(vec![
+                report.torrent.to_string(),
+                report.fingerprint.fingerprint.clone(),
+                report.fingerprint.total_size.to_string(),
+                report.fingerprint.piece_length.to_string(),
+            ]);
+        }
+        table
+    }
+}
+
+/// announce to the trackers of torrents and sum up their health across all of them
+#[derive(FromArgs)]
+#[argh(subcommand, name = "check-trackers")]
+pub struct CheckTrackersArgs {
+    /// torrent files
+    #[argh(positional)]
+    torrents: Vec<String>,
+
+    /// file keeping tracker results between runs [default: tracker-cache.json in the state directory]
+    #[argh(option)]
+    cache: Option<String>,
+
+    /// how long a cached result is reused, e.g. 30m or 6h, 0 to announce every run [default: 1h]
+    #[argh(option, from_str_fn(parse_duration_or_zero))]
+    ttl: Option<Duration>,
+
+    /// announce to every tracker again instead of using cached results
+    #[argh(switch)]
+    refresh: bool,
+}
+
+/// replace tracker URLs of existing torrents in place, the info dict is untouched
+#[derive(FromArgs)]
+#[argh(subcommand, name = "retrack")]
+pub struct RetrackArgs {
+    /// torrent files to rewrite
+    #[argh(positional)]
+    torrents: Vec<String>,
+
+    /// new announce URL, multiple allowed; {infohash}, {infohash_v2} and {name} are filled in
+    #[argh(option, short = 'a')]
+    announce: Vec<String>,
+
+    /// only replace URLs starting with this prefix and keep the others
+    #[argh(option)]
+    replace: Option<String>,
+
+    /// print the new trackers without writing
+    #[argh(switch, short = 'n')]
+    dry_run: bool,
+}
+
+/// change the metadata of a torrent without rehashing
+#[derive(FromArgs)]
+#[argh(subcommand, name = "edit")]
+pub struct EditArgs {
+    /// torrent file
+    #[argh(positional)]
+    torrent: String,
+
+    /// replace the trackers, one tier per URL, multiple allowed (\"\" to clear)
+    #[argh(option, short = 'a')]
+    announce: Vec<String>,
+
+    /// reorder, delete and add trackers and tiers in an interactive session
+    #[argh(switch)]
+    trackers_interactive: bool,
+
+    /// new comment (\"\" to clear)
+    #[argh(option, short = 'c')]
+    comment: Option<String>,
+
+    /// new creation date: unix timestamp, RFC 3339 or YYYY-MM-DD[ HH:MM[:SS]]
+    #[argh(option, from_str_fn(datetime::parse_date))]
+    date: Option<i64>,
+
+    /// remove the creation date
+    #[argh(switch, short = 'd')]
+    no_date: bool,
+
+    /// set the private flag, changes the info-hash
+    #[argh(switch)]
+    private: bool,
+
+    /// clear the private flag, changes the info-hash
+    #[argh(switch)]
+    public: bool,
+
+    /// new source (\"\" to clear), changes the info-hash
+    #[argh(option, short = 's')]
+    source: Option<String>,
+
+    /// drop and redact trackers by the tracker_policy of the config
+    #[argh(switch)]
+    apply_policy: bool,
+
+    /// write to this file instead of the torrent itself
+    #[argh(option, short = 'o')]
+    output: Option<String>,
+
+    /// overwrite the -o output if it exists
+    #[argh(switch, short = 'f')]
+    force: bool,
+}
+
+/// copy a torrent for another tracker with new trackers and source, without rehashing
+#[derive(FromArgs)]
+#[argh(subcommand, name = "crossseed")]
+pub struct CrossSeedArgs {
+    /// torrent file
+    #[argh(positional)]
+    torrent: String,
+
+    /// announce URL of the other tracker, one tier per URL, multiple allowed [default: trackers of the config]
+    #[argh(option, short = 'a')]
+    announce: Vec<String>,
+
+    /// source tag of the other tracker, changes the info-hash [default: source of the config]
+    #[argh(option, short = 's')]
+    source: Option<String>,
+
+    /// output torrent
+    #[argh(option, short = 'o')]
+    output: String,
+
+    /// overwrite the output if it exists
+    #[argh(switch, short = 'f')]
+    force: bool,
+}
+
+/// show completion, save path and trackers from a client .fastresume file
+#[derive(FromArgs)]
+#[argh(subcommand, name = "resume-info")]
+pub struct ResumeInfoArgs {
+    /// resume file
+    #[argh(positional)]
+    resume: String,
+}
+
+/// check that every file of a torrent exists with the right size, without hashing
+#[derive(FromArgs)]
+#[argh(subcommand, name = "sizecheck")]
+pub struct SizeCheckArgs {
+    /// torrent file
+    #[argh(positional)]
+    torrent: String,
+
+    /// data to check
+    #[argh(positional)]
+    target: String,
+}
+
+/// rebuild a torrent for data that changed since, reusing the hashes of unchanged pieces
+#[derive(FromArgs)]
+#[argh(subcommand, name = "update")]
+pub struct UpdateArgs {
+    /// torrent created from an earlier state of the data
+    #[argh(positional)]
+    torrent: String,
+
+    /// current data
+    #[argh(positional)]
+    target: String,
+
+    /// output torrent [default: <target>.torrent]
+    #[argh(option, short = 'o')]
+    output: Option<String>,
+
+    /// overwrite the output if it exists
+    #[argh(switch, short = 'f')]
+    force: bool,
+}
+
+/// make one torrent per top-level directory of a torrent, reusing its piece hashes
+#[derive(FromArgs)]
+#[argh(subcommand, name = "split")]
+pub struct SplitArgs {
+    /// torrent to split
+    #[argh(positional)]
+    torrent: String,
+
+    /// one torrent per top-level subdirectory
+    #[argh(switch)]
+    by_dir: bool,
+
+    /// verified data of the torrent
+    #[argh(option)]
+    data: String,
+
+    /// output directory [default: next to the torrent]
+    #[argh(option, short = 'o')]
+    output: Option<String>,
+
+    /// overwrite outputs that exist
+    #[argh(switch, short = 'f')]
+    force: bool,
+}
+
+/// combine several directories into one torrent, each under its own name
+#[derive(FromArgs)]
+#[argh(subcommand, name = "join")]
+pub struct JoinArgs {
+    /// output torrent
+    #[argh(positional)]
+    output: String,
+
+    /// source directories or files
+    #[argh(positional)]
+    sources: Vec<String>,
+
+    /// name of the combined torrent
+    #[argh(option)]
+    name: String,
+
+    /// mapping file of origin path to torrent path [default: <output>.map]
+    #[argh(option)]
+    map: Option<String>,
+
+    /// piece size, 16K to 128M or an exponent 14..=27 (1 << n), overrides config
+    #[argh(option, short = 'l', from_str_fn(parse_piece_size))]
+    piece_size: Option<u8>,
+
+    /// private torrent, overrides config
+    #[argh(switch, short = 'p')]
+    private: bool,
+
+    /// overwrite the output and mapping file if they exist
+    #[argh(switch, short = 'f')]
+    force: bool,
+}
+
+/// fetch the info dict of a magnet link from peers (BEP 9) and write it as a .torrent
+#[derive(FromArgs)]
+#[argh(subcommand, name = "magnet")]
+pub struct MagnetArgs {
+    /// magnet link, quoted so the shell leaves the & alone
+    #[argh(positional)]
+    uri: Magnet,
+
+    /// output torrent [default: <name>.torrent]
+    #[argh(option, short = 'o')]
+    output: Option<String>,
+
+    /// seconds to look for peers and fetch the metadata [default: 120]
+    #[argh(option, default = "120")]
+    timeout: u64,
+
+    /// overwrite the output if it exists
+    #[argh(switch, short = 'f')]
+    force: bool,
+}
+
+/// generate random files and their torrent, the same for a seed, for tests and benchmarks
+#[derive(FromArgs)]
+#[argh(subcommand, name = "gen-fixture")]
+pub struct GenFixtureArgs {
+    /// directory to create the content and the torrent in, new or empty
+    #[argh(positional)]
+    output: String,
+
+    /// number of files [default: 100]
+    #[argh(option, default = "100")]
+    files: usize,
+
+    /// file size distribution: zipf, uniform or equal [default: zipf]
+    #[argh(option, default = "SizeDistribution::Zipf")]
+    sizes: SizeDistribution,
+
+    /// largest file size, e.g. 4M [default: 1M]
+    #[argh(option, from_str_fn(parse_size))]
+    max_size: Option<usize>,
+
+    /// piece size, e.g. 16KiB [default: 16KiB]
+    #[argh(option, from_str_fn(parse_size))]
+    piece_size: Option<usize>,
+
+    /// random seed [default: 0]
+    #[argh(option, default = "0")]
+    seed: u64,
+}
+
+/// create a torrent by answering prompts for target, piece size, trackers and output
+#[derive(FromArgs)]
+#[argh(subcommand, name = "wizard")]
+pub struct WizardArgs {}
+
+/// list or remove verify states and caches kept in the state directory
+#[derive(FromArgs)]
+#[argh(subcommand, name = "state")]
+pub struct StateArgs {
+    #[argh(subcommand)]
+    command: StateCommand,
+}
+
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum StateCommand {
+    Ls(StateLsArgs),
+    Clean(StateCleanArgs),
+}
+
+/// list the files of the state directory with their kind, size and age
+#[derive(FromArgs)]
+#[argh(subcommand, name = "ls")]
+struct StateLsArgs {}
+
+/// remove verify states and caches from the state directory
+#[derive(FromArgs)]
+#[argh(subcommand, name = "clean")]
+struct StateCleanArgs {
+    /// only remove files unchanged for this long, e.g. 30d or 12h
+    #[argh(option, from_str_fn(parse_duration))]
+    older_than: Option<Duration>,
+}
+
+/// print the version, with --verbose also what this build supports
+#[derive(FromArgs)]
+#[argh(subcommand, name = "version")]
+pub struct VersionArgs {
+    /// print build features, hash backends, supported BEPs and modes, and the config file
+    #[argh(switch)]
+    pub verbose: bool,
+}
+
+/// replace this executable with the latest release after checking its SHA-256
+#[cfg(feature = "self-update")]
+#[derive(FromArgs)]
+#[argh(subcommand, name = "self-update")]
+pub struct SelfUpdateArgs {
+    /// only report whether a newer release exists
+    #[argh(switch)]
+    check: bool,
+
+    /// reinstall the latest release even if it is not newer
+    #[argh(switch, short = 'f')]
+    force: bool,
+}
+
+impl SubCommand {
+    /// Name of the command if it writes files, refused with `--assert-readonly`.
+    pub fn writing_name(&self) -> Option<&'static str> {
+        match self {
+            SubCommand::CheckTrackers(_) => Some("check-trackers"),
+            SubCommand::Retrack(_) => Some("retrack"),
+            SubCommand::Edit(_) => Some("edit"),
+            SubCommand::CrossSeed(_) => Some("crossseed"),
+            SubCommand::Update(_) => Some("update"),
+            SubCommand::Split(_) => Some("split"),
+            SubCommand::Join(_) => Some("join"),
+            SubCommand::Magnet(_) => Some("magnet"),
+            SubCommand::GenFixture(_) => Some("gen-fixture"),
+            SubCommand::Wizard(_) => Some("wizard"),
+            SubCommand::State(StateArgs {
+                command: StateCommand::Clean(_),
+            }) => Some("state clean"),
+            #[cfg(feature = "self-update")]
+            SubCommand::SelfUpdate(_) => Some("self-update"),
+            _ => None,
+        }
+    }
+}
+
+pub fn run(command: SubCommand, config: &Config, quiet: bool) -> TrResult<()> {
+    match command {
+        SubCommand::Info(args) => info(args, config),
+        SubCommand::Lint(args) => lint(args, config, quiet),
+        SubCommand::CompareData(args) => compare_data(args, config, quiet),
+        SubCommand::MigrateCheck(args) => migrate_check(args, config, quiet),
+        SubCommand::Overlap(args) => overlap(args),
+        SubCommand::Fingerprint(args) => fingerprint(args),
+        SubCommand::CheckTrackers(args) => check_trackers(args, quiet),
+        SubCommand::Retrack(args) => retrack(args, quiet),
+        SubCommand::Edit(args) => edit(args, config, quiet),
+        SubCommand::CrossSeed(args) => cross_seed(args, config, quiet),
+        SubCommand::ResumeInfo(args) => {
+            println!("{}", ResumeData::read_resume(&args.resume)?);
+            Ok(())
+        }
+        SubCommand::SizeCheck(args) => size_check(args, quiet),
+        SubCommand::Update(args) => update(args, config, quiet),
+        SubCommand::Split(args) => split(args, config, quiet),
+        SubCommand::Join(args) => join(args, config, quiet),
+        SubCommand::Magnet(args) => magnet(args, quiet),
+        SubCommand::GenFixture(args) => gen_fixture(args, config, quiet),
+        // the wizard only fills in the create mode arguments, see main
+        SubCommand::Wizard(_) => Ok(()),
+        SubCommand::State(args) => state(args, quiet),
+        // printed in main, before the config is loaded
+        SubCommand::Version(_) => Ok(()),
+        #[cfg(feature = "self-update")]
+        SubCommand::SelfUpdate(args) => crate::self_update::self_update(args.check, args.force),
+    }
+}
+
+/// Prints `key: value` lines for bug reports and scripts; keys and the value formats
+/// are kept stable.
+pub fn version(args: &VersionArgs, config_path: &str) {
+    println!("{NAME_VERSION}");
+    if !args.verbose {
+        return;
+    }
+    let features: Vec<&str> = [
+        ("numa", cfg!(feature = "numa")),
+        ("self-update", cfg!(feature = "self-update")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+    let supported = |yes: bool| if yes { "supported" } else { "not supported" };
+    println!(
+        "Target: {}-{}",
+        std::env::consts::ARCH,
+        std::env::consts::OS
+    );
+    println!(
+        "Features: {}",
+        if features.is_empty() {
+            String::from("none")
+        } else {
+            features.join(" ")
+        }
+    );
+    println!(
+        "SHA-1: sha1 (RustCrypto), CPU extensions {}",
+        sha_extensions()
+    );
+    println!(
+        "SHA-256: sha2 (RustCrypto), CPU extensions {}",
+        sha_extensions()
+    );
+    println!("BEPs: 3 5 9 10 12 15 17 19 27 38 47 52");
+    println!("Meta versions: 1 2 hybrid");
+    let commands: Vec<&str> = <SubCommand as argh::SubCommands>::COMMANDS
+        .iter()
+        .map(|command| command.name)
+        .collect();
+    println!("Commands: create verify {}", commands.join(" "));
+    println!("Auto yield: {}", supported(cfg!(target_os = "linux")));
+    println!("Thread pinning: {}", supported(crate::numa::SUPPORTED));
+    let config_state = match std::fs::read_to_string(config_path) {
+        Ok(content) => match toml::from_str::<Config>(&content) {
+            Ok(_) => String::from("loaded"),
+            Err(e) => format!("invalid, defaults used: {}", e.message()),
+        },
+        Err(_) => String::from("not found, defaults used"),
+    };
+    println!("Config: {config_path} ({config_state})");
+}
+
+/// Whether the CPU has the SHA instructions the RustCrypto hashers switch to at runtime.
+fn sha_extensions() -> &'static str {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    let detected = std::arch::is_x86_feature_detected!("sha");
+    #[cfg(target_arch = "aarch64")]
+    let detected = std::arch::is_aarch64_feature_detected!("sha2");
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64", target_arch = "aarch64")))]
+    let detected = false;
+    if detected { "in use" } else { "not available" }
+}
+
+fn read_info_torrent(torrent_path: &str) -> TrResult<Torrent> {
+    let torrent = Torrent::read_torrent(torrent_path.to_string())?;
+    if torrent.get_info().is_none() {
+        return Err(TrError::MissingField(String::from("info")));
+    }
+    Ok(torrent)
+}
+
+/// Writes the info of a torrent, or with `print_tree` its file tree. Bencoded files that
+/// are no torrent, such as DHT state or client settings, are written as a bencode tree
+/// instead of failing for their missing info dict.
+pub fn write_info(
+    text: &mut Vec<u8>,
+    path: &str,
+    print_tree: bool,
+    sort_locale: SortLocale,
+) -> TrResult<()> {
+    crash::set_context(format!("reading '{path}'"));
+    let data = std::fs::read(path)?;
+    let torrent = match Torrent::from_bytes(&data) {
+        Ok(torrent) => torrent,
+        Err(e) => {
+            return match parse_bencode(&data, &mut 0) {
+                Ok(Bencode::Dict(map)) if map.contains_key("info") => Err(e),
+                Ok(value) => {
+                    writeln!(
+                        text,
+                        "Bencoded data, not a torrent (no info dict), {} bytes:",
+                        data.len()
+                    )?;
+                    bencode::write_tree(text, &value)?;
+                    Ok(())
+                }
+                Err(_) => Err(e),
+            };
+        }
+    };
+    if print_tree {
+        torrent.write_file_tree(text, sort_locale)?;
+    } else {
+        writeln!(text, "{torrent}")?;
+    }
+    Ok(())
+}
+
+/// Prints the info of torrents in the `--format` given, which is not human.
+pub fn print_info_reports(torrent_paths: &[String]) -> TrResult<()> {
+    let mut reports = Vec::with_capacity(torrent_paths.len());
+    for torrent_path in torrent_paths {
+        let torrent = match read_info_torrent(torrent_path) {
+            Ok(torrent) => torrent,
+            Err(e) => {
+                let data = std::fs::read(torrent_path)?;
+                return Err(match parse_bencode(&data, &mut 0) {
+                    Ok(Bencode::Dict(map)) if map.contains_key("info") => e,
+                    Ok(_) => TrError::InvalidTorrent(format!(
+                        "'{torrent_path}' is bencoded data without an info dict, only --format human shows it"
+                    )),
+                    Err(_) => e,
+                });
+            }
+        };
+        reports.push(InfoReport::new(torrent_path, &torrent));
+    }
+    InfoReports(reports).print()
+}
+
+fn info(args: InfoArgs, config: &Config) -> TrResult<()> {
+    if args.torrents.is_empty() {
+        return Err(TrError::MissingField(String::from("torrent file")));
+    }
+    if output::format() != Format::Human {
+        return print_info_reports(&args.torrents);
+    }
+    if !args.table {
+        let mut text = Vec::new();
+        for torrent_path in &args.torrents {
+            write_info(
+                &mut text,
+                torrent_path,
+                args.print_tree,
+                config.sort_locale.unwrap_or(SortLocale::Natural),
+            )?;
+        }
+        page(&text);
+        return Ok(());
+    }
+
+    let torrents = args
+        .torrents
+        .iter()
+        .map(|torrent_path| read_info_torrent(torrent_path))
+        .collect::<TrResult<Vec<Torrent>>>()?;
+
+    let columns: Vec<[String; TABLE_LABELS.len()]> = args
+        .torrents
+        .iter()
+        .zip(&torrents)
+        .map(|(torrent_path, torrent)| {
+            let info = torrent.get_info().unwrap();
+            let file_name = Path::new(torrent_path)
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or(torrent_path);
+            let trackers: Vec<String> = torrent.trackers().into_iter().flatten().collect();
+            let mut column = [
+                file_name.to_string(),
+                info.name.clone().unwrap_or_default(),
+                human_size(torrent.total_size()),
+                torrent.files().len().to_string(),
+                human_size(info.piece_length),
+                info.private.to_string(),
+                info.source.clone().unwrap_or_default(),
+                trackers.join(" "),
+                torrent.info_hash().unwrap_or_default(),
+                if torrent.is_hybrid() {
+                    torrent.info_hash_v2().unwrap_or_default()
+                } else {
+                    String::new()
+                },
+            ];
+            // hashes are compared and copied whole, up to 64 hex digits for v2
+            for (cell, label) in column.iter_mut().zip(TABLE_LABELS) {
+                if !label.starts_with("Info hash") {
+                    *cell = truncate_cell(std::mem::take(cell));
+                }
+            }
+            column
+        })
+        .collect();
+    let rows: Vec<(&str, Vec<&String>)> = TABLE_LABELS
+        .iter()
+        .enumerate()
+        .map(|(i, label)| (*label, columns.iter().map(|column| &column[i]).collect()))
+        .collect();
+
+    let label_width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+    let col_widths: Vec<usize> = (0..columns.len())
+        .map(|i| {
+            rows.iter()
+                .map(|(_, cells)| cells[i].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    for (label, cells) in &rows {
+        let differs = *label != TABLE_LABELS[0] && cells.iter().any(|cell| *cell != cells[0]);
+        let mut line = format!("{} {label:label_width$}", if differs { '*' } else { ' ' });
+        for (cell, width) in cells.iter().zip(&col_widths) {
+            line.push_str(&format!(" | {cell:width$}"));
+        }
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
+
+fn truncate_cell(cell: String) -> String {
+    if cell.chars().count() <= MAX_TABLE_CELL {
+        cell
+    } else {
+        let mut cell: String = cell.chars().take(MAX_TABLE_CELL - 3).collect();
+        cell.push_str("...");
+        cell
+    }
+}
+
+fn lint(args: LintArgs, config: &Config, quiet: bool) -> TrResult<()> {
+    let limits = PathLimits {
+        max_length: Some(args.max_path_length),
+        max_depth: args.max_path_depth,
+    };
+    let policy = Policy {
+        deny: args.deny,
+        allow: args.allow,
+    };
+    policy.validate().map_err(TrError::ParseError)?;
+
+    let mut report = Vec::new();
+    for torrent_path in &args.torrents {
+        let torrent = read_info_torrent(torrent_path)?;
+        let info = torrent.get_info().unwrap();
+        let mut findings = check_paths(info, &limits);
+        findings.extend(lint::check_torrent_size(&torrent));
+        findings.extend(lint::check_client_compat(&torrent));
+        findings.extend(lint::check_metadata(info));
+        findings.extend(lint::check_trackers(&torrent, &config.tracker_policy));
+        report.push((torrent_path, policy.apply(findings)));
+    }
+
+    let format = args.format.unwrap_or_else(output::format);
+    LintReports(
+        report
+            .iter()
+            .map(|(torrent, findings)| LintReport { torrent, findings })
+            .collect(),
+    )
+    .write_as(format, &mut std::io::stdout().lock())?;
+    if format == Format::Human && !quiet {
+        for (torrent_path, findings) in &report {
+            if findings.is_empty() {
+                eprintln!("I: {torrent_path}: no issues found.");
+            }
+        }
+    }
+
+    let errors = report
+        .iter()
+        .flat_map(|(_, findings)| findings)
+        .filter(|finding| finding.severity == Severity::Error)
+        .count();
+    if errors > 0 {
+        return Err(TrError::InvalidTorrent(format!(
+            "{errors} lint findings are errors"
+        )));
+    }
+    Ok(())
+}
+
+fn compare_data(args: CompareDataArgs, config: &Config, quiet: bool) -> TrResult<()> {
+    for copy in [&args.copy_a, &args.copy_b] {
+        if !Path::new(copy).exists() {
+            return Err(TrError::InvalidPath(format!("{copy} does not exist")));
+        }
+    }
+    let torrent = read_info_torrent(&args.torrent)?;
+    let tr_info = torrent.get_info().unwrap();
+    if !quiet {
+        eprintln!("I: Compare mode.");
+        eprintln!("Torrent: {}", args.torrent);
+        eprintln!("Copy A:  {}", args.copy_a);
+        eprintln!("Copy B:  {}", args.copy_b);
+    }
+
+    let result = tr_info.compare_data(
+        &args.copy_a,
+        &args.copy_b,
+        config.n_jobs,
+        config.read_buffer,
+        quiet,
+        true,
+    )?;
+
+    let total = result.both_ok
+        + result.a_only.len()
+        + result.b_only.len()
+        + result.neither_same.len()
+        + result.neither_diverge.len();
+    println!("Comparison Result:");
+    println!("Pieces: {total:8} total");
+    println!("  Both copies OK:      {:8}", result.both_ok);
+    println!("  Only copy A OK:      {:8}", result.a_only.len());
+    println!("  Only copy B OK:      {:8}", result.b_only.len());
+    println!("  Both bad, identical: {:8}", result.neither_same.len());
+    println!("  Both bad, diverging: {:8}", result.neither_diverge.len());
+
+    let mut diverging: Vec<(usize, &str)> = result
+        .a_only
+        .iter()
+        .map(|&i| (i, "A matches the torrent"))
+        .chain(result.b_only.iter().map(|&i| (i, "B matches the torrent")))
+        .chain(
+            result
+                .neither_diverge
+                .iter()
+                .map(|&i| (i, "neither matches the torrent")),
+        )
+        .collect();
+    diverging.sort();
+    if !diverging.is_empty() {
+        println!("\nDiverging pieces:");
+        for (i, note) in diverging.iter().take(MAX_DISPLAYED_PIECES) {
+            println!("- piece {i}: {note}");
+        }
+        if diverging.len() > MAX_DISPLAYED_PIECES {
+            println!("  Truncated at {MAX_DISPLAYED_PIECES} pieces...");
+        }
+    }
+
+    let a_complete = result.both_ok + result.a_only.len() == total;
+    let b_complete = result.both_ok + result.b_only.len() == total;
+    println!();
+    match (a_complete, b_complete) {
+        (true, true) => println!("Both copies match the torrent."),
+        (true, false) => println!("Copy A matches the torrent, keep A."),
+        (false, true) => println!("Copy B matches the torrent, keep B."),
+        (false, false) => {
+            let a_ok = result.both_ok + result.a_only.len();
+            let b_ok = result.both_ok + result.b_only.len();
+            println!(
+                "Neither copy is complete ({a_ok} vs {b_ok} good pieces), merging A and B would recover {} pieces.",
+                result.both_ok + result.a_only.len() + result.b_only.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+fn migrate_check(args: MigrateCheckArgs, config: &Config, quiet: bool) -> TrResult<()> {
+    for root in [&args.old_root, &args.new_root] {
+        if !Path::new(root).exists() {
+            return Err(TrError::InvalidPath(format!("{root} does not exist")));
+        }
+    }
+    let torrent = read_info_torrent(&args.torrent)?;
+    let tr_info = torrent.get_info().unwrap();
+    if !quiet {
+        eprintln!("I: Migration check mode.");
+        eprintln!("Torrent: {}", args.torrent);
+        eprintln!("Old:     {}", args.old_root);
+        eprintln!("New:     {}", args.new_root);
+    }
+
+    let result = tr_info.migrate_check(
+        &args.old_root,
+        &args.new_root,
+        config.n_jobs,
+        config.read_buffer,
+        quiet,
+        true,
+    )?;
+
+    let total = result.new_ok
+        + result.old_only.len()
+        + result.both_bad_same.len()
+        + result.both_bad_diverge.len();
+    println!("Migration Check Result:");
+    println!("Pieces: {total:8} total");
+    println!("  New copy OK:                 {:8}", result.new_ok);
+    println!("  Damaged by the migration:    {:8}", result.old_only.len());
+    println!(
+        "  Bad in both, identical:      {:8}",
+        result.both_bad_same.len()
+    );
+    println!(
+        "  Bad in both, diverging:      {:8}",
+        result.both_bad_diverge.len()
+    );
+
+    let mut failing: Vec<(usize, &str)> = result
+        .old_only
+        .iter()
+        .map(|&i| (i, "old copy is good, copy it again"))
+        .chain(
+            result
+                .both_bad_same
+                .iter()
+                .map(|&i| (i, "already bad in the old copy, copied as it was")),
+        )
+        .chain(
+            result
+                .both_bad_diverge
+                .iter()
+                .map(|&i| (i, "bad in both copies, and they differ")),
+        )
+        .collect();
+    failing.sort();
+    if !failing.is_empty() {
+        println!("\nFailing pieces of the new copy:");
+        for (i, note) in failing.iter().take(MAX_DISPLAYED_PIECES) {
+            println!("- piece {i}: {note}");
+        }
+        if failing.len() > MAX_DISPLAYED_PIECES {
+            println!("  Truncated at {MAX_DISPLAYED_PIECES} pieces...");
+        }
+    }
+
+    println!();
+    let changed = result.old_only.len() + result.both_bad_diverge.len();
+    if changed > 0 {
+        return Err(TrError::InvalidPath(format!(
+            "NO-GO: the migration changed {changed} pieces, keep the old copy"
+        )));
+    }
+    if result.both_bad_same.is_empty() {
+        println!("GO: the new copy matches the torrent, the old copy can be deleted.");
+    } else {
+        println!(
+            "GO: the new copy is identical to the old one, {} pieces were already bad before the migration.",
+            result.both_bad_same.len()
+        );
+    }
+    Ok(())
+}
+
+fn overlap(args: OverlapArgs) -> TrResult<()> {
+    let (a, b) = (
+        read_info_torrent(&args.torrent_a)?,
+        read_info_torrent(&args.torrent_b)?,
+    );
+    let (a_info, b_info) = (a.get_info().unwrap(), b.get_info().unwrap());
+    if a_info.pieces.is_empty() || b_info.pieces.is_empty() {
+        return Err(TrError::InvalidTorrent(String::from(
+            "both torrents need v1 piece hashes",
+        )));
+    }
+    let result = overlap::overlap(a_info, b_info)?;
+    let (a_size, a_pieces) = (a.total_size(), a.piece_count());
+    let percent = |part: usize| part as f64 * 100.0 / a_size.max(1) as f64;
+
+    println!("Overlap Result:");
+    println!("A: {} [{} pieces]", args.torrent_a, a_pieces);
+    println!("B: {} [{} pieces]", args.torrent_b, b.piece_count());
+    println!(
+        "Files of A found in B:     {:8} of {:8} [{}, {:.1}%]",
+        result.matched_files,
+        a.files().len(),
+        human_size(result.matched_size),
+        percent(result.matched_size)
+    );
+    println!(
+        "Pieces of A inside them:   {:8} of {:8} [{}, {:.1}%]",
+        result.covered_pieces,
+        a_pieces,
+        human_size(result.covered_size),
+        percent(result.covered_size)
+    );
+    if result.same_piece_length {
+        println!("  Hash-identical with B:   {:8}", result.identical_pieces());
+    } else {
+        println!("  Piece sizes differ, no piece hashes can be compared.");
+    }
+    if !result.identical.is_empty() {
+        println!("\nIdentical piece ranges:");
+        for (range, b_first) in result.identical.iter().take(MAX_DISPLAYED_PIECES) {
+            println!(
+                "- A {}-{} = B {}-{}",
+                range.start,
+                range.end - 1,
+                b_first,
+                b_first + range.len() - 1
+            );
+        }
+        if result.identical.len() > MAX_DISPLAYED_PIECES {
+            println!("  Truncated at {MAX_DISPLAYED_PIECES} ranges...");
+        }
+    }
+    println!(
+        "\nWith B's data, a client could reuse about {} of A ({:.1}%) and download {} pieces [{}].",
+        human_size(result.covered_size),
+        percent(result.covered_size),
+        a_pieces - result.covered_pieces,
+        human_size(a_size - result.covered_size)
+    );
+    Ok(())
+}
+
+fn fingerprint(args: FingerprintArgs) -> TrResult<()> {
+    if args.torrents.is_empty() {
+        return Err(TrError::MissingField(String::from("torrent file")));
+    }
+    let mut fingerprints = Vec::with_capacity(args.torrents.len());
+    for torrent_path in &args.torrents {
+        let torrent = read_info_torrent(torrent_path)?;
+        fingerprints.push(Fingerprint::new(
+            torrent.get_info().unwrap(),
+            args.head_pieces,
+        )?);
+    }
+    FingerprintReports(
+        args.torrents
+            .iter()
+            .zip(&fingerprints)
+            .map(|(torrent, fingerprint)| FingerprintReport {
+                torrent,
+                fingerprint,
+            })
+            .collect(),
+    )
+    .write_as(
+        args.format.unwrap_or_else(output::format),
+        &mut std::io::stdout().lock(),
+    )
+}
+
+/// Announces once to each tracker of the torrents that has no result younger than the TTL
+/// in the cache, then prints every tracker with the number of torrents using it and the
+/// torrents left without a working tracker.
+fn check_trackers(args: CheckTrackersArgs, quiet: bool) -> TrResult<()> {
+    if args.torrents.is_empty() {
+        return Err(TrError::MissingField(String::from("torrent file")));
+    }
+    let ttl = args.ttl.unwrap_or(DEF_TRACKER_TTL);
+    let cache_path = match args.cache {
+        Some(path) => path,
+        None => state::state_file(state::TRACKER_CACHE)?
+            .to_string_lossy()
+            .to_string(),
+    };
+    let mut cache = HealthCache::load(&cache_path)?;
+
+    // announce URL -> info-hash to announce with, and the torrents listing it
+    let mut users: BTreeMap<String, (InfoHash, Vec<usize>)> = BTreeMap::new();
+    let mut tracked = Vec::new();
+    let mut hashes = Vec::new();
+    for torrent_path in &args.torrents {
+        let torrent = read_info_torrent(torrent_path)?;
+        let mut info_hash = [0u8; SHA1_HASH_SIZE];
+        if let Some(hash) = torrent.info_hash().and_then(|hash| hex::decode(hash).ok()) {
+            // v2-only torrents announce the truncated SHA-256 info-hash
+            info_hash.copy_from_slice(&hash[..SHA1_HASH_SIZE]);
+        }
+        let index = tracked.len();
+        let mut urls: Vec<String> = torrent.trackers().into_iter().flatten().collect();
+        urls.sort();
+        urls.dedup();
+        for url in urls {
+            users
+                .entry(url)
+                .or_insert_with(|| (info_hash, Vec::new()))
+                .1
+                .push(index);
+        }
+        tracked.push(torrent_path);
+        hashes.push(hex::encode(info_hash));
+    }
+
+    let stale: Vec<(String, InfoHash)> = users
+        .iter()
+        .filter(|(url, _)| args.refresh || cache.fresh(url, ttl).is_none())
+        .map(|(url, (info_hash, _))| (url.clone(), *info_hash))
+        .collect();
+    if !quiet && !stale.is_empty() {
+        eprintln!(
+            "I: Announcing to {} trackers, {} cached.",
+            stale.len(),
+            users.len() - stale.len()
+        );
+    }
+    // kept apart from the cache, whose entries a zero TTL never lets be fresh
+    let checked: BTreeMap<&String, TrackerHealth> = stale
+        .iter()
+        .map(|(url, _)| url)
+        .zip(tracker_health::check(&stale))
+        .collect();
+
+    let mut working = vec![false; tracked.len()];
+    let mut has_trackers = vec![false; tracked.len()];
+    let (mut down, mut refused) = (0, 0);
+    for (url, (_, torrents)) in &users {
+        let Some(health) = checked.get(url).or_else(|| cache.fresh(url, ttl)) else {
+            continue;
+        };
+        for &index in torrents {
+            has_trackers[index] = true;
+            working[index] |= health.works_for(&hashes[index]);
+        }
+        if !health.up {
+            down += 1;
+        } else if health.refused {
+            refused += 1;
+        } else if quiet {
+            continue;
+        }
+        let status = if !health.up {
+            "DOWN"
+        } else if health.refused {
+            "REFUSED"
+        } else {
+            "UP"
+        };
+        println!(
+            "{status:<8} {:>6} ms  {url} ({} torrents)",
+            health.latency_ms,
+            torrents.len()
+        );
+        if let Some(message) = &health.message {
+            println!("{:20}{message}", "");
+        }
+    }
+    for (url, health) in checked {
+        cache.insert(url.clone(), health);
+    }
+    cache.save(&cache_path, ttl)?;
+
+    let orphaned: Vec<&String> = tracked
+        .iter()
+        .zip(has_trackers.iter().zip(&working))
+        .filter(|(_, (has, works))| **has && !**works)
+        .map(|(path, _)| *path)
+        .collect();
+    for torrent_path in &orphaned {
+        println!("NO TRACKER  {torrent_path}");
+    }
+    println!(
+        "Trackers: {} ({} up, {refused} refusing, {down} down, {} from cache), torrents: {} ({} without a working tracker)",
+        users.len(),
+        users.len() - down - refused,
+        users.len() - stale.len(),
+        tracked.len(),
+        orphaned.len()
+    );
+    if !orphaned.is_empty() {
+        return Err(TrError::InvalidTorrent(format!(
+            "{} of {} torrents have no working tracker",
+            orphaned.len(),
+            tracked.len()
+        )));
+    }
+    Ok(())
+}
+
+fn retrack(args: RetrackArgs, quiet: bool) -> TrResult<()> {
+    if args.announce.is_empty() && args.replace.is_none() {
+        return Err(TrError::MissingField(String::from(
+            "announce URL (-a) or --replace prefix",
+        )));
+    }
+    for torrent_path in &args.torrents {
+        let mut torrent = read_info_torrent(torrent_path)?;
+        let vars = [
+            ("infohash", torrent.info_hash_v1().unwrap_or_default()),
+            ("infohash_v2", torrent.info_hash_v2().unwrap_or_default()),
+            (
+                "name",
+                torrent
+                    .get_info()
+                    .and_then(|info| info.name.clone())
+                    .unwrap_or_default(),
+            ),
+        ];
+        let new_urls: Vec<String> = args
+            .announce
+            .iter()
+            .map(|url| fill_template(url, &vars))
+            .collect();
+
+        let tiers = match &args.replace {
+            Some(prefix) => torrent
+                .trackers()
+                .into_iter()
+                .map(|tier| {
+                    let mut new_tier: Vec<String> = Vec::new();
+                    for url in tier {
+                        let urls = if url.starts_with(prefix.as_str()) {
+                            new_urls.clone()
+                        } else {
+                            vec![url]
+                        };
+                        for url in urls {
+                            if !new_tier.contains(&url) {
+                                new_tier.push(url);
+                            }
+                        }
+                    }
+                    new_tier
+                })
+                .collect(),
+            None => new_urls.iter().map(|url| vec![url.clone()]).collect(),
+        };
+        torrent.set_trackers(tiers);
+
+        if !quiet || args.dry_run {
+            println!("Torrent: {torrent_path}");
+            for (tier_id, tier) in torrent.trackers().iter().enumerate() {
+                for url in tier {
+                    println!("  Tier {tier_id}: {url}");
+                }
+            }
+        }
+        if !args.dry_run {
+            let file_sha256 = torrent.write_to_file(torrent_path.clone(), true)?;
+            if !quiet {
+                println!("  File SHA-256: {file_sha256}");
+            }
+        }
+    }
+    Ok(())
+}
+
+fn edit(args: EditArgs, config: &Config, quiet: bool) -> TrResult<()> {
+    if args.announce.is_empty()
+        && !args.trackers_interactive
+        && args.comment.is_none()
+        && args.date.is_none()
+        && !args.no_date
+        && !args.private
+        && !args.public
+        && args.source.is_none()
+        && !args.apply_policy
+    {
+        return Err(TrError::MissingField(String::from(
+            "edit to make, e.g. -a, --comment, --private, --trackers-interactive or --apply-policy",
+        )));
+    }
+    if args.private && args.public {
+        return Err(TrError::ParseError(String::from(
+            "--private and --public exclude each other",
+        )));
+    }
+    if args.date.is_some() && args.no_date {
+        return Err(TrError::ParseError(String::from(
+            "--date and --no-date exclude each other",
+        )));
+    }
+    if args.apply_policy && config.tracker_policy.is_empty() {
+        return Err(TrError::MissingField(String::from(
+            "tracker_policy with drop or redact entries in the config",
+        )));
+    }
+    if let Some(output) = &args.output
+        && !args.force
+        && Path::new(output).exists()
+    {
+        return Err(TrError::OutputExists(output.clone()));
+    }
+    let mut torrent = read_info_torrent(&args.torrent)?;
+    let old_hash = torrent.info_hash();
+
+    if !args.announce.is_empty() {
+        let tiers = if args.announce.iter().any(String::is_empty) {
+            Vec::new()
+        } else {
+            args.announce.iter().map(|url| vec![url.clone()]).collect()
+        };
+        torrent.set_trackers(tiers);
+    }
+    if args.trackers_interactive {
+        let Some(tiers) = wizard::edit_tiers(torrent.trackers())? else {
+            if !quiet {
+                eprintln!("I: Nothing written.");
+            }
+            return Ok(());
+        };
+        torrent.set_trackers(tiers);
+    }
+    if args.apply_policy {
+        let (tiers, findings) = config.tracker_policy.apply(torrent.trackers());
+        if !quiet {
+            for finding in &findings {
+                eprintln!("I: {}", finding.message);
+            }
+            if findings.is_empty() {
+                eprintln!("I: The tracker policy changes nothing.");
+            }
+        }
+        torrent.set_trackers(tiers);
+    }
+    if let Some(comment) = args.comment {
+        torrent.set_comment(Some(comment).filter(|c| !c.is_empty()));
+    }
+    if args.date.is_some() || args.no_date {
+        torrent.set_creation_date(args.date);
+    }
+    if args.private || args.public {
+        torrent.set_private(args.private)?;
+    }
+    if let Some(source) = args.source {
+        torrent.set_source(Some(source).filter(|s| !s.is_empty()))?;
+    }
+
+    let new_hash = torrent.info_hash();
+    if new_hash != old_hash {
+        warn(
+            WarningKind::Compatibility,
+            "The info-hash changed, clients and trackers see a new torrent and the old one is not updated.",
+        );
+    }
+    // the torrent itself is rewritten in place, another file only with -f
+    let force = args.output.is_none() || args.force;
+    let torrent_path = args.output.unwrap_or(args.torrent);
+    let file_sha256 = torrent.write_to_file(torrent_path.clone(), force)?;
+    if !quiet {
+        println!("Torrent: {torrent_path}");
+        if new_hash != old_hash {
+            print_info_hashes(&torrent);
+        }
+        println!("File SHA-256: {file_sha256}");
+    }
+    Ok(())
+}
+
+/// Writes a copy of a torrent with the trackers and source of another tracker. Files and
+/// pieces are kept, the new source gives it an info-hash of its own.
+fn cross_seed(args: CrossSeedArgs, config: &Config, quiet: bool) -> TrResult<()> {
+    let tiers = if args.announce.is_empty() {
+        announce_tiers(&config.tracker_list, config.tracker_tiers)
+    } else {
+        args.announce.iter().map(|url| vec![url.clone()]).collect()
+    };
+    if tiers.is_empty() {
+        return Err(TrError::MissingField(String::from(
+            "announce URL of the other tracker (-a or tracker_list in the config)",
+        )));
+    }
+    let source = args
+        .source
+        .or_else(|| config.source.clone())
+        .filter(|s| !s.is_empty());
+
+    let mut torrent = read_info_torrent(&args.torrent)?;
+    let old_hash = torrent.info_hash();
+    torrent.set_trackers(tiers);
+    // patches the stored info dict, v2 file tree and unknown info keys are copied as is
+    torrent.set_source(source)?;
+    if torrent.info_hash() == old_hash {
+        return Err(TrError::ParseError(format!(
+            "the copy would have the info-hash of '{}', give a source other than its own with -s",
+            args.torrent
+        )));
+    }
+
+    let file_sha256 = torrent.write_to_file(args.output.clone(), args.force)?;
+    if !quiet {
+        println!("Torrent: {}", args.output);
+        print_info_hashes(&torrent);
+        println!("File SHA-256: {file_sha256}");
+    }
+    Ok(())
+}
+
+fn magnet(args: MagnetArgs, quiet: bool) -> TrResult<()> {
+    let magnet = args.uri;
+    if !quiet {
+        eprintln!(
+            "I: Fetching metadata for {}.",
+            magnet
+                .name
+                .clone()
+                .unwrap_or_else(|| hex::encode(magnet.info_hash))
+        );
+    }
+    let info = magnet::fetch_metadata(&magnet, Duration::from_secs(args.timeout), quiet)?;
+    // the info dict is kept byte for byte, so the torrent has the magnet's info-hash
+    let mut bcode = b"d4:info".to_vec();
+    bcode.extend_from_slice(&info);
+    bcode.push(b'e');
+    let mut torrent = Torrent::from_bytes(&bcode)?;
+    torrent.set_trackers(magnet.trackers.into_iter().map(|url| vec![url]).collect());
+
+    let torrent_path = match args.output {
+        Some(output) => output,
+        None => {
+            // the name comes from a peer, keep it from pointing outside the current directory
+            let name = torrent.get_info().and_then(|info| info.get_name().ok());
+            let name = name
+                .as_deref()
+                .and_then(|name| Path::new(name).file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| hex::encode(magnet.info_hash));
+            format!("{name}.torrent")
+        }
+    };
+    let file_sha256 = torrent.write_to_file(torrent_path.clone(), args.force)?;
+    println!("Torrent: {torrent_path}");
+    if !quiet {
+        println!("File SHA-256: {file_sha256}");
+    }
+    Ok(())
+}
+
+fn gen_fixture(args: GenFixtureArgs, config: &Config, quiet: bool) -> TrResult<()> {
+    let output = Path::new(&args.output);
+    if output
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some())
+    {
+        return Err(TrError::InvalidPath(format!(
+            "{} is not empty, fixtures go to a new directory",
+            args.output
+        )));
+    }
+    let name = format!("fixture-{}", args.seed);
+    let root = output.join(&name);
+    let torrent_path = output.join(format!("{name}.torrent"));
+    let piece_length = args.piece_size.unwrap_or(1 << 14);
+    check_piece_length(piece_length)?;
+    let max_size = args.max_size.unwrap_or(1 << 20);
+    let files = fixture::generate(&root, args.files, args.sizes, max_size, args.seed)?;
+    let total: usize = files.iter().map(|f| f.length).sum();
+    if !quiet {
+        eprintln!("I: {} files, {} written.", files.len(), human_size(total));
+    }
+
+    // no date, so the same seed also gives the same torrent file
+    let torrent = TorrentBuilder::new()
+        .creation_date(None)
+        .piece_length(piece_length)
+        .walk_mode(WalkMode::Alphabetical)
+        .n_jobs(config.n_jobs)
+        .quiet(quiet)
+        .pause_keys(true)
+        .build(&root.to_string_lossy())?;
+    torrent.write_to_file(torrent_path.to_string_lossy().to_string(), false)?;
+    println!("Content: {}", root.display());
+    println!("Torrent: {}", torrent_path.display());
+    print_info_hashes(&torrent);
+    Ok(())
+}
+
+fn size_check(args: SizeCheckArgs, quiet: bool) -> TrResult<()> {
+    let torrent = read_info_torrent(&args.torrent)?;
+    let base_path = Path::new(&args.target);
+    let files = torrent.files();
+    let files: Vec<&TrFile> = files
+        .iter()
+        .filter(|f| !f.padding && f.symlink.is_none())
+        .collect();
+    let (mut missing, mut wrong_size) = (0, 0);
+    for tr_file in files.iter() {
+        let full_path = tr_file.join_full_path(base_path);
+        let display_path = full_path.display();
+        match full_path.metadata() {
+            Ok(meta) if meta.is_file() && meta.len() as usize == tr_file.length => {
+                if !quiet {
+                    println!("OK       {display_path}");
+                }
+            }
+            Ok(meta) if meta.is_file() => {
+                println!(
+                    "SIZE     {display_path} (expected {}, found {})",
+                    tr_file.length,
+                    meta.len()
+                );
+                wrong_size += 1;
+            }
+            _ => {
+                println!("MISSING  {display_path}");
+                missing += 1;
+            }
+        }
+    }
+    let ok = files.len() - missing - wrong_size;
+    println!(
+        "Files: {:8} total = {ok:8} ok + {missing:8} missing + {wrong_size:8} wrong size",
+        files.len()
+    );
+    if missing + wrong_size > 0 {
+        return Err(TrError::InvalidPath(format!(
+            "{} of {} files missing or of the wrong size",
+            missing + wrong_size,
+            files.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Creation settings carried over from an existing torrent, for rebuilding its content.
+/// A torrent with v1 pieces and a `meta version` of 2 is rebuilt as hybrid, the callers
+/// refuse v2-only ones.
+fn tr_config_like(info: &TrInfo, config: &Config, torrent_path: &str) -> TrConfig {
+    let hybrid = info.meta_version == Some(2);
+    TrConfig {
+        piece_length: info.piece_length,
+        auto_piece_size: false,
+        meta_version: if hybrid {
+            MetaVersion::Hybrid
+        } else {
+            MetaVersion::V1
+        },
+        pad_files: hybrid,
+        private: info.private,
+        n_jobs: config.n_jobs,
+        read_buffer: config.read_buffer,
+        walk_mode: WalkMode::Alphabetical,
+        sort_locale: config.sort_locale,
+        stable_order_check: false,
+        file_order: None,
+        link_policy: LinkPolicy::Follow,
+        offline_files: OfflinePolicy::Abort,
+        output_path: canonical_output_path(torrent_path),
+        exclude_torrents: false,
+        skip_junk: false,
+        exclude: Vec::new(),
+        include: Vec::new(),
+        min_file_size: None,
+        max_file_size: None,
+        piece_cache: None,
+        source: info.source.clone(),
+        collections: info.collections.clone().unwrap_or_default(),
+        info_keys: info.extra.clone(),
+        pause_keys: true,
+    }
+}
+
+/// Whether every file starts on a piece boundary, as padding files make them, so a
+/// changed file leaves the pieces of the others alone.
+fn files_aligned(files: &[TrFile], piece_length: usize) -> bool {
+    let mut offset = 0;
+    for tr_file in files {
+        if !tr_file.padding && offset % piece_length != 0 {
+            return false;
+        }
+        offset += tr_file.length;
+    }
+    true
+}
+
+fn update(args: UpdateArgs, config: &Config, quiet: bool) -> TrResult<()> {
+    if !Path::new(&args.target).exists() {
+        return Err(TrError::InvalidPath(format!(
+            "{} does not exist",
+            args.target
+        )));
+    }
+    let old = read_info_torrent(&args.torrent)?;
+    let old_info = old.get_info().unwrap();
+    if old_info.pieces.is_empty() {
+        return Err(TrError::InvalidTorrent(String::from(
+            "torrent has no v1 piece hashes to reuse",
+        )));
+    }
+    let old_files = old.files();
+    if !files_aligned(&old_files, old_info.piece_length) {
+        return Err(TrError::InvalidTorrent(String::from(
+            "update needs a torrent with padding files, create it with --pad-files or --meta-version hybrid",
+        )));
+    }
+    let torrent_path = args
+        .output
+        .unwrap_or_else(|| format!("{}.torrent", args.target.trim_end_matches(['/', '\\'])));
+    if !args.force && Path::new(&torrent_path).exists() {
+        return Err(TrError::OutputExists(torrent_path));
+    }
+
+    let mut tr_config = tr_config_like(old_info, config, &torrent_path);
+
+    // files already in the torrent keep their order so their pieces stay aligned,
+    // new files are appended in alphabetical order
+    let old_files: Vec<&TrFile> = old_files.iter().filter(|f| !f.padding).collect();
+    let current = TrInfo::preflight(&args.target, &tr_config)?;
+    let current_files = current.tr_files()?;
+    let current_files: Vec<&TrFile> = current_files.iter().filter(|f| !f.padding).collect();
+    let old_paths: HashSet<&[String]> = old_files.iter().map(|f| f.path.as_slice()).collect();
+    let current_paths: HashSet<&[String]> =
+        current_files.iter().map(|f| f.path.as_slice()).collect();
+    let removed: Vec<&TrFile> = old_files
+        .iter()
+        .copied()
+        .filter(|f| !current_paths.contains(f.path.as_slice()))
+        .collect();
+    let added: Vec<&TrFile> = current_files
+        .iter()
+        .copied()
+        .filter(|f| !old_paths.contains(f.path.as_slice()))
+        .collect();
+    tr_config.file_order = Some(
+        old_files
+            .iter()
+            .copied()
+            .filter(|f| current_paths.contains(f.path.as_slice()))
+            .chain(added.iter().copied())
+            .map(|f| f.path.clone())
+            .collect(),
+    );
+    tr_config.pad_files = true;
+
+    if !quiet {
+        eprintln!("I: Update mode.");
+        eprintln!("Torrent: {}", args.torrent);
+        eprintln!("Target:  {}", args.target);
+        for tr_file in &removed {
+            println!("Removed: {}", tr_file.path.join("/"));
+        }
+        for tr_file in &added {
+            println!("Added:   {}", tr_file.path.join("/"));
+        }
+        eprintln!("I: {} files added, {} removed.", added.len(), removed.len());
+    }
+
+    let mut cache = PieceCache::in_memory();
+    match old.creation_date() {
+        Some(created) => {
+            old_info.seed_piece_cache(&args.target, Some(created), &mut cache)?;
+        }
+        // sizes alone do not tell an unchanged file from a rewritten one
+        None => warn(
+            WarningKind::Data,
+            "Torrent has no creation date to tell unchanged files by, every piece is hashed again.",
+        ),
+    }
+    let mut torrent = old.new_like(
+        Some(NAME_VERSION.to_string()),
+        Some(Local::now().timestamp()),
+    );
+    torrent.preflight_torrent(&args.target, &tr_config)?;
+    let perf = torrent.hash_with_cache(&args.target, &tr_config, Some(&mut cache), quiet)?;
+    if !quiet {
+        print_cache_reuse(&perf, &torrent);
+    }
+    let file_sha256 = torrent.write_to_file(torrent_path.clone(), args.force)?;
+    if !quiet {
+        println!("Torrent: {torrent_path}");
+        print_info_hashes(&torrent);
+        println!("File SHA-256: {file_sha256}");
+    }
+    Ok(())
+}
+
+fn split(args: SplitArgs, config: &Config, quiet: bool) -> TrResult<()> {
+    if !args.by_dir {
+        return Err(TrError::MissingField(String::from(
+            "split mode, e.g. --by-dir",
+        )));
+    }
+    let old = read_info_torrent(&args.torrent)?;
+    let old_info = old.get_info().unwrap();
+    if old_info.files.is_none() || old_info.pieces.is_empty() {
+        return Err(TrError::InvalidTorrent(String::from(
+            "only multi-file torrents with v1 piece hashes can be split",
+        )));
+    }
+    let old_files = old.files();
+    let mut dirs: Vec<&str> = Vec::new();
+    for tr_file in old_files.iter().filter(|f| !f.padding) {
+        match tr_file.path.as_slice() {
+            [dir, _, ..] if !dirs.contains(&dir.as_str()) => dirs.push(dir),
+            [_, _, ..] => {}
+            _ => warn(
+                WarningKind::SkippedFile,
+                format!(
+                    "'{}' is not in a subdirectory and is left out of the split",
+                    tr_file.path.join("/")
+                ),
+            ),
+        }
+    }
+    let out_dir = match &args.output {
+        Some(dir) => PathBuf::from(dir),
+        None => Path::new(&args.torrent)
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default(),
+    };
+    let outputs: Vec<String> = dirs
+        .iter()
+        .map(|dir| {
+            out_dir
+                .join(format!("{dir}.torrent"))
+                .to_string_lossy()
+                .into_owned()
+        })
+        .collect();
+    if !args.force
+        && let Some(existing) = outputs.iter().find(|path| Path::new(path).exists())
+    {
+        return Err(TrError::OutputExists(existing.clone()));
+    }
+
+    // the data is verified, so sizes alone tell which files the old hashes still cover
+    let mut cache = PieceCache::in_memory();
+    old_info.seed_piece_cache(&args.data, None, &mut cache)?;
+    if !quiet {
+        eprintln!("I: Split mode, {} directories.", dirs.len());
+    }
+    for (dir, torrent_path) in dirs.iter().zip(outputs) {
+        let target = Path::new(&args.data)
+            .join(dir)
+            .to_string_lossy()
+            .into_owned();
+        let mut tr_config = tr_config_like(old_info, config, &torrent_path);
+        tr_config.file_order = Some(
+            old_files
+                .iter()
+                .filter(|f| f.path.len() > 1 && f.path[0] == *dir)
+                .map(|f| f.path[1..].to_vec())
+                .collect(),
+        );
+        if !quiet {
+            eprintln!("Target:  {target}");
+        }
+        let mut torrent = old.new_like(
+            Some(NAME_VERSION.to_string()),
+            Some(Local::now().timestamp()),
+        );
+        torrent.preflight_torrent(&target, &tr_config)?;
+        let perf = torrent.hash_with_cache(&target, &tr_config, Some(&mut cache), quiet)?;
+        if !quiet {
+            print_cache_reuse(&perf, &torrent);
+        }
+        torrent.write_to_file(torrent_path.clone(), args.force)?;
+        if !quiet && let Some(info_hash) = torrent.info_hash() {
+            println!("{info_hash}  {torrent_path}");
+        }
+    }
+    Ok(())
+}
+
+fn join(args: JoinArgs, config: &Config, quiet: bool) -> TrResult<()> {
+    if args.sources.is_empty() {
+        return Err(TrError::MissingField(String::from("source directory")));
+    }
+    let piece_size = args
+        .piece_size
+        .or(config.piece_size)
+        .unwrap_or(DEF_PIECE_SIZE);
+    if !(MIN_PIECE_SIZE..=MAX_PIECE_SIZE).contains(&piece_size) {
+        return Err(TrError::ParseError(format!(
+            "piece size must be between 16K and 128M (exponent {MIN_PIECE_SIZE} to {MAX_PIECE_SIZE})"
+        )));
+    }
+    let map_path = args
+        .map
+        .clone()
+        .unwrap_or_else(|| format!("{}.map", args.output));
+    if !args.force
+        && let Some(existing) = [&args.output, &map_path]
+            .into_iter()
+            .find(|path| Path::new(path).exists())
+    {
+        return Err(TrError::OutputExists(existing.clone()));
+    }
+    let tr_config = TrConfig {
+        piece_length: 1usize << piece_size,
+        auto_piece_size: false,
+        meta_version: MetaVersion::V1,
+        pad_files: false,
+        private: args.private || config.private,
+        n_jobs: config.n_jobs,
+        read_buffer: config.read_buffer,
+        walk_mode: WalkMode::Alphabetical,
+        sort_locale: config.sort_locale,
+        stable_order_check: false,
+        file_order: None,
+        link_policy: LinkPolicy::Follow,
+        offline_files: OfflinePolicy::Abort,
+        output_path: canonical_output_path(&args.output),
+        exclude_torrents: false,
+        skip_junk: false,
+        exclude: Vec::new(),
+        include: Vec::new(),
+        min_file_size: None,
+        max_file_size: None,
+        piece_cache: None,
+        source: config.source.clone().filter(|s| !s.is_empty()),
+        collections: Vec::new(),
+        info_keys: Default::default(),
+        pause_keys: true,
+    };
+
+    // every source becomes a top-level entry named after it, in the given order
+    let mut tr_files: Vec<TrFile> = Vec::new();
+    let mut f_path_list: Vec<PathBuf> = Vec::new();
+    let mut names: HashSet<String> = HashSet::new();
+    for source in &args.sources {
+        let source = source.trim_end_matches(['/', '\\']);
+        let info = TrInfo::preflight(source, &tr_config)?;
+        let name = info.get_name()?;
+        if !names.insert(name.clone()) {
+            return Err(TrError::InvalidPath(format!(
+                "two sources are named '{name}', rename one first"
+            )));
+        }
+        for tr_file in info.tr_files()?.iter() {
+            f_path_list.push(tr_file.join_full_path(Path::new(source)));
+            tr_files.push(TrFile {
+                length: tr_file.length,
+                path: [name.clone()]
+                    .into_iter()
+                    .chain(tr_file.path.iter().cloned())
+                    .collect(),
+                padding: false,
+                symlink: tr_file.symlink.as_ref().map(|target| {
+                    [name.clone()]
+                        .into_iter()
+                        .chain(target.iter().cloned())
+                        .collect()
+                }),
+            });
+        }
+    }
+    if !quiet {
+        eprintln!("I: Join mode, {} sources.", args.sources.len());
+        eprintln!("Torrent: {}", args.output);
+    }
+
+    let total_size: usize = tr_files.iter().map(|f| f.length).sum();
+    let mut info = TrInfo {
+        collections: None,
+        files: Some(tr_files),
+        length: None,
+        name: Some(args.name.clone()),
+        piece_length: tr_config.piece_length,
+        pieces: vec![0u8; total_size.div_ceil(tr_config.piece_length) * SHA1_HASH_SIZE],
+        private: tr_config.private,
+        source: tr_config.source.clone(),
+        meta_version: None,
+        v2: None,
+        extra: Default::default(),
+    };
+    info.hash_pieces_from(&f_path_list, &tr_config, None, quiet)?;
+
+    let tiers = announce_tiers(&config.tracker_list, config.tracker_tiers);
+    let mut torrent = Torrent::new(
+        None,
+        None,
+        None,
+        Some(NAME_VERSION.to_string()),
+        Some(Local::now().timestamp()),
+        Some(String::from("UTF-8")),
+    );
+    torrent.set_trackers(tiers);
+    let mut mapping = String::new();
+    for (f_path, tr_file) in f_path_list.iter().zip(info.tr_files()?.iter()) {
+        mapping += &format!(
+            "{}\t{}/{}\n",
+            f_path.display(),
+            args.name,
+            tr_file.path.join("/")
+        );
+    }
+    torrent.set_info(info);
+    check_write(Path::new(&map_path))?;
+    let file_sha256 = torrent.write_to_file(args.output.clone(), args.force)?;
+    write(&map_path, mapping)?;
+    if !quiet {
+        print_info_hashes(&torrent);
+        println!("File SHA-256: {file_sha256}");
+        println!("Mapping: {map_path}");
+    }
+    Ok(())
+}
+
+/// Age as the largest whole unit, e.g. `3d` or `5h`.
+fn short_age(age: Duration) -> String {
+    let secs = age.as_secs();
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
+fn print_state_entries(entries: &[StateEntry]) {
+    for entry in entries {
+        println!(
+            "{:<14} {:>10} {:>5}  {}",
+            entry.kind,
+            human_size(entry.size as usize),
+            short_age(entry.age),
+            entry.path.display()
+        );
+    }
+}
+
+fn state(args: StateArgs, quiet: bool) -> TrResult<()> {
+    let dir = state::state_dir();
+    match args.command {
+        StateCommand::Ls(_) => {
+            let entries = state::entries()?;
+            if !quiet {
+                eprintln!("I: State directory '{}'.", dir.display());
+            }
+            print_state_entries(&entries);
+            let total: u64 = entries.iter().map(|entry| entry.size).sum();
+            println!("{} files, {}", entries.len(), human_size(total as usize));
+        }
+        StateCommand::Clean(clean_args) => {
+            let removed = state::clean(clean_args.older_than)?;
+            if !quiet {
+                print_state_entries(&removed);
+            }
+            let total: u64 = removed.iter().map(|entry| entry.size).sum();
+            println!(
+                "Removed {} files, {} from '{}'",
+                removed.len(),
+                human_size(total as usize),
+                dir.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all};
+
+    use super::*;
+    use crate::tr_info::VerifyOptions;
+
+    /// Fresh directory under the system temp dir, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("tur-{name}-{}", std::process::id()));
+            let _ = remove_dir_all(&dir);
+            create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.0.join(name).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = remove_dir_all(&self.0);
+        }
+    }
+
+    fn content(len: usize, seed: u8) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8 ^ seed).collect()
+    }
+
+    /// Creates a padded torrent of `data`, dated after the files so `update` reuses them,
+    /// then changes one file, adds another and updates the torrent.
+    fn update_padded(dir: &TempDir, meta_version: MetaVersion) -> (Torrent, Torrent) {
+        let data = dir.path("data");
+        create_dir_all(&data).unwrap();
+        write(dir.path("data/a.bin"), content(40000, 1)).unwrap();
+        write(dir.path("data/b.bin"), content(5000, 2)).unwrap();
+        let old = TorrentBuilder::new()
+            .piece_length(1 << 14)
+            .pad_files(true)
+            .meta_version(meta_version)
+            .walk_mode(WalkMode::Alphabetical)
+            .creation_date(Some(Local::now().timestamp() + 3600))
+            .build(&data)
+            .unwrap();
+        old.write_to_file(dir.path("old.torrent"), false).unwrap();
+
+        write(dir.path("data/b.bin"), content(6000, 3)).unwrap();
+        write(dir.path("data/c.bin"), content(20000, 4)).unwrap();
+        let args = UpdateArgs {
+            torrent: dir.path("old.torrent"),
+            target: data,
+            output: Some(dir.path("new.torrent")),
+            force: false,
+        };
+        update(args, &Config::default(), true).unwrap();
+        (old, Torrent::read_torrent(dir.path("new.torrent")).unwrap())
+    }
+
+    #[test]
+    fn update_reuses_the_pieces_of_unchanged_files() {
+        let dir = TempDir::new("update-v1");
+        let (old, new) = update_padded(&dir, MetaVersion::V1);
+        let (old_info, new_info) = (old.get_info().unwrap(), new.get_info().unwrap());
+        assert!(!new.is_hybrid());
+
+        // a.bin comes first and fills three pieces, their hashes carry over
+        assert_eq!(
+            new_info.pieces[..3 * SHA1_HASH_SIZE],
+            old_info.pieces[..3 * SHA1_HASH_SIZE]
+        );
+        let (summary, _) = new_info
+            .verify(&dir.path("data"), &VerifyOptions::default())
+            .unwrap();
+        assert_eq!(summary.total_pieces, 3 + 1 + 2);
+        assert_eq!(summary.failed_pieces, 0);
+    }
+
+    #[test]
+    fn update_keeps_a_hybrid_torrent_hybrid() {
+        let dir = TempDir::new("update-hybrid");
+        let (_, new) = update_padded(&dir, MetaVersion::Hybrid);
+        assert!(new.is_hybrid());
+        let (summary, _) = new
+            .get_info()
+            .unwrap()
+            .verify(&dir.path("data"), &VerifyOptions::default())
+            .unwrap();
+        assert_eq!(summary.failed_pieces, 0);
+    }
+}
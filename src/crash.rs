@@ -0,0 +1,59 @@
+use std::cell::RefCell;
+use std::panic;
+
+use crate::NAME_VERSION;
+use crate::warnings;
+
+const ISSUES_URL: &str = "https://github.com/lapluis/TorrentUtilsR/issues";
+
+thread_local! {
+    // per thread, so the parallel jobs of a batch each report their own target
+    static CONTEXT: RefCell<String> = const { RefCell::new(String::new()) };
+}
+
+/// Records what the calling thread is doing, e.g. "reading 'a.torrent'", for the report
+/// of a panic.
+pub fn set_context(context: impl Into<String>) {
+    CONTEXT.with_borrow_mut(|current| *current = context.into());
+}
+
+/// What the calling thread is doing, for threads it starts to take over with `set_context`.
+pub fn context() -> String {
+    CONTEXT.with_borrow(Clone::clone)
+}
+
+/// Replaces the default panic output with a report saying what was being done and where
+/// to file the bug. The default output, backtrace included, follows with RUST_BACKTRACE.
+pub fn install_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let payload = info.payload();
+        let message = payload
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown cause");
+        // output piped into `head` and the like was closed early, not a bug
+        if message.starts_with("failed printing to std") && message.contains("Broken pipe") {
+            warnings::print_summary();
+            std::process::exit(141);
+        }
+        let context = context();
+        let context = if context.is_empty() {
+            String::from("starting")
+        } else {
+            context
+        };
+        eprintln!();
+        eprintln!("Error: internal error while {context}: {message}");
+        if let Some(location) = info.location() {
+            eprintln!("  at {}:{}", location.file(), location.line());
+        }
+        eprintln!(
+            "This is a bug in {NAME_VERSION}. Please report it at {ISSUES_URL} with the command line and, if you can share it, the torrent file."
+        );
+        if std::env::var_os("RUST_BACKTRACE").is_some() {
+            default_hook(info);
+        }
+    }));
+}
@@ -0,0 +1,71 @@
+use std::sync::OnceLock;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
+
+const DEF_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+// tried in order for dates without a UTC offset, taken as local time
+const LOCAL_FORMATS: [&str; 4] = [
+    "%Y-%m-%d %H:%M:%S",
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%dT%H:%M",
+];
+
+static DATE_FORMAT: OnceLock<String> = OnceLock::new();
+
+/// Checks a strftime format for `--date-format`, `rfc3339` selects RFC 3339 output.
+pub fn parse_date_format(s: &str) -> Result<String, String> {
+    if s == "rfc3339" || !StrftimeItems::new(s).any(|item| matches!(item, Item::Error)) {
+        Ok(s.to_string())
+    } else {
+        Err(format!("invalid date format '{s}'"))
+    }
+}
+
+pub fn set_date_format(format: String) {
+    let _ = DATE_FORMAT.set(format);
+}
+
+/// Local time of a unix timestamp in the `--date-format` format, the timestamp itself if
+/// it is out of range.
+pub fn format_timestamp(ts: i64) -> String {
+    let Some(dt) = Local.timestamp_opt(ts, 0).single() else {
+        return ts.to_string();
+    };
+    match DATE_FORMAT.get().map(String::as_str) {
+        Some("rfc3339") => dt.to_rfc3339(),
+        Some(format) => dt.format(format).to_string(),
+        None => dt.format(DEF_DATE_FORMAT).to_string(),
+    }
+}
+
+/// Parses `--date`: a unix timestamp, RFC 3339 (`2024-05-01T12:00:00+02:00`), or a local
+/// `YYYY-MM-DD[ HH:MM[:SS]]` with `T` or a space before the time.
+pub fn parse_date(s: &str) -> Result<i64, String> {
+    let s = s.trim();
+    if let Ok(ts) = s.parse::<i64>() {
+        return Ok(ts);
+    }
+    if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+        return Ok(dt.timestamp());
+    }
+    let naive = LOCAL_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(|| {
+            format!(
+                "invalid date '{s}', expected a unix timestamp, RFC 3339 or YYYY-MM-DD[ HH:MM[:SS]]"
+            )
+        })?;
+    Local
+        .from_local_datetime(&naive)
+        .earliest()
+        .map(|dt| dt.timestamp())
+        .ok_or_else(|| format!("'{s}' does not exist in the local time zone"))
+}
@@ -0,0 +1,69 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::tr_info::{SHA1_HASH_SIZE, TrInfo};
+use crate::utils::{TrError, TrResult};
+
+/// Prefix of every fingerprint, changed along with the canonical form.
+const VERSION: &str = "fp1";
+
+/// What identifies the content of a torrent apart from its name, file names, trackers
+/// and other metadata, so indexers can match the same release across trackers.
+#[derive(Serialize)]
+pub struct Fingerprint {
+    /// `fp1-` and the SHA-256 of the canonical form of the fields below.
+    pub fingerprint: String,
+    pub total_size: usize,
+    pub piece_length: usize,
+    /// Ascending, padding files left out.
+    pub file_sizes: Vec<usize>,
+    /// Hex SHA-1 hashes of the first pieces, if asked for.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub head_pieces: Vec<String>,
+}
+
+impl Fingerprint {
+    /// Fingerprints `info`, with the hashes of its first `head_pieces` pieces.
+    pub fn new(info: &TrInfo, head_pieces: usize) -> TrResult<Self> {
+        let mut file_sizes: Vec<usize> = info
+            .tr_files()?
+            .iter()
+            .filter(|f| !f.padding)
+            .map(|f| f.length)
+            .collect();
+        file_sizes.sort_unstable();
+        if head_pieces > 0 && info.pieces.is_empty() {
+            return Err(TrError::InvalidTorrent(String::from(
+                "no v1 piece hashes for head pieces",
+            )));
+        }
+        let head_pieces: Vec<String> = info
+            .pieces
+            .chunks(SHA1_HASH_SIZE)
+            .take(head_pieces)
+            .map(hex::encode)
+            .collect();
+
+        // fp1:<piece length>:<total size>:<sizes>[:<head pieces>]
+        let join = |items: Vec<String>| items.join(",");
+        let mut canonical = format!(
+            "{VERSION}:{}:{}:{}",
+            info.piece_length,
+            info.total_size(),
+            join(file_sizes.iter().map(usize::to_string).collect())
+        );
+        if !head_pieces.is_empty() {
+            canonical += &format!(":{}", join(head_pieces.clone()));
+        }
+        Ok(Fingerprint {
+            fingerprint: format!(
+                "{VERSION}-{}",
+                hex::encode(Sha256::digest(canonical.as_bytes()))
+            ),
+            total_size: info.total_size(),
+            piece_length: info.piece_length,
+            file_sizes,
+            head_pieces,
+        })
+    }
+}
@@ -0,0 +1,112 @@
+use std::fs::{File, create_dir_all};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::utils::{TrResult, check_write};
+
+const WRITE_CHUNK: usize = 64 * 1024;
+
+/// How `generate` spreads file sizes up to the largest size.
+#[derive(Clone, Copy)]
+pub enum SizeDistribution {
+    /// the file of rank r gets max / r: a few large files and a long tail of small ones
+    Zipf,
+    /// any size from 0 to the largest, empty files included
+    Uniform,
+    /// every file gets the largest size
+    Equal,
+}
+
+impl FromStr for SizeDistribution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zipf" => Ok(SizeDistribution::Zipf),
+            "uniform" => Ok(SizeDistribution::Uniform),
+            "equal" => Ok(SizeDistribution::Equal),
+            _ => Err(format!(
+                "Invalid size distribution '{s}', expected zipf, uniform or equal"
+            )),
+        }
+    }
+}
+
+/// SplitMix64: seedable and the same on every platform, so a seed names a fixture.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..n`, `n` must not be 0.
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+}
+
+/// A generated file, `path` relative to the fixture root.
+pub struct FixtureFile {
+    pub path: PathBuf,
+    pub length: usize,
+}
+
+/// Writes `files` files of random bytes under `root`, spread over up to two levels of
+/// directories. The same arguments give the same tree and content.
+pub fn generate(
+    root: &Path,
+    files: usize,
+    sizes: SizeDistribution,
+    max_size: usize,
+    seed: u64,
+) -> TrResult<Vec<FixtureFile>> {
+    check_write(root)?;
+    let mut rng = Rng::new(seed);
+    let mut buf = vec![0u8; WRITE_CHUNK];
+    let mut generated = Vec::with_capacity(files);
+    for i in 0..files {
+        let mut path = PathBuf::new();
+        for _ in 0..rng.below(3) {
+            path.push(format!("dir{:02}", rng.below(4)));
+        }
+        path.push(format!("file{i:04}.bin"));
+        let length = match sizes {
+            SizeDistribution::Zipf => max_size / (rng.below(files as u64) as usize + 1),
+            SizeDistribution::Uniform => rng.below(max_size as u64 + 1) as usize,
+            SizeDistribution::Equal => max_size,
+        };
+
+        let full_path = root.join(&path);
+        if let Some(parent) = full_path.parent() {
+            create_dir_all(parent)?;
+        }
+        let mut out = BufWriter::new(File::create(&full_path)?);
+        let mut left = length;
+        while left > 0 {
+            let n = left.min(WRITE_CHUNK);
+            rng.fill(&mut buf[..n]);
+            out.write_all(&buf[..n])?;
+            left -= n;
+        }
+        out.flush()?;
+        generated.push(FixtureFile { path, length });
+    }
+    Ok(generated)
+}
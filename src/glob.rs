@@ -0,0 +1,185 @@
+use std::str::FromStr;
+
+#[derive(Clone)]
+enum Token {
+    Literal(char),
+    /// `?`: one character other than `/`
+    One,
+    /// `*`: any run of characters other than `/`
+    Star,
+    /// `**/`: nothing, or any path ending with `/`
+    AnyDirs,
+    /// `**` elsewhere: anything, `/` included
+    AnyPath,
+    /// `[a-z]` or `[!a-z]`
+    Class(Vec<(char, char)>, bool),
+}
+
+/// Shell-style pattern over torrent-relative paths with `/` separators: `*` and `?` stay
+/// within one path component, `**` spans components and `[...]` is a character class.
+/// A pattern without `/` is matched against the file name at any depth, like in
+/// `.gitignore`, so `*.nfo` matches `Extras/info.nfo`; others match the whole path, and a
+/// leading `/` only anchors them, so `/*.nfo` matches `info.nfo` alone.
+#[derive(Clone)]
+pub struct Glob {
+    tokens: Vec<Token>,
+    name_only: bool,
+}
+
+impl FromStr for Glob {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(String::from(
+                "Invalid pattern '', expected a glob such as *.nfo",
+            ));
+        }
+        let mut tokens = Vec::new();
+        let mut chars = s.strip_prefix('/').unwrap_or(s).chars().peekable();
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::One,
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        Token::AnyDirs
+                    } else {
+                        Token::AnyPath
+                    }
+                }
+                '*' => Token::Star,
+                '[' => {
+                    let negated = chars.next_if(|&c| c == '!' || c == '^').is_some();
+                    let mut ranges = Vec::new();
+                    loop {
+                        match chars.next() {
+                            Some(']') if !ranges.is_empty() => break,
+                            Some(start) if chars.next_if_eq(&'-').is_some() => {
+                                match chars.next_if(|&c| c != ']') {
+                                    Some(end) => ranges.push((start, end)),
+                                    // a `-` before the closing `]` is literal
+                                    None => ranges.extend([(start, start), ('-', '-')]),
+                                }
+                            }
+                            Some(start) => ranges.push((start, start)),
+                            None => return Err(format!("Invalid pattern '{s}', unclosed [")),
+                        }
+                    }
+                    Token::Class(ranges, negated)
+                }
+                c => Token::Literal(c),
+            };
+            tokens.push(token);
+        }
+        Ok(Glob {
+            tokens,
+            name_only: !s.contains('/'),
+        })
+    }
+}
+
+impl Glob {
+    /// Whether `path`, relative and `/`-separated, matches.
+    pub fn matches(&self, path: &str) -> bool {
+        let path = if self.name_only {
+            path.rsplit('/').next().unwrap_or(path)
+        } else {
+            path
+        };
+        let chars: Vec<char> = path.chars().collect();
+        let n = chars.len();
+        // next[j]: the tokens after the current one match chars[j..]
+        let mut next = vec![false; n + 1];
+        next[n] = true;
+        for token in self.tokens.iter().rev() {
+            let mut here = vec![false; n + 1];
+            for j in (0..=n).rev() {
+                let c = chars.get(j).copied();
+                here[j] = match token {
+                    Token::Literal(l) => c == Some(*l) && next[j + 1],
+                    Token::One => c.is_some_and(|c| c != '/') && next[j + 1],
+                    Token::Class(ranges, negated) => {
+                        c.is_some_and(|c| {
+                            c != '/'
+                                && ranges.iter().any(|&(lo, hi)| (lo..=hi).contains(&c)) != *negated
+                        }) && next[j + 1]
+                    }
+                    Token::Star => next[j] || (c.is_some_and(|c| c != '/') && here[j + 1]),
+                    Token::AnyPath => next[j] || (c.is_some() && here[j + 1]),
+                    Token::AnyDirs => next[j] || (j..n).any(|k| chars[k] == '/' && next[k + 1]),
+                };
+            }
+            next = here;
+        }
+        next[0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn glob(pattern: &str) -> Glob {
+        pattern.parse().unwrap()
+    }
+
+    #[test]
+    fn patterns_match_paths() {
+        let cases: &[(&str, &str, bool)] = &[
+            // name-only patterns match the file name at any depth
+            ("*.nfo", "info.nfo", true),
+            ("*.nfo", "Extras/info.nfo", true),
+            ("*.nfo", "info.nfo/video.mkv", false),
+            ("Sample", "a/b/Sample", true),
+            ("Sample", "Sample2", false),
+            ("?.txt", "dir/a.txt", true),
+            ("?.txt", "ab.txt", false),
+            // patterns with a `/` match the whole path, a leading `/` only anchors them
+            ("Extras/*.nfo", "Extras/info.nfo", true),
+            ("Extras/*.nfo", "Disc/Extras/info.nfo", false),
+            ("/*.nfo", "info.nfo", true),
+            ("/*.nfo", "Extras/info.nfo", false),
+            ("/Extras", "Extras", true),
+            ("*/a.txt", "x/y/a.txt", false),
+            // `**/` matches no directory or any number of them
+            ("**/Sample/**", "Sample/a.mkv", true),
+            ("**/Sample/**", "Disc 1/Sample/a.mkv", true),
+            ("**/Sample/**", "Disc 1/Samples/a.mkv", false),
+            ("**/*.txt", "a.txt", true),
+            ("**/*.txt", "a/b/c.txt", true),
+            ("a/**/b", "a/b", true),
+            ("a/**/b", "a/x/y/b", true),
+            ("a/**/b", "ab/b", false),
+            // `**` elsewhere spans components
+            ("a/**", "a/x/y", true),
+            ("a/b**.txt", "a/bc/d.txt", true),
+            ("a**.txt", "ab/c.txt", false),
+            // classes, ranges and negation, never matching `/`
+            ("*.[tT][xX][tT]", "notes.TxT", true),
+            ("file[0-9].bin", "file7.bin", true),
+            ("file[0-9].bin", "filex.bin", false),
+            ("file[!0-9].bin", "filex.bin", true),
+            ("file[!0-9].bin", "file7.bin", false),
+            ("file[^0-9].bin", "filex.bin", true),
+            ("a[!x]b/c", "a/b/c", false),
+            ("[]]", "]", true),
+            ("[a-]", "-", true),
+        ];
+        for &(pattern, path, expected) in cases {
+            assert_eq!(
+                glob(pattern).matches(path),
+                expected,
+                "'{pattern}' against '{path}'"
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_patterns_are_rejected() {
+        for pattern in ["", "[a-z", "*.[!"] {
+            assert!(pattern.parse::<Glob>().is_err(), "'{pattern}'");
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Torrent creation, inspection and verification behind the `TorrentUtilsR` binary.
+//!
+//! The main entry points are [`Torrent`](torrent::Torrent) for reading and writing
+//! `.torrent` files, [`TrInfo`](tr_info::TrInfo) for the info dictionary with the
+//! walk, hash and verify logic, [`TrFile`](tr_file::TrFile) for one file of the
+//! content, and [`bencode`] for the raw format.
+//!
+//! ```no_run
+//! use torrent_utils::torrent::Torrent;
+//! use torrent_utils::tr_info::VerifyOptions;
+//!
+//! let torrent = Torrent::read_torrent("example.torrent".to_string())?;
+//! let info = torrent.get_info().expect("parsed torrents have an info dictionary");
+//! let options = VerifyOptions {
+//!     n_jobs: 4,
+//!     ..VerifyOptions::default()
+//! };
+//! let (summary, _stats) = info.verify("downloads", &options)?;
+//! println!("{} of {} pieces failed", summary.failed_pieces, summary.total_pieces);
+//! # Ok::<(), torrent_utils::utils::TrError>(())
+//! ```
+//!
+//! The `cli` feature, on by default, pulls in the binary's dependencies. Without it
+//! progress bars are not drawn and interactive prompts are unavailable.
+
+pub mod bencode;
+pub mod crash;
+pub mod datetime;
+pub mod fingerprint;
+pub mod fixture;
+pub mod glob;
+pub mod lint;
+pub mod magnet;
+pub mod net;
+pub mod numa;
+pub mod output;
+pub mod overlap;
+pub mod pause;
+pub mod perf;
+pub mod piece_buffer;
+pub mod piece_cache;
+pub mod report;
+pub mod resources;
+pub mod resume;
+pub mod state;
+pub mod throttle;
+pub mod torrent;
+pub mod tr_file;
+pub mod tr_info;
+pub mod tracker_health;
+pub mod utils;
+pub mod warnings;
+
+/// Piece size of `TorrentBuilder` and `join` unless set, as a power of two (16 MiB). Create
+/// mode picks one from the content size instead, see `tr_info::auto_piece_size`.
+pub const DEF_PIECE_SIZE: u8 = 24;
+/// Smallest accepted piece size, as a power of two (16 KiB).
+pub const MIN_PIECE_SIZE: u8 = 14;
+/// Largest accepted piece size, as a power of two (128 MiB).
+pub const MAX_PIECE_SIZE: u8 = 27;
+
+/// Written to `created by` and sent as the user agent.
+pub const NAME_VERSION: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
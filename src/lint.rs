@@ -0,0 +1,419 @@
+use std::fmt::{Display, Formatter, Result as fmtResult};
+
+use serde::{Deserialize, Serialize};
+
+use crate::torrent::Torrent;
+use crate::tr_info::{SHA1_HASH_SIZE, TrInfo};
+use crate::utils::human_size;
+use crate::{MAX_PIECE_SIZE, MIN_PIECE_SIZE};
+
+pub const DEF_MAX_PATH_LENGTH: usize = 240; // leaves room for a save directory under MAX_PATH (260)
+const MAX_COMPONENT_LENGTH: usize = 255;
+const COMPAT_MAX_PIECE_LENGTH: usize = 16 << 20; // uTorrent and several trackers reject larger pieces
+const COMPAT_MAX_PIECES: usize = 1 << 21; // libtorrent's default load limit
+const TRACKER_SIZE_LIMITS: [usize; 2] = [1 << 20, 5 << 19]; // 1 MiB, 2.5 MiB
+
+/// Rule IDs with their default severity. IDs are stable, pipelines refer to them in
+/// `--deny` and `--allow`.
+pub const RULES: [(&str, Severity); 9] = [
+    ("path-component-length", Severity::Error),
+    ("path-length", Severity::Error),
+    ("path-depth", Severity::Error),
+    ("piece-size-compat", Severity::Warning),
+    ("piece-count-compat", Severity::Warning),
+    ("torrent-size", Severity::Warning),
+    ("missing-source-tag", Severity::Warning),
+    ("length-and-files", Severity::Warning),
+    ("tracker-policy", Severity::Warning),
+];
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Serialize)]
+pub struct Finding {
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(rule: &'static str, message: String) -> Self {
+        let severity = RULES
+            .iter()
+            .find(|(id, _)| *id == rule)
+            .map_or(Severity::Warning, |(_, severity)| *severity);
+        Finding {
+            rule,
+            severity,
+            message,
+        }
+    }
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        write!(f, "{}: {}", self.rule, self.message)
+    }
+}
+
+/// Rules dropped by `--allow` and raised to errors by `--deny`.
+pub struct Policy {
+    pub deny: Vec<String>,
+    pub allow: Vec<String>,
+}
+
+impl Policy {
+    pub fn validate(&self) -> Result<(), String> {
+        match self
+            .deny
+            .iter()
+            .chain(&self.allow)
+            .find(|rule| !RULES.iter().any(|(id, _)| id == rule))
+        {
+            Some(rule) => Err(format!("unknown lint rule '{rule}'")),
+            None => Ok(()),
+        }
+    }
+
+    pub fn apply(&self, findings: Vec<Finding>) -> Vec<Finding> {
+        findings
+            .into_iter()
+            .filter(|finding| !self.allow.iter().any(|rule| rule == finding.rule))
+            .map(|mut finding| {
+                if self.deny.iter().any(|rule| rule == finding.rule) {
+                    finding.severity = Severity::Error;
+                }
+                finding
+            })
+            .collect()
+    }
+}
+
+pub struct PathLimits {
+    pub max_length: Option<usize>,
+    pub max_depth: Option<usize>,
+}
+
+/// Checks every file path (torrent name included) against the limits, lengths are
+/// counted in UTF-16 units as Windows does. Returns one finding per violation.
+pub fn check_paths(info: &TrInfo, limits: &PathLimits) -> Vec<Finding> {
+    let name = info.name.clone().unwrap_or_default();
+    let paths: Vec<Vec<&str>> = match &info.files {
+        Some(files) => files
+            .iter()
+            .filter(|f| !f.padding)
+            .map(|f| {
+                let mut path = vec![name.as_str()];
+                path.extend(f.path.iter().map(String::as_str));
+                path
+            })
+            .collect(),
+        None => vec![vec![name.as_str()]],
+    };
+
+    let mut findings = Vec::new();
+    for path in paths {
+        let full_path = path.join("/");
+        for component in &path {
+            let len = component.encode_utf16().count();
+            if len > MAX_COMPONENT_LENGTH {
+                findings.push(Finding::new(
+                    "path-component-length",
+                    format!(
+                        "'{full_path}': component '{component}' is {len} characters, limit {MAX_COMPONENT_LENGTH}"
+                    ),
+                ));
+            }
+        }
+        let len = full_path.encode_utf16().count();
+        if let Some(max_length) = limits.max_length
+            && len > max_length
+        {
+            findings.push(Finding::new(
+                "path-length",
+                format!("'{full_path}': path is {len} characters, limit {max_length}"),
+            ));
+        }
+        let depth = path.len() - 1;
+        if let Some(max_depth) = limits.max_depth
+            && depth > max_depth
+        {
+            findings.push(Finding::new(
+                "path-depth",
+                format!("'{full_path}': path is {depth} directories deep, limit {max_depth}"),
+            ));
+        }
+    }
+    findings
+}
+
+/// Checks the estimated .torrent size against common tracker upload limits and suggests
+/// a piece size that fits.
+pub fn check_torrent_size(torrent: &Torrent) -> Vec<Finding> {
+    let (Some(info), Some(estimated)) = (torrent.get_info(), torrent.encoded_len()) else {
+        return Vec::new();
+    };
+    let total_size = torrent.total_size();
+    let meta_len = estimated - info.pieces.len();
+    let mut findings = Vec::new();
+    for limit in TRACKER_SIZE_LIMITS {
+        if estimated <= limit {
+            continue;
+        }
+        let suggestion = (MIN_PIECE_SIZE..=MAX_PIECE_SIZE)
+            .find(|&n| meta_len + total_size.div_ceil(1usize << n) * SHA1_HASH_SIZE <= limit);
+        let message = match suggestion {
+            Some(n) => format!(
+                "Torrent size exceeds {}, a common tracker upload limit; consider piece size {n} [{}]",
+                human_size(limit),
+                human_size(1usize << n)
+            ),
+            None => format!(
+                "Torrent size exceeds {}, a common tracker upload limit",
+                human_size(limit)
+            ),
+        };
+        findings.push(Finding::new("torrent-size", message));
+    }
+    findings
+}
+
+/// Checks piece size and count against what popular clients accept.
+pub fn check_client_compat(torrent: &Torrent) -> Vec<Finding> {
+    let Some(info) = torrent.get_info() else {
+        return Vec::new();
+    };
+    let mut findings = Vec::new();
+    if info.piece_length > COMPAT_MAX_PIECE_LENGTH {
+        findings.push(Finding::new(
+            "piece-size-compat",
+            format!(
+                "Piece size {} exceeds {}, which uTorrent and several trackers reject",
+                human_size(info.piece_length),
+                human_size(COMPAT_MAX_PIECE_LENGTH)
+            ),
+        ));
+    }
+    let piece_count = torrent.piece_count();
+    if piece_count > COMPAT_MAX_PIECES {
+        findings.push(Finding::new(
+            "piece-count-compat",
+            format!(
+                "{piece_count} pieces exceed {COMPAT_MAX_PIECES}, the default limit of libtorrent based clients"
+            ),
+        ));
+    }
+    findings
+}
+
+/// Checks metadata trackers commonly require, and that the info dict describes its
+/// content one way only.
+pub fn check_metadata(info: &TrInfo) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    if info.private && info.source.is_none() {
+        findings.push(Finding::new(
+            "missing-source-tag",
+            String::from(
+                "Private torrent without a source tag, cross-seeding gives the same info-hash",
+            ),
+        ));
+    }
+    if info.files.is_some()
+        && let Some(length) = info.length
+    {
+        findings.push(Finding::new(
+            "length-and-files",
+            format!(
+                "Info dict has both length ({length}) and files, files is used as by libtorrent, clients reading length see a different torrent"
+            ),
+        ));
+    }
+    findings
+}
+
+/// The `tracker_policy` table of the config: collection rules for tracker URLs, applied
+/// by `edit --apply-policy` and reported by `lint`.
+#[derive(Default, Deserialize)]
+pub struct TrackerPolicy {
+    /// Trackers to remove: a full announce URL, or a host (with or without port) to
+    /// remove every URL of.
+    #[serde(default)]
+    pub drop: Vec<String>,
+    /// Query parameters to remove from announce URLs, e.g. `passkey`.
+    #[serde(default)]
+    pub redact: Vec<String>,
+}
+
+impl TrackerPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.drop.is_empty() && self.redact.is_empty()
+    }
+
+    fn drops(&self, url: &str) -> bool {
+        let authority = url
+            .split_once("://")
+            .map_or(url, |(_, rest)| rest)
+            .split(['/', '?'])
+            .next()
+            .unwrap_or_default();
+        let host_port = authority
+            .rsplit_once('@')
+            .map_or(authority, |(_, host)| host);
+        let host = match host_port.rsplit_once(':') {
+            Some((host, port)) if port.bytes().all(|b| b.is_ascii_digit()) => host,
+            _ => host_port,
+        };
+        self.drop
+            .iter()
+            .any(|entry| entry == url || entry == host_port || entry == host)
+    }
+
+    /// `url` without the redacted query parameters, and the names of those removed.
+    fn redact(&self, url: &str) -> (String, Vec<String>) {
+        let Some((base, query)) = url.split_once('?') else {
+            return (url.to_string(), Vec::new());
+        };
+        let mut removed = Vec::new();
+        let kept: Vec<&str> = query
+            .split('&')
+            .filter(|param| {
+                let name = param.split('=').next().unwrap_or_default();
+                let redacted = self.redact.iter().any(|r| r == name);
+                if redacted {
+                    removed.push(name.to_string());
+                }
+                !redacted
+            })
+            .collect();
+        if kept.is_empty() {
+            (base.to_string(), removed)
+        } else {
+            (format!("{base}?{}", kept.join("&")), removed)
+        }
+    }
+
+    /// Applies the policy to announce tiers. Returns the new tiers, without the ones left
+    /// empty, and a finding per dropped or redacted URL.
+    pub fn apply(&self, tiers: Vec<Vec<String>>) -> (Vec<Vec<String>>, Vec<Finding>) {
+        let mut findings = Vec::new();
+        let mut new_tiers = Vec::new();
+        for tier in tiers {
+            let mut new_tier = Vec::new();
+            for url in tier {
+                let (redacted, removed) = self.redact(&url);
+                if self.drops(&url) {
+                    findings.push(Finding::new(
+                        "tracker-policy",
+                        format!("Tracker {redacted} is on the drop list"),
+                    ));
+                    continue;
+                }
+                if !removed.is_empty() {
+                    findings.push(Finding::new(
+                        "tracker-policy",
+                        format!(
+                            "Tracker {redacted} carries parameters to redact: {}",
+                            removed.join(", ")
+                        ),
+                    ));
+                }
+                if !new_tier.contains(&redacted) {
+                    new_tier.push(redacted);
+                }
+            }
+            if !new_tier.is_empty() {
+                new_tiers.push(new_tier);
+            }
+        }
+        (new_tiers, findings)
+    }
+}
+
+/// Checks the trackers of a torrent against the `tracker_policy` of the config.
+pub fn check_trackers(torrent: &Torrent, policy: &TrackerPolicy) -> Vec<Finding> {
+    policy.apply(torrent.trackers()).1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn policy_allows_and_denies_rules() {
+        let policy = Policy {
+            deny: strings(&["torrent-size"]),
+            allow: strings(&["missing-source-tag"]),
+        };
+        assert!(policy.validate().is_ok());
+        let findings = policy.apply(vec![
+            Finding::new("missing-source-tag", String::new()),
+            Finding::new("torrent-size", String::new()),
+            Finding::new("path-depth", String::new()),
+        ]);
+        let kept: Vec<(&str, Severity)> = findings.iter().map(|f| (f.rule, f.severity)).collect();
+        assert!(
+            kept == [
+                ("torrent-size", Severity::Error),
+                ("path-depth", Severity::Error)
+            ]
+        );
+
+        let unknown = Policy {
+            deny: Vec::new(),
+            allow: strings(&["no-such-rule"]),
+        };
+        assert_eq!(
+            unknown.validate(),
+            Err(String::from("unknown lint rule 'no-such-rule'"))
+        );
+    }
+
+    #[test]
+    fn tracker_policy_drops_and_redacts() {
+        let policy = TrackerPolicy {
+            drop: strings(&["bad.example", "other.example:8080", "http://t/announce"]),
+            redact: strings(&["passkey"]),
+        };
+        let tiers = vec![
+            strings(&[
+                "http://bad.example:6969/announce",
+                "udp://user@other.example:8080",
+                "http://other.example:9090/a",
+            ]),
+            strings(&["http://t/announce"]),
+            strings(&[
+                "http://p/a?passkey=1&x=2",
+                "http://p/a?x=2",
+                "http://q/a?passkey=3",
+            ]),
+        ];
+        let (tiers, findings) = policy.apply(tiers);
+        assert_eq!(
+            tiers,
+            [
+                strings(&["http://other.example:9090/a"]),
+                strings(&["http://p/a?x=2", "http://q/a"]),
+            ]
+        );
+        let messages: Vec<String> = findings.iter().map(|f| f.message.clone()).collect();
+        assert_eq!(
+            messages,
+            [
+                "Tracker http://bad.example:6969/announce is on the drop list",
+                "Tracker udp://user@other.example:8080 is on the drop list",
+                "Tracker http://t/announce is on the drop list",
+                "Tracker http://p/a?x=2 carries parameters to redact: passkey",
+                "Tracker http://q/a carries parameters to redact: passkey",
+            ]
+        );
+    }
+}
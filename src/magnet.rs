@@ -0,0 +1,678 @@
+use std::collections::HashSet;
+use std::io::{Error as ioError, Read, Result as ioResult, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use sha1::{Digest, Sha1};
+
+use crate::bencode::{Bencode, parse_bencode, write_bytes};
+use crate::net;
+use crate::tr_info::SHA1_HASH_SIZE;
+use crate::utils::{TrError, TrResult, base32_decode, url_decode, url_encode_bytes};
+use crate::warnings::{WarningKind, warn};
+
+pub type InfoHash = [u8; SHA1_HASH_SIZE];
+
+/// Metadata is exchanged in pieces of 16 KiB (BEP 9).
+const METADATA_PIECE: usize = 16384;
+/// Far above the info dict of any real torrent, bounds what a peer can make us allocate.
+const MAX_METADATA_SIZE: usize = 64 << 20;
+/// Large enough for the bitfield of a torrent with millions of pieces.
+const MAX_MESSAGE: usize = 1 << 20;
+/// Extension message ID we ask peers to use for `ut_metadata`.
+const UT_METADATA_ID: u8 = 1;
+const EXTENDED: u8 = 20;
+const PARALLEL_PEERS: usize = 8;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+const PEER_TIMEOUT: Duration = Duration::from_secs(20);
+const UDP_TIMEOUT: Duration = Duration::from_secs(5);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(15);
+const DHT_ROUTERS: [&str; 3] = [
+    "router.bittorrent.com:6881",
+    "dht.transmissionbt.com:6881",
+    "router.utorrent.com:6881",
+];
+const DHT_LOOKUP: Duration = Duration::from_secs(30);
+const DHT_WANTED_PEERS: usize = 100;
+/// Announced port; nothing listens on it, peers are only ever dialed.
+const PORT: u16 = 6881;
+
+/// The parts of a `magnet:` URI needed to find the torrent's metadata.
+pub struct Magnet {
+    pub info_hash: InfoHash,
+    pub name: Option<String>,
+    pub trackers: Vec<String>,
+    pub peers: Vec<String>,
+}
+
+impl FromStr for Magnet {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let query = s
+            .strip_prefix("magnet:?")
+            .ok_or_else(|| format!("'{s}' is not a magnet link"))?;
+        let mut info_hash = None;
+        let mut name = None;
+        let mut trackers = Vec::new();
+        let mut peers = Vec::new();
+        for param in query.split('&') {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let value = url_decode(value);
+            match key {
+                "xt" => {
+                    if let Some(hash) = value.strip_prefix("urn:btih:") {
+                        info_hash = Some(parse_btih(hash)?);
+                    }
+                }
+                "dn" => name = Some(value),
+                "tr" => trackers.push(value),
+                "x.pe" => peers.push(value),
+                _ => {}
+            }
+        }
+        let info_hash = info_hash.ok_or_else(|| {
+            String::from(
+                "magnet link has no urn:btih: info-hash, v2-only magnets are not supported",
+            )
+        })?;
+        Ok(Magnet {
+            info_hash,
+            name,
+            trackers,
+            peers,
+        })
+    }
+}
+
+/// Info-hash as 40 hex digits or, in older magnets, 32 base32 characters.
+fn parse_btih(s: &str) -> Result<InfoHash, String> {
+    let mut hash = [0u8; SHA1_HASH_SIZE];
+    match s.len() {
+        40 => hex::decode_to_slice(s, &mut hash).map_err(|_| format!("invalid info-hash '{s}'"))?,
+        32 => hash.copy_from_slice(
+            &base32_decode(s).ok_or_else(|| format!("invalid base32 info-hash '{s}'"))?,
+        ),
+        _ => return Err(format!("invalid info-hash '{s}', expected 40 hex digits")),
+    }
+    Ok(hash)
+}
+
+/// Azureus-style peer ID: client and version, then bytes differing per run.
+pub fn peer_id() -> InfoHash {
+    let version: String = env!("CARGO_PKG_VERSION")
+        .chars()
+        .filter(char::is_ascii_digit)
+        .chain("0000".chars())
+        .take(4)
+        .collect();
+    let seed = format!(
+        "{:?}{}",
+        SystemTime::now().duration_since(SystemTime::UNIX_EPOCH),
+        std::process::id()
+    );
+    let mut id = [0u8; SHA1_HASH_SIZE];
+    id.copy_from_slice(&Sha1::digest(seed.as_bytes()));
+    id[..8].copy_from_slice(format!("-TU{version}-").as_bytes());
+    id
+}
+
+/// Finds peers through the magnet's peer addresses and trackers, then the DHT, and asks
+/// them for the info dict (BEP 9) until one hands over bytes hashing to the info-hash.
+/// Returns the bencoded info dict.
+pub fn fetch_metadata(magnet: &Magnet, timeout: Duration, quiet: bool) -> TrResult<Vec<u8>> {
+    if net::any_proxy().is_some() && !net::tcp_proxied() {
+        return Err(network_error(
+            "an HTTP proxy only carries HTTP(S) requests, use a SOCKS5 one to reach peers",
+        ));
+    }
+    let deadline = Instant::now() + timeout;
+    let peer_id = peer_id();
+    let mut tried = HashSet::new();
+
+    let mut peers: Vec<SocketAddr> = magnet
+        .peers
+        .iter()
+        .filter_map(|peer| peer.to_socket_addrs().ok())
+        .flatten()
+        .collect();
+    for tracker in &magnet.trackers {
+        match announce(tracker, &magnet.info_hash, &peer_id) {
+            Ok(found) => {
+                if !quiet {
+                    eprintln!("I: {} peers from {tracker}.", found.len());
+                }
+                peers.extend(found);
+            }
+            Err(e) => warn(WarningKind::Network, format!("Tracker {tracker}: {e}")),
+        }
+    }
+    if let Some(info) = try_peers(&peers, &mut tried, &magnet.info_hash, &peer_id, deadline) {
+        return Ok(info);
+    }
+
+    if net::any_proxy().is_some() {
+        return Err(network_error(&format!(
+            "no peer sent the metadata ({} peers tried), the DHT cannot be used through the proxy",
+            tried.len()
+        )));
+    }
+    if !quiet {
+        eprintln!("I: Looking up peers in the DHT.");
+    }
+    let dht_deadline = deadline.min(Instant::now() + DHT_LOOKUP);
+    let peers = dht_peers(&magnet.info_hash, &peer_id, dht_deadline)?;
+    if !quiet {
+        eprintln!("I: {} peers from the DHT.", peers.len());
+    }
+    try_peers(&peers, &mut tried, &magnet.info_hash, &peer_id, deadline).ok_or_else(|| {
+        ioError::other(format!(
+            "no peer sent the metadata within {}s ({} peers tried)",
+            timeout.as_secs(),
+            tried.len()
+        ))
+        .into()
+    })
+}
+
+/// Asks up to `PARALLEL_PEERS` peers at a time, each peer at most once per run.
+fn try_peers(
+    peers: &[SocketAddr],
+    tried: &mut HashSet<SocketAddr>,
+    info_hash: &InfoHash,
+    peer_id: &InfoHash,
+    deadline: Instant,
+) -> Option<Vec<u8>> {
+    let peers: Vec<SocketAddr> = peers
+        .iter()
+        .filter(|p| tried.insert(**p))
+        .copied()
+        .collect();
+    let next = AtomicUsize::new(0);
+    let found: Mutex<Option<Vec<u8>>> = Mutex::new(None);
+    let done = || found.lock().unwrap_or_else(|e| e.into_inner()).is_some();
+    thread::scope(|s| {
+        for _ in 0..PARALLEL_PEERS.min(peers.len()) {
+            s.spawn(|| {
+                while !done() && Instant::now() < deadline {
+                    let Some(peer) = peers.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        break;
+                    };
+                    if let Ok(info) = fetch_from_peer(*peer, info_hash, peer_id, deadline) {
+                        found
+                            .lock()
+                            .unwrap_or_else(|e| e.into_inner())
+                            .get_or_insert(info);
+                    }
+                }
+            });
+        }
+    });
+    found.into_inner().unwrap_or_else(|e| e.into_inner())
+}
+
+fn network_error(msg: &str) -> TrError {
+    ioError::other(msg.to_string()).into()
+}
+
+/// Handshakes with the extension protocol (BEP 10), then requests every metadata piece.
+fn fetch_from_peer(
+    addr: SocketAddr,
+    info_hash: &InfoHash,
+    peer_id: &InfoHash,
+    deadline: Instant,
+) -> TrResult<Vec<u8>> {
+    let deadline = deadline.min(Instant::now() + PEER_TIMEOUT);
+    let mut stream = net::tcp_connect(addr, net::timeout(CONNECT_TIMEOUT))?;
+    stream.set_read_timeout(Some(PEER_TIMEOUT))?;
+    stream.set_write_timeout(Some(PEER_TIMEOUT))?;
+
+    let mut handshake = Vec::with_capacity(68);
+    handshake.push(19);
+    handshake.extend_from_slice(b"BitTorrent protocol");
+    // reserved bit 20 from the right: extension protocol
+    handshake.extend_from_slice(&[0, 0, 0, 0, 0, 0x10, 0, 0]);
+    handshake.extend_from_slice(info_hash);
+    handshake.extend_from_slice(peer_id);
+    stream.write_all(&handshake)?;
+    let mut reply = [0u8; 68];
+    stream.read_exact(&mut reply)?;
+    if reply[..20] != handshake[..20] {
+        return Err(network_error("not a BitTorrent peer"));
+    }
+    if reply[28..48] != info_hash[..] {
+        return Err(network_error("peer serves another torrent"));
+    }
+    if reply[25] & 0x10 == 0 {
+        return Err(network_error(
+            "peer does not support the extension protocol",
+        ));
+    }
+    send_extended(&mut stream, 0, b"d1:md11:ut_metadatai1eee")?;
+
+    let mut metadata = Vec::new();
+    let mut received: Vec<bool> = Vec::new();
+    while Instant::now() < deadline {
+        let msg = read_message(&mut stream)?;
+        if msg.len() < 2 || msg[0] != EXTENDED {
+            continue;
+        }
+        let payload = &msg[2..];
+        let mut pos = 0;
+        let Bencode::Dict(dict) = parse_bencode(payload, &mut pos)? else {
+            continue;
+        };
+        match msg[1] {
+            0 => {
+                let their_id = match dict.get("m") {
+                    Some(Bencode::Dict(m)) => match m.get("ut_metadata") {
+                        Some(Bencode::Int(id)) => u8::try_from(*id).ok().filter(|&id| id > 0),
+                        _ => None,
+                    },
+                    _ => None,
+                }
+                .ok_or_else(|| network_error("peer does not serve metadata"))?;
+                let size = match dict.get("metadata_size") {
+                    Some(Bencode::Int(size)) if (1..=MAX_METADATA_SIZE).contains(size) => *size,
+                    _ => return Err(network_error("peer sent no usable metadata size")),
+                };
+                metadata = vec![0; size];
+                received = vec![false; size.div_ceil(METADATA_PIECE)];
+                for piece in 0..received.len() {
+                    let request = format!("d8:msg_typei0e5:piecei{piece}ee");
+                    send_extended(&mut stream, their_id, request.as_bytes())?;
+                }
+            }
+            UT_METADATA_ID => {
+                let piece = match dict.get("piece") {
+                    Some(Bencode::Int(piece)) if *piece < received.len() => *piece,
+                    _ => continue,
+                };
+                match dict.get("msg_type") {
+                    Some(Bencode::Int(1)) => {
+                        let start = piece * METADATA_PIECE;
+                        let end = (start + METADATA_PIECE).min(metadata.len());
+                        let data = &payload[pos..];
+                        if data.len() != end - start {
+                            return Err(network_error("peer sent a metadata piece of wrong size"));
+                        }
+                        metadata[start..end].copy_from_slice(data);
+                        received[piece] = true;
+                        if received.iter().all(|&r| r) {
+                            if Sha1::digest(&metadata)[..] != info_hash[..] {
+                                return Err(network_error("peer sent metadata of another torrent"));
+                            }
+                            return Ok(metadata);
+                        }
+                    }
+                    Some(Bencode::Int(2)) => {
+                        return Err(network_error("peer rejected the metadata request"));
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    Err(network_error("peer timed out"))
+}
+
+fn read_message(stream: &mut TcpStream) -> TrResult<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len)?;
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_MESSAGE {
+        return Err(network_error("peer sent an oversized message"));
+    }
+    let mut msg = vec![0; len];
+    stream.read_exact(&mut msg)?;
+    Ok(msg)
+}
+
+fn send_extended(stream: &mut TcpStream, id: u8, payload: &[u8]) -> ioResult<()> {
+    let mut msg = Vec::with_capacity(payload.len() + 6);
+    msg.extend_from_slice(&(payload.len() as u32 + 2).to_be_bytes());
+    msg.push(EXTENDED);
+    msg.push(id);
+    msg.extend_from_slice(payload);
+    stream.write_all(&msg)
+}
+
+/// Peers from an HTTP(S) (BEP 3) or UDP (BEP 15) tracker. A `failure reason` from the
+/// tracker is returned as `TrError::TrackerRefused`.
+pub fn announce(
+    tracker: &str,
+    info_hash: &InfoHash,
+    peer_id: &InfoHash,
+) -> TrResult<Vec<SocketAddr>> {
+    announce_event(tracker, info_hash, peer_id, false)
+}
+
+/// Sends `event=stopped` for `peer_id`, so the tracker drops the peer an earlier
+/// `announce` registered instead of listing it until it times out.
+pub fn announce_stopped(tracker: &str, info_hash: &InfoHash, peer_id: &InfoHash) -> TrResult<()> {
+    announce_event(tracker, info_hash, peer_id, true).map(|_| ())
+}
+
+fn announce_event(
+    tracker: &str,
+    info_hash: &InfoHash,
+    peer_id: &InfoHash,
+    stopped: bool,
+) -> TrResult<Vec<SocketAddr>> {
+    if is_onion(tracker) && !net::tcp_proxied() {
+        return Err(network_error(
+            "onion trackers need a SOCKS5 proxy such as Tor, e.g. --proxy socks5h://127.0.0.1:9050",
+        ));
+    }
+    if let Some(rest) = tracker.strip_prefix("udp://") {
+        let host = rest.split('/').next().unwrap_or(rest);
+        udp_announce(host, info_hash, peer_id, stopped)
+    } else if tracker.starts_with("http://") || tracker.starts_with("https://") {
+        http_announce(tracker, info_hash, peer_id, stopped)
+    } else {
+        Err(network_error("unsupported tracker protocol"))
+    }
+}
+
+fn is_onion(tracker: &str) -> bool {
+    let host = tracker.split("://").nth(1).unwrap_or(tracker);
+    let host = host.split(['/', '?']).next().unwrap_or(host);
+    let host = host.rsplit_once(':').map_or(host, |(host, _)| host);
+    host.ends_with(".onion")
+}
+
+/// Announces with the system `curl`, as `self-update` downloads, so no TLS stack is built in.
+fn http_announce(
+    tracker: &str,
+    info_hash: &InfoHash,
+    peer_id: &InfoHash,
+    stopped: bool,
+) -> TrResult<Vec<SocketAddr>> {
+    let separator = if tracker.contains('?') { '&' } else { '?' };
+    // left=1: trackers leave seeds out of the peers they give to seeds
+    let url = format!(
+        "{tracker}{separator}info_hash={}&peer_id={}&port={PORT}&uploaded=0&downloaded=0&left=1&compact=1&numwant=200{}",
+        url_encode_bytes(info_hash),
+        url_encode_bytes(peer_id),
+        if stopped { "&event=stopped" } else { "" }
+    );
+    let max_time = net::timeout(HTTP_TIMEOUT).as_secs_f64().to_string();
+    let output = net::curl()
+        .args(["--proto", "=http,https", "--max-time", &max_time, &url])
+        .output()
+        .map_err(|e| ioError::other(format!("Failed to run curl: {e}")))?;
+    if !output.status.success() {
+        return Err(
+            ioError::other(String::from_utf8_lossy(&output.stderr).trim().to_string()).into(),
+        );
+    }
+    let mut pos = 0;
+    let Bencode::Dict(response) = parse_bencode(&output.stdout, &mut pos)? else {
+        return Err(network_error("tracker response is not a dictionary"));
+    };
+    if let Some(Bencode::Bytes(reason)) = response.get("failure reason") {
+        return Err(TrError::TrackerRefused(
+            String::from_utf8_lossy(reason).into_owned(),
+        ));
+    }
+    let mut peers = Vec::new();
+    match response.get("peers") {
+        Some(Bencode::Bytes(compact)) => peers.extend(compact_peers(compact, false)),
+        Some(Bencode::List(list)) => {
+            for peer in list {
+                let Bencode::Dict(peer) = peer else {
+                    continue;
+                };
+                if let (Some(Bencode::Bytes(ip)), Some(Bencode::Int(port))) =
+                    (peer.get("ip"), peer.get("port"))
+                    && let (Ok(ip), Ok(port)) = (
+                        String::from_utf8_lossy(ip).parse::<IpAddr>(),
+                        u16::try_from(*port),
+                    )
+                {
+                    peers.push(SocketAddr::new(ip, port));
+                }
+            }
+        }
+        _ => {}
+    }
+    if let Some(Bencode::Bytes(compact)) = response.get("peers6") {
+        peers.extend(compact_peers(compact, true));
+    }
+    Ok(peers)
+}
+
+fn udp_announce(
+    host: &str,
+    info_hash: &InfoHash,
+    peer_id: &InfoHash,
+    stopped: bool,
+) -> TrResult<Vec<SocketAddr>> {
+    if net::any_proxy().is_some() {
+        return Err(network_error(
+            "not announced, the proxy only carries HTTP(S) trackers",
+        ));
+    }
+    let addr = host
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| network_error("tracker host does not resolve"))?;
+    let socket = UdpSocket::bind(if addr.is_ipv6() {
+        "[::]:0"
+    } else {
+        "0.0.0.0:0"
+    })?;
+    socket.set_read_timeout(Some(net::timeout(UDP_TIMEOUT)))?;
+    socket.connect(addr)?;
+    let transaction = &peer_id[16..20];
+
+    let mut request = Vec::with_capacity(98);
+    request.extend_from_slice(&0x41727101980u64.to_be_bytes());
+    request.extend_from_slice(&0u32.to_be_bytes());
+    request.extend_from_slice(transaction);
+    socket.send(&request)?;
+    let mut buf = [0u8; 2048];
+    let n = socket.recv(&mut buf)?;
+    if n < 16 || buf[..4] != 0u32.to_be_bytes() || &buf[4..8] != transaction {
+        return Err(network_error("invalid connect response"));
+    }
+    let connection_id = &buf[8..16];
+
+    request.clear();
+    request.extend_from_slice(connection_id);
+    request.extend_from_slice(&1u32.to_be_bytes());
+    request.extend_from_slice(transaction);
+    request.extend_from_slice(info_hash);
+    request.extend_from_slice(peer_id);
+    request.extend_from_slice(&0u64.to_be_bytes()); // downloaded
+    request.extend_from_slice(&1u64.to_be_bytes()); // left
+    request.extend_from_slice(&0u64.to_be_bytes()); // uploaded
+    request.extend_from_slice(&if stopped { 3u32 } else { 0 }.to_be_bytes()); // event: stopped or none
+    request.extend_from_slice(&0u32.to_be_bytes()); // IP: sender's
+    request.extend_from_slice(transaction); // key
+    request.extend_from_slice(&(-1i32).to_be_bytes()); // numwant: default
+    request.extend_from_slice(&PORT.to_be_bytes());
+    socket.send(&request)?;
+    let n = socket.recv(&mut buf)?;
+    if n >= 8 && buf[..4] == 3u32.to_be_bytes() {
+        return Err(TrError::TrackerRefused(
+            String::from_utf8_lossy(&buf[8..n]).into_owned(),
+        ));
+    }
+    if n < 20 || buf[..4] != 1u32.to_be_bytes() || &buf[4..8] != transaction {
+        return Err(network_error("invalid announce response"));
+    }
+    Ok(compact_peers(&buf[20..n], addr.is_ipv6()))
+}
+
+/// Peers packed as 4 or 16 address bytes followed by a big-endian port.
+fn compact_peers(bytes: &[u8], ipv6: bool) -> Vec<SocketAddr> {
+    let size = if ipv6 { 18 } else { 6 };
+    bytes
+        .chunks_exact(size)
+        .filter_map(|chunk| {
+            let (ip, port) = chunk.split_at(size - 2);
+            let ip = if ipv6 {
+                IpAddr::from(<[u8; 16]>::try_from(ip).ok()?)
+            } else {
+                IpAddr::from(<[u8; 4]>::try_from(ip).ok()?)
+            };
+            Some(SocketAddr::new(ip, u16::from_be_bytes([port[0], port[1]])))
+        })
+        .collect()
+}
+
+/// Iterative `get_peers` lookup (BEP 5), querying the nodes closest to the info-hash
+/// first, until enough peers are found, no closer nodes are left or `deadline` passes.
+fn dht_peers(
+    info_hash: &InfoHash,
+    node_id: &InfoHash,
+    deadline: Instant,
+) -> TrResult<Vec<SocketAddr>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(Duration::from_millis(500)))?;
+    let mut query = Vec::new();
+    query.extend_from_slice(b"d1:ad2:id");
+    write_bytes(&mut query, node_id)?;
+    query.extend_from_slice(b"9:info_hash");
+    write_bytes(&mut query, info_hash)?;
+    query.extend_from_slice(b"e1:q9:get_peers1:t2:tu1:y1:qe");
+
+    let distance = |id: &InfoHash| -> InfoHash {
+        let mut d = [0u8; SHA1_HASH_SIZE];
+        for (i, byte) in d.iter_mut().enumerate() {
+            *byte = id[i] ^ info_hash[i];
+        }
+        d
+    };
+    // routers have no known ID, they sort first with distance zero
+    let mut nodes: Vec<(InfoHash, SocketAddr)> = DHT_ROUTERS
+        .iter()
+        .filter_map(|router| router.to_socket_addrs().ok())
+        .flatten()
+        .filter(SocketAddr::is_ipv4)
+        .map(|addr| ([0u8; SHA1_HASH_SIZE], addr))
+        .collect();
+    let mut queried = HashSet::new();
+    let mut peers = HashSet::new();
+    let mut buf = [0u8; 4096];
+    while Instant::now() < deadline && peers.len() < DHT_WANTED_PEERS {
+        nodes.sort_by_key(|(id, _)| distance(id));
+        let batch: Vec<SocketAddr> = nodes
+            .iter()
+            .map(|(_, addr)| *addr)
+            .filter(|addr| !queried.contains(addr))
+            .take(PARALLEL_PEERS)
+            .collect();
+        if batch.is_empty() {
+            break;
+        }
+        for addr in &batch {
+            queried.insert(*addr);
+            let _ = socket.send_to(&query, addr);
+        }
+        let round_end = Instant::now() + Duration::from_secs(1);
+        while Instant::now() < round_end.min(deadline) {
+            let Ok((n, _)) = socket.recv_from(&mut buf) else {
+                continue;
+            };
+            let mut pos = 0;
+            let Ok(Bencode::Dict(response)) = parse_bencode(&buf[..n], &mut pos) else {
+                continue;
+            };
+            let Some(Bencode::Dict(r)) = response.get("r") else {
+                continue;
+            };
+            if let Some(Bencode::List(values)) = r.get("values") {
+                for value in values {
+                    if let Bencode::Bytes(compact) = value {
+                        peers.extend(compact_peers(compact, false));
+                    }
+                }
+            }
+            if let Some(Bencode::Bytes(compact)) = r.get("nodes") {
+                for node in compact.chunks_exact(26) {
+                    let (id, addr) = node.split_at(SHA1_HASH_SIZE);
+                    let (Ok(id), Some(addr)) =
+                        (InfoHash::try_from(id), compact_peers(addr, false).pop())
+                    else {
+                        continue;
+                    };
+                    if !queried.contains(&addr) {
+                        nodes.push((id, addr));
+                    }
+                }
+            }
+        }
+        nodes.retain(|(_, addr)| !queried.contains(addr));
+    }
+    Ok(peers.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH_HEX: &str = "0102030405060708090a0b0c0d0e0f1011121314";
+    const HASH_BASE32: &str = "AEBAGBAFAYDQQCIKBMGA2DQPCAIREEYU";
+
+    fn hash() -> InfoHash {
+        std::array::from_fn(|i| i as u8 + 1)
+    }
+
+    #[test]
+    fn btih_is_hex_or_base32() {
+        assert_eq!(parse_btih(HASH_HEX), Ok(hash()));
+        assert_eq!(parse_btih(&HASH_HEX.to_uppercase()), Ok(hash()));
+        assert_eq!(parse_btih(HASH_BASE32), Ok(hash()));
+        assert_eq!(parse_btih(&HASH_BASE32.to_lowercase()), Ok(hash()));
+        assert!(parse_btih(&HASH_HEX[..38]).is_err());
+        assert!(parse_btih(&"g".repeat(40)).is_err());
+        assert!(parse_btih(&"1".repeat(32)).is_err());
+    }
+
+    #[test]
+    fn magnet_params_are_decoded() {
+        let uri = format!(
+            "magnet:?xt=urn:btih:{HASH_HEX}&dn=My%20Data&tr=http%3A%2F%2Ft%2Fa\
+             &tr=udp://u:1&x.pe=10.0.0.1:6881&xt=urn:btmh:1220ab&so=0"
+        );
+        let magnet: Magnet = uri.parse().unwrap();
+        assert_eq!(magnet.info_hash, hash());
+        assert_eq!(magnet.name.as_deref(), Some("My Data"));
+        assert_eq!(magnet.trackers, ["http://t/a", "udp://u:1"]);
+        assert_eq!(magnet.peers, ["10.0.0.1:6881"]);
+    }
+
+    #[test]
+    fn magnets_need_a_btih() {
+        assert!("http://example/".parse::<Magnet>().is_err());
+        assert!("magnet:?xt=urn:btmh:1220ab&dn=x".parse::<Magnet>().is_err());
+        assert!("magnet:?xt=urn:btih:xyz".parse::<Magnet>().is_err());
+    }
+
+    #[test]
+    fn compact_peers_unpack_addresses_and_ports() {
+        let v4 = [10, 0, 0, 1, 0x1a, 0xe1, 192, 168, 1, 2, 0, 80, 1];
+        assert_eq!(
+            compact_peers(&v4, false),
+            [
+                "10.0.0.1:6881".parse::<SocketAddr>().unwrap(),
+                "192.168.1.2:80".parse().unwrap()
+            ]
+        );
+        let mut v6 = [0u8; 18];
+        v6[15] = 1;
+        v6[16..].copy_from_slice(&443u16.to_be_bytes());
+        assert_eq!(
+            compact_peers(&v6, true),
+            ["[::1]:443".parse::<SocketAddr>().unwrap()]
+        );
+        assert!(compact_peers(&v4[..5], false).is_empty());
+    }
+}
@@ -1,25 +1,70 @@
-use std::io::{Write, stdin, stdout};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs::{read_to_string, write};
+use std::io::{BufRead, IsTerminal, Write, stdin, stdout};
 use std::path::{MAIN_SEPARATOR, Path, PathBuf};
-use std::process::exit;
+use std::process::{Command, Stdio, exit};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::thread;
+use std::time::{Duration, Instant};
 
 use argh::FromArgs;
-use serde::Deserialize;
+use chrono::TimeZone;
+use dialoguer::MultiSelect;
+use serde::{Deserialize, Deserializer};
+use walkdir::WalkDir;
 
-mod bencode;
-mod torrent;
-mod tr_file;
-mod tr_info;
-mod utils;
+mod commands;
+#[cfg(feature = "self-update")]
+mod self_update;
+mod wizard;
 
-use torrent::Torrent;
-use tr_info::WalkMode;
+use torrent_utils::{
+    DEF_PIECE_SIZE, MAX_PIECE_SIZE, MIN_PIECE_SIZE, NAME_VERSION, bencode, crash, datetime,
+    fingerprint, fixture, glob, lint, magnet, net, numa, output, overlap, pause, perf, piece_cache,
+    report, resume, state, throttle, torrent, tr_file, tr_info, tracker_health, utils, warnings,
+};
+
+use bencode::ExtraValue;
+use commands::SubCommand;
+use glob::Glob;
+use lint::{PathLimits, TrackerPolicy, check_paths};
+use output::{Format, OutputFormat};
+use pause::PauseListener;
+use perf::PerfStats;
+use report::{VerifyReport, VerifyState};
+use resume::ResumeData;
+use throttle::AutoYield;
+use torrent::{KNOWN_ROOT_KEYS, Torrent};
+use tr_info::{
+    KNOWN_INFO_KEYS, LinkPolicy, MetaVersion, OfflinePolicy, SortLocale, VerifyOptions,
+    VerifyOrder, WalkMode,
+};
 
 use crate::tr_info::TrConfig;
+use crate::utils::{Progress, ProgressMode, TrError, TrResult};
+use crate::warnings::{WarningKind, warn};
 
-const DEF_PIECE_SIZE: u8 = 24; // 1 << 24 = 16777216 bytes = 16 MiB
+const RESOURCE_REPORT_INTERVAL: Duration = Duration::from_secs(10); // --verbose
 
-const NAME_VERSION: &str = concat!(env!("CARGO_PKG_NAME"), " ", env!("CARGO_PKG_VERSION"));
+/// A `tracker_list` entry, a plain URL or a list of URLs forming one tier.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TrackerEntry {
+    Url(String),
+    Tier(Vec<String>),
+}
+
+/// How plain URLs of `tracker_list` are grouped into announce-list tiers (BEP 12).
+#[derive(Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum TrackerTiers {
+    /// each URL is its own tier, clients announce to all of them
+    #[default]
+    Separate,
+    /// all plain URLs share one tier, clients use the first that responds
+    Single,
+}
 
 #[derive(Deserialize)]
 struct Config {
@@ -29,40 +74,129 @@ struct Config {
     #[serde(default = "default_n_jobs")]
     n_jobs: usize,
 
+    #[serde(default, deserialize_with = "deserialize_size")]
+    read_buffer: Option<usize>,
+
+    #[serde(default)]
+    piece_cache: Option<String>,
+
+    #[serde(default)]
+    state_dir: Option<String>,
+
     #[serde(default)]
     walk_mode: u8,
 
+    #[serde(default)]
+    sort_locale: Option<SortLocale>,
+
+    #[serde(default)]
+    auto_yield: bool,
+
+    #[serde(default)]
+    max_open_files: Option<usize>,
+
     #[serde(default)]
     private: bool,
 
-    #[serde(default = "def_piece_size")]
-    piece_size: u8,
+    #[serde(default, deserialize_with = "deserialize_piece_size")]
+    piece_size: Option<u8>,
 
     #[serde(default)]
     source: Option<String>,
 
     #[serde(default)]
-    tracker_list: Vec<String>,
-}
+    path_substitutions: Option<HashMap<String, String>>,
+
+    #[serde(default)]
+    tracker_list: Vec<TrackerEntry>,
+
+    #[serde(default)]
+    tracker_tiers: TrackerTiers,
+
+    #[serde(default)]
+    tracker_policy: TrackerPolicy,
+
+    #[serde(default)]
+    skip_junk: bool,
+
+    #[serde(default)]
+    exclude: Vec<String>,
+
+    #[serde(default)]
+    include: Vec<String>,
+
+    #[serde(default)]
+    proxy: Option<String>,
 
-const fn def_piece_size() -> u8 {
-    DEF_PIECE_SIZE
+    #[serde(default)]
+    user_agent: Option<String>,
+
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    network_timeout: Option<Duration>,
 }
 
 const fn default_n_jobs() -> usize {
     1
 }
 
+fn deserialize_size<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| utils::parse_size(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+/// `piece_size` as an exponent (`22`) or a size string (`"4M"`).
+fn deserialize_piece_size<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<u8>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum PieceSize {
+        Exponent(u8),
+        Size(String),
+    }
+    match Option::<PieceSize>::deserialize(deserializer)? {
+        Some(PieceSize::Exponent(n)) => Ok(Some(n)),
+        Some(PieceSize::Size(s)) => utils::parse_piece_size(&s)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Duration>, D::Error> {
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| utils::parse_duration(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
             wait_exit: false,
             n_jobs: 1,
+            read_buffer: None,
+            piece_cache: None,
+            state_dir: None,
             walk_mode: 0,
+            sort_locale: None,
+            auto_yield: false,
+            max_open_files: None,
             private: false,
-            piece_size: DEF_PIECE_SIZE,
+            piece_size: None,
             source: None,
+            path_substitutions: None,
             tracker_list: Vec::new(),
+            tracker_tiers: TrackerTiers::Separate,
+            tracker_policy: TrackerPolicy::default(),
+            skip_junk: false,
+            exclude: Vec::new(),
+            include: Vec::new(),
+            proxy: None,
+            user_agent: None,
+            network_timeout: None,
         }
     }
 }
@@ -79,23 +213,47 @@ struct Args {
     #[argh(option, short = 'g', default = "get_config_path()")]
     config: String,
 
-    /// output path or torrent name (only for create mode)
+    /// output path or torrent name (only for create mode), output directory with --batch-stdin
     #[argh(option, short = 'o')]
     output: Option<String>,
 
-    /// piece size (1 << n, 14..=27), overrides config [default: 24]
-    #[argh(option, short = 'l')]
+    /// piece size, 16K to 128M or an exponent 14..=27 (1 << n), overrides config [default: auto, 1000 to 2000 pieces of up to 16 MiB]
+    #[argh(option, short = 'l', from_str_fn(utils::parse_piece_size))]
     piece_size: Option<u8>,
 
+    /// hashes to write: 1 (SHA-1 pieces), 2 (BEP 52 merkle trees, v2-only clients) or hybrid (both) [default: 1]
+    #[argh(option)]
+    meta_version: Option<MetaVersion>,
+
+    /// pad every file but the last to a piece boundary (BEP 47 padding files), so `update` can reuse the pieces after a changed file
+    #[argh(switch)]
+    pad_files: bool,
+
     /// announce URLs, multiple allowed, overrides config (\"\" to clear)
     #[argh(option, short = 'a')]
     announce: Vec<String>,
 
+    /// web seed URL (BEP 19 url-list), an HTTP(S) mirror of the content, multiple allowed
+    #[argh(option, from_str_fn(utils::parse_http_url))]
+    web_seed: Vec<String>,
+
+    /// HTTP seed URL (BEP 17 httpseeds), a script serving pieces to older clients, multiple allowed
+    #[argh(option, from_str_fn(utils::parse_http_url))]
+    http_seed: Vec<String>,
+
+    /// DHT bootstrap node (BEP 5 nodes) as host:port, for trackerless torrents, multiple allowed
+    #[argh(option, from_str_fn(utils::parse_node))]
+    node: Vec<(String, u16)>,
+
+    /// pick the trackers to use from the configured list in a checklist
+    #[argh(switch)]
+    choose_trackers: bool,
+
     /// private torrent, overrides config
     #[argh(switch, short = 'p')]
     private: bool,
 
-    /// comment
+    /// comment, placeholders {path} {name} {date} {size} {bytes} {files} are filled in
     #[argh(option, short = 'c')]
     comment: Option<String>,
 
@@ -103,22 +261,206 @@ struct Args {
     #[argh(switch, short = 'd')]
     no_date: bool,
 
-    /// torrent source
+    /// creation date instead of now: unix timestamp, RFC 3339 or YYYY-MM-DD[ HH:MM[:SS]]
+    #[argh(option, from_str_fn(datetime::parse_date))]
+    date: Option<i64>,
+
+    /// strftime format for dates in info output, or rfc3339 [default: %Y-%m-%d %H:%M:%S]
+    #[argh(option, from_str_fn(datetime::parse_date_format))]
+    date_format: Option<String>,
+
+    /// source tag written into the info dict, required by many private trackers, overrides config (\"\" for none)
     #[argh(option, short = 's')]
     source: Option<String>,
 
+    /// collection name (BEP 38 `collections`), multiple allowed
+    #[argh(option)]
+    collection: Vec<String>,
+
+    /// extra info dict key as key=value, integers are written as integers, multiple allowed; changes the info-hash
+    #[argh(option, from_str_fn(bencode::parse_extra_key))]
+    info_key: Vec<(String, ExtraValue)>,
+
+    /// extra top-level key as key=value outside the info dict, e.g. publisher, multiple allowed
+    #[argh(option, from_str_fn(bencode::parse_extra_key))]
+    root_key: Vec<(String, ExtraValue)>,
+
     /// walk mode [default: 0]
     #[argh(option, short = 'w')]
     walk_mode: Option<u8>,
 
+    /// name comparator for sorted walk modes and tree printing: c, natural or unicode
+    #[argh(option)]
+    sort_locale: Option<SortLocale>,
+
+    /// fail if the walk mode leaves two files in an ambiguous order
+    #[argh(switch)]
+    stable_order_check: bool,
+
+    /// symlinks and junctions: follow, skip (those outside the target or duplicating another file) or store (as BEP 47 symlink entries, v1 only) [default: follow]
+    #[argh(option)]
+    link_policy: Option<LinkPolicy>,
+
+    /// cloud placeholder files without local data: hydrate (download them before hashing), skip or abort [default: abort]
+    #[argh(option)]
+    offline_files: Option<OfflinePolicy>,
+
+    /// fail if a path, torrent name included, is longer than this
+    #[argh(option)]
+    max_path_length: Option<usize>,
+
+    /// fail if a path, torrent name included, is more directories deep than this
+    #[argh(option)]
+    max_path_depth: Option<usize>,
+
+    /// fail instead of warning when piece size or piece count exceed what popular clients accept
+    #[argh(switch)]
+    strict_compat: bool,
+
+    /// leave all .torrent files inside the target out, the output torrent is always left out
+    #[argh(switch)]
+    exclude_torrents: bool,
+
+    /// leave out OS litter such as .DS_Store, Thumbs.db, desktop.ini, @eaDir and ._* files
+    #[argh(switch)]
+    skip_junk: bool,
+
+    /// leave out files and directories matching this glob, e.g. \"*.nfo\" or \"**/Sample/**\", multiple allowed, added to config
+    #[argh(option)]
+    exclude: Vec<Glob>,
+
+    /// only keep files matching this glob, e.g. \"*.flac\", multiple allowed, added to config, excludes still apply
+    #[argh(option)]
+    include: Vec<Glob>,
+
+    /// leave out files smaller than this, e.g. 1 to skip empty files or 100K
+    #[argh(option, from_str_fn(utils::parse_size))]
+    min_file_size: Option<usize>,
+
+    /// leave out files larger than this, e.g. 4G
+    #[argh(option, from_str_fn(utils::parse_size))]
+    max_file_size: Option<usize>,
+
+    /// create a single-file torrent when the target directory holds exactly one file
+    #[argh(switch)]
+    flatten_single: bool,
+
+    /// write the file order of the created torrent, one relative path per line; with --batch-stdin a directory getting a <torrent name>.order per target
+    #[argh(option)]
+    export_order: Option<String>,
+
+    /// order files as listed in a file written by --export-order, overrides walk mode
+    #[argh(option)]
+    order: Option<String>,
+
     /// force overwrite
     #[argh(switch, short = 'f')]
     force: bool,
 
+    /// read target paths line by line from stdin and create a torrent for each
+    #[argh(switch)]
+    batch_stdin: bool,
+
+    /// number of targets created at once with --batch-stdin, sharing the -j threads [default: 1]
+    #[argh(option)]
+    parallel_jobs: Option<usize>,
+
+    /// enumerate files and estimate the torrent size without hashing (only for create mode)
+    #[argh(switch, short = 'n')]
+    dry_run: bool,
+
     /// number of threads to use (only for verify mode) [default: 1]
     #[argh(option, short = 'j')]
     n_jobs: Option<usize>,
 
+    /// only hash the pieces a client .fastresume file reports complete (only for verify mode)
+    #[argh(option)]
+    trust_resume: Option<String>,
+
+    /// with --trust-resume, hash the pieces reported incomplete instead
+    #[argh(switch)]
+    check_incomplete: bool,
+
+    /// read and hash failed pieces once more before reporting them, for flaky storage (only for verify mode)
+    #[argh(switch)]
+    paranoid: bool,
+
+    /// verify order: torrent, size-desc (largest files first), path or recent-first (last modified files first) [default: torrent]
+    #[argh(option)]
+    verify_order: Option<VerifyOrder>,
+
+    /// stop verifying after this long, e.g. 2h or 1h30m, and save progress to --state (only for verify mode)
+    #[argh(option, from_str_fn(utils::parse_duration))]
+    max_duration: Option<Duration>,
+
+    /// verify progress file, pieces checked in earlier sessions are skipped [default with --max-duration: <info-hash>.verify-state in the state directory]
+    #[argh(option)]
+    state: Option<String>,
+
+    /// write the verify result as JSON to this file
+    #[argh(option)]
+    report: Option<String>,
+
+    /// print the verify result as JSON instead of text, same as --format json (only for verify mode)
+    #[argh(switch)]
+    json: bool,
+
+    /// output format of info, verify, lint and fingerprint results: human, json, csv or markdown [default: human]
+    #[argh(option)]
+    format: Option<Format>,
+
+    /// show files newly failing or recovered since an earlier --report
+    #[argh(option)]
+    compare_with: Option<String>,
+
+    /// per-read chunk size, e.g. 1M or 256K [default: min(piece length, 4M)]
+    #[argh(option, from_str_fn(utils::parse_size))]
+    read_buffer: Option<usize>,
+
+    /// reuse piece hashes of unchanged files from this cache file and update it, default for one in the state directory (only for create mode)
+    #[argh(option)]
+    piece_cache: Option<String>,
+
+    /// directory of verify states, the tracker cache and the default piece cache, overrides config [default: TorrentUtilsR in the user cache directory]
+    #[argh(option)]
+    state_dir: Option<String>,
+
+    /// throttle hashing while other processes use the same disk (Linux only)
+    #[argh(switch)]
+    auto_yield: bool,
+
+    /// pin hashing threads to CPUs spread over the NUMA nodes (Linux, needs the numa feature)
+    #[argh(switch)]
+    pin_threads: bool,
+
+    /// print read throughput per top-level entry, device and slow files
+    #[argh(switch)]
+    perf_summary: bool,
+
+    /// progress display: classic, minimal, dots, or every=30s for plain lines at an interval
+    #[argh(option, from_str_fn(utils::parse_progress))]
+    progress: Option<ProgressMode>,
+
+    /// report open files, memory and read buffers every 10 seconds while hashing
+    #[argh(switch)]
+    verbose: bool,
+
+    /// most content files open at once, lowers the threads reading at once, overrides config
+    #[argh(option)]
+    max_open_files: Option<usize>,
+
+    /// proxy for HTTP(S) requests such as tracker announces, e.g. socks5h://127.0.0.1:9050, overrides config [default: $HTTPS_PROXY]
+    #[argh(option, from_str_fn(net::parse_proxy))]
+    proxy: Option<String>,
+
+    /// user agent for HTTP(S) requests, overrides config
+    #[argh(option)]
+    user_agent: Option<String>,
+
+    /// never write a file: create mode, writing commands and options such as --report are refused
+    #[argh(switch)]
+    assert_readonly: bool,
+
     /// hide progress bar and other non-error output
     #[argh(switch, short = 'q')]
     quiet: bool,
@@ -134,6 +476,9 @@ struct Args {
     /// print version info and exit
     #[argh(switch, short = 'v')]
     version: bool,
+
+    #[argh(subcommand)]
+    command: Option<SubCommand>,
 }
 
 fn get_config_path() -> String {
@@ -142,7 +487,9 @@ fn get_config_path() -> String {
     exe_dir.join("config.toml").to_string_lossy().to_string()
 }
 
+/// Last step before exiting: shows the collected warnings, then waits for Enter if asked.
 fn wait_for_enter(wait: bool) {
+    warnings::print_summary();
     if wait {
         print!("Press Enter to exit...");
         let _ = stdout().flush();
@@ -150,28 +497,726 @@ fn wait_for_enter(wait: bool) {
     }
 }
 
+fn start_auto_yield(enabled: bool, target_path: &str, quiet: bool) -> Option<AutoYield> {
+    if !enabled {
+        return None;
+    }
+    let auto_yield = AutoYield::start(Path::new(target_path), quiet);
+    if auto_yield.is_none() {
+        warn(
+            WarningKind::Config,
+            "--auto-yield is not supported on this platform, ignored.",
+        );
+    }
+    auto_yield
+}
+
+fn check_torrent_size(torrent: &Torrent, quiet: bool) {
+    if !quiet {
+        let total_size = torrent.total_size();
+        let estimated = torrent.encoded_len().unwrap_or_default();
+        eprintln!(
+            "Files: {}, Length: {total_size} bytes [{}]",
+            torrent.files().len(),
+            utils::human_size(total_size)
+        );
+        eprintln!(
+            "Pieces: {}, Estimated Torrent Size: {estimated} bytes [{}]",
+            torrent.piece_count(),
+            utils::human_size(estimated)
+        );
+    }
+    for finding in lint::check_torrent_size(torrent) {
+        warn(WarningKind::Compatibility, finding.to_string());
+    }
+}
+
+/// Warns about layouts popular clients refuse to load, an error with `strict`.
+fn check_client_compat(torrent: &Torrent, strict: bool) -> TrResult<()> {
+    let findings = lint::check_client_compat(torrent);
+    if strict && !findings.is_empty() {
+        let problems: Vec<String> = findings.iter().map(ToString::to_string).collect();
+        return Err(TrError::InvalidTorrent(problems.join("; ")));
+    }
+    for finding in findings {
+        warn(WarningKind::Compatibility, finding.to_string());
+    }
+    Ok(())
+}
+
+/// Tells whether the client's bitfield matched the data for the pieces it selected.
+fn report_bitfield(selected: &[bool], failed: usize, check_incomplete: bool) {
+    let checked = selected.iter().filter(|&&sel| sel).count();
+    if check_incomplete {
+        println!(
+            "Client bitfield: {} of {checked} pieces reported incomplete already pass.",
+            checked - failed
+        );
+    } else if failed == 0 {
+        println!("Client bitfield is honest: all {checked} pieces reported complete pass.");
+    } else {
+        println!(
+            "Client bitfield is wrong: {failed} of {checked} pieces reported complete fail (phantom completion, e.g. from disk errors)."
+        );
+    }
+}
+
+fn build_tr_config(args: &Args, config: &mut Config) -> Result<TrConfig, String> {
+    let piece_size = args.piece_size.or(config.piece_size);
+    if piece_size.is_some_and(|n| !(MIN_PIECE_SIZE..=MAX_PIECE_SIZE).contains(&n)) {
+        return Err(String::from(
+            "Piece size must be between 16K and 128M (exponent 14 to 27).",
+        ));
+    }
+    if let (Some(min), Some(max)) = (args.min_file_size, args.max_file_size)
+        && min > max
+    {
+        return Err(String::from(
+            "--min-file-size must not be larger than --max-file-size.",
+        ));
+    }
+
+    if !args.announce.is_empty() {
+        config.tracker_list = if args.announce.iter().any(|s| s.is_empty()) {
+            Vec::new()
+        } else {
+            args.announce
+                .iter()
+                .cloned()
+                .map(TrackerEntry::Url)
+                .collect()
+        };
+    }
+    if args.choose_trackers {
+        config.tracker_list = choose_trackers(std::mem::take(&mut config.tracker_list))?;
+    }
+    if let Some((key, _)) = args
+        .info_key
+        .iter()
+        .find(|(key, _)| KNOWN_INFO_KEYS.contains(&key.as_str()))
+    {
+        return Err(format!(
+            "--info-key cannot set '{key}', it is written from the target and options."
+        ));
+    }
+    if let Some((key, _)) = args
+        .root_key
+        .iter()
+        .find(|(key, _)| KNOWN_ROOT_KEYS.contains(&key.as_str()))
+    {
+        return Err(format!(
+            "--root-key cannot set '{key}', use the matching option instead."
+        ));
+    }
+    let meta_version = args.meta_version.unwrap_or(MetaVersion::V1);
+    let piece_cache = match args.piece_cache.clone().or(config.piece_cache.clone()) {
+        Some(path) if path == "default" => Some(
+            state::state_file(state::PIECE_CACHE)
+                .map_err(|e| format!("Failed to create the state directory: {e}"))?
+                .to_string_lossy()
+                .to_string(),
+        ),
+        piece_cache => piece_cache,
+    };
+    if meta_version != MetaVersion::V1 && piece_cache.is_some() {
+        warn(
+            WarningKind::Config,
+            "The piece cache holds v1 hashes, ignored with --meta-version 2 or hybrid.",
+        );
+    }
+    if meta_version == MetaVersion::Hybrid
+        && (args.walk_mode.is_some_and(|mode| mode != 0) || args.order.is_some())
+    {
+        warn(
+            WarningKind::Config,
+            "Hybrid torrents list files in file tree order, --walk-mode and --order are ignored.",
+        );
+    }
+    for (key, value) in &args.info_key {
+        warn(
+            WarningKind::Compatibility,
+            format!(
+                "Info key '{key}' = {value} is part of the info-hash, the torrent will not match one created without it."
+            ),
+        );
+    }
+
+    Ok(TrConfig {
+        piece_length: 1usize << piece_size.unwrap_or(DEF_PIECE_SIZE),
+        auto_piece_size: piece_size.is_none(),
+        meta_version,
+        pad_files: args.pad_files,
+        private: args.private || config.private,
+        n_jobs: config.n_jobs,
+        read_buffer: config.read_buffer,
+        piece_cache: piece_cache.filter(|_| meta_version == MetaVersion::V1),
+        walk_mode: match args.walk_mode.unwrap_or(config.walk_mode) {
+            0 => WalkMode::Default,
+            1 => WalkMode::Alphabetical,
+            2 => WalkMode::BreadthFirstAlphabetical,
+            3 => WalkMode::BreadthFirstLevel,
+            4 => WalkMode::FileSize,
+            _ => return Err(String::from("Invalid walk mode.")),
+        },
+        sort_locale: config.sort_locale,
+        stable_order_check: args.stable_order_check,
+        link_policy: args.link_policy.unwrap_or(LinkPolicy::Follow),
+        offline_files: args.offline_files.unwrap_or(OfflinePolicy::Abort),
+        output_path: None,
+        exclude_torrents: args.exclude_torrents,
+        skip_junk: args.skip_junk || config.skip_junk,
+        exclude: config
+            .exclude
+            .iter()
+            .map(|pattern| pattern.parse())
+            .chain(args.exclude.iter().cloned().map(Ok))
+            .collect::<Result<_, _>>()?,
+        include: config
+            .include
+            .iter()
+            .map(|pattern| pattern.parse())
+            .chain(args.include.iter().cloned().map(Ok))
+            .collect::<Result<_, _>>()?,
+        min_file_size: args.min_file_size,
+        max_file_size: args.max_file_size,
+        file_order: match &args.order {
+            Some(order_path) => Some(
+                read_to_string(order_path)
+                    .map_err(|e| format!("Failed to read file order '{order_path}': {e}"))?
+                    .lines()
+                    .filter(|line| !line.is_empty())
+                    .map(|line| line.split('/').map(str::to_owned).collect())
+                    .collect(),
+            ),
+            None => None,
+        },
+        source: args
+            .source
+            .clone()
+            .or(config.source.clone())
+            .filter(|s| !s.is_empty()),
+        collections: args.collection.clone(),
+        info_keys: args.info_key.iter().cloned().collect(),
+        pause_keys: true,
+    })
+}
+
+/// Output paths for batch targets. Targets sharing a file name get their parent directory
+/// name as prefix, remaining collisions a numeric suffix, both in input order.
+fn batch_torrent_paths(inputs: &[String], output_dir: Option<&str>) -> Vec<String> {
+    let out_dir = |input: &Path| match output_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => input.parent().map(Path::to_path_buf).unwrap_or_default(),
+    };
+    let base_paths: Vec<PathBuf> = inputs
+        .iter()
+        .map(|input| {
+            let input = Path::new(input);
+            let name = input.file_name().unwrap_or(input.as_os_str());
+            out_dir(input).join(format!("{}.torrent", name.to_string_lossy()))
+        })
+        .collect();
+
+    let mut used = HashSet::new();
+    let mut torrent_paths = Vec::with_capacity(inputs.len());
+    for (input, base_path) in inputs.iter().zip(&base_paths) {
+        let mut path = base_path.clone();
+        if base_paths.iter().filter(|p| *p == base_path).count() > 1 {
+            let input = Path::new(input);
+            if let Some(parent) = input.parent().and_then(Path::file_name) {
+                let name = base_path.file_name().unwrap_or_default().to_string_lossy();
+                path = out_dir(input).join(format!("{}.{name}", parent.to_string_lossy()));
+            }
+        }
+        let stem = path.with_extension("");
+        let mut n = 1;
+        while used.contains(&path) {
+            n += 1;
+            path = PathBuf::from(format!("{}.{n}.torrent", stem.display()));
+        }
+        used.insert(path.clone());
+        if path != *base_path {
+            warn(
+                WarningKind::Output,
+                format!(
+                    "Output name collision, '{input}' is written to '{}'",
+                    path.display()
+                ),
+            );
+        }
+        torrent_paths.push(path.to_string_lossy().to_string());
+    }
+    torrent_paths
+}
+
+/// The only file below `dir`, if `dir` is a directory holding exactly one file.
+fn single_file_in(dir: &str) -> Option<String> {
+    if !Path::new(dir).is_dir() {
+        return None;
+    }
+    let mut files = WalkDir::new(dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file());
+    match (files.next(), files.next()) {
+        (Some(file), None) => Some(file.path().to_string_lossy().to_string()),
+        _ => None,
+    }
+}
+
+/// Lets the user untick configured trackers, a tier is shown as one entry.
+fn choose_trackers(tracker_list: Vec<TrackerEntry>) -> Result<Vec<TrackerEntry>, String> {
+    if tracker_list.is_empty() {
+        return Ok(tracker_list);
+    }
+    let items: Vec<String> = tracker_list
+        .iter()
+        .map(|entry| match entry {
+            TrackerEntry::Url(url) => url.clone(),
+            TrackerEntry::Tier(urls) => urls.join(" | "),
+        })
+        .collect();
+    let chosen = MultiSelect::new()
+        .with_prompt("Trackers (space to toggle, enter to confirm)")
+        .items(&items)
+        .defaults(&vec![true; items.len()])
+        .interact()
+        .map_err(|e| format!("Tracker selection failed: {e}"))?;
+    Ok(tracker_list
+        .into_iter()
+        .enumerate()
+        .filter(|(i, _)| chosen.contains(i))
+        .map(|(_, entry)| entry)
+        .collect())
+}
+
+/// Groups the configured trackers into announce-list tiers, `announce` is then set to
+/// the first URL for clients without BEP 12 support.
+fn announce_tiers(tracker_list: &[TrackerEntry], mode: TrackerTiers) -> Vec<Vec<String>> {
+    let mut tiers: Vec<Vec<String>> = Vec::new();
+    let mut shared_tier: Option<usize> = None;
+    for entry in tracker_list {
+        match (entry, mode) {
+            (TrackerEntry::Url(url), TrackerTiers::Separate) => tiers.push(vec![url.clone()]),
+            (TrackerEntry::Url(url), TrackerTiers::Single) => match shared_tier {
+                Some(i) => tiers[i].push(url.clone()),
+                None => {
+                    shared_tier = Some(tiers.len());
+                    tiers.push(vec![url.clone()]);
+                }
+            },
+            (TrackerEntry::Tier(urls), _) => tiers.push(urls.clone()),
+        }
+    }
+    tiers.retain(|tier| !tier.is_empty());
+    tiers
+}
+
+/// Why the requested run would write a file, checked before anything is read.
+fn readonly_conflict(args: &Args) -> Option<String> {
+    if let Some(name) = args.command.as_ref().and_then(SubCommand::writing_name) {
+        return Some(format!("the {name} command writes torrent files"));
+    }
+    if args.command.is_some() {
+        return None;
+    }
+    let options = [
+        (args.report.is_some(), "--report"),
+        (
+            args.state.is_some() || args.max_duration.is_some(),
+            "--state",
+        ),
+        (args.export_order.is_some(), "--export-order"),
+        (args.piece_cache.is_some(), "--piece-cache"),
+    ];
+    if let Some((_, option)) = options.iter().find(|(set, _)| *set) {
+        return Some(format!("{option} writes a file"));
+    }
+    if args.batch_stdin || (args.input.len() == 1 && !args.input[0].ends_with(".torrent")) {
+        return Some(String::from("create mode writes a torrent file"));
+    }
+    None
+}
+
+/// File order of a batch job in the `--export-order` directory, named after its torrent,
+/// whose names `batch_torrent_paths` keeps unique.
+fn batch_order_path(order_dir: &str, torrent_path: &str) -> String {
+    let stem = Path::new(torrent_path).file_stem().unwrap_or_default();
+    Path::new(order_dir)
+        .join(format!("{}.order", stem.to_string_lossy()))
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Where create mode writes `output`: as given when it is a path, next to the target
+/// when it is a bare name.
+fn output_torrent_path(input: &str, output: &str) -> String {
+    if Path::new(output).is_absolute() || output.contains(MAIN_SEPARATOR) {
+        output.to_string()
+    } else {
+        let parent_path = Path::new(input).parent().unwrap_or_else(|| Path::new("."));
+        parent_path.join(output).to_string_lossy().to_string()
+    }
+}
+
+/// Canonical path of a torrent that may not exist yet, through its parent directory.
+fn canonical_output_path(torrent_path: &str) -> Option<PathBuf> {
+    let output_path = Path::new(torrent_path);
+    output_path
+        .parent()
+        .map(|p| {
+            if p.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                p
+            }
+        })
+        .and_then(|p| p.canonicalize().ok())
+        .zip(output_path.file_name())
+        .map(|(parent, file_name)| parent.join(file_name))
+}
+
+/// Shows `text` through `$PAGER` (default `less -R`, `more` on Windows) when stdout is a
+/// terminal too short for it, and prints it directly otherwise or if the pager fails to
+/// start. An empty `$PAGER` disables paging.
+pub fn page(text: &[u8]) {
+    let line_count = text.iter().filter(|&&b| b == b'\n').count();
+    let fits = !stdout().is_terminal()
+        || console::Term::stdout()
+            .size_checked()
+            .is_none_or(|(rows, _)| line_count < rows as usize);
+    if fits || !run_pager(text) {
+        let _ = stdout().write_all(text);
+    }
+}
+
+fn run_pager(text: &[u8]) -> bool {
+    let pager = env::var("PAGER")
+        .unwrap_or_else(|_| String::from(if cfg!(windows) { "more" } else { "less -R" }));
+    let mut parts = pager.split_whitespace();
+    let Some(program) = parts.next() else {
+        return false;
+    };
+    let Ok(mut child) = Command::new(program)
+        .args(parts)
+        .stdin(Stdio::piped())
+        .spawn()
+    else {
+        return false;
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // quitting the pager early closes the pipe, that is not an error
+        let _ = stdin.write_all(text);
+    }
+    child.wait().is_ok()
+}
+
+/// Tells how many pieces of `torrent` came from the piece cache, if one was used.
+pub fn print_cache_reuse(perf: &PerfStats, torrent: &Torrent) {
+    if let Some(reused) = perf.reused_pieces {
+        eprintln!(
+            "I: {reused} of {} pieces reused from the piece cache.",
+            torrent.piece_count()
+        );
+    }
+}
+
+/// Prints the info-hash of a written torrent, and for a hybrid torrent also its v2
+/// info-hash, which v2 clients show instead.
+pub fn print_info_hashes(torrent: &Torrent) {
+    if let Some(info_hash) = torrent.info_hash() {
+        println!("Info hash: {info_hash}");
+    }
+    if torrent.is_hybrid()
+        && let Some(v2) = torrent.info_hash_v2()
+    {
+        println!("Info hash v2: {v2}");
+    }
+}
+
+fn create_torrent_file(
+    input: &str,
+    torrent_path: String,
+    export_order: Option<&str>,
+    args: &Args,
+    config: &Config,
+    tr_config: &TrConfig,
+    quiet: bool,
+) -> TrResult<()> {
+    let flattened = if args.flatten_single {
+        single_file_in(input)
+    } else {
+        None
+    };
+    let input = flattened.as_deref().unwrap_or(input);
+    crash::set_context(format!("creating '{torrent_path}' from '{input}'"));
+    if !quiet {
+        if flattened.is_some() {
+            eprintln!("I: Directory holds a single file, creating a single-file torrent.");
+        }
+        eprintln!("Target:  {input}");
+        eprintln!("Torrent: {torrent_path}");
+        if tr_config.private {
+            eprintln!("Private Torrent");
+        }
+        match tr_config.meta_version {
+            MetaVersion::V1 => {}
+            MetaVersion::V2 => eprintln!("Meta Version: 2 (BEP 52)"),
+            MetaVersion::Hybrid => eprintln!("Meta Version: hybrid v1 + v2"),
+        }
+    }
+
+    let announce_list = announce_tiers(&config.tracker_list, config.tracker_tiers);
+
+    let mut torrent = Torrent::new(
+        if announce_list.is_empty() {
+            None
+        } else {
+            Some(announce_list[0][0].clone())
+        },
+        if announce_list.is_empty() {
+            None
+        } else {
+            Some(announce_list)
+        },
+        args.comment.clone(),
+        Some(NAME_VERSION.to_string()),
+        if args.no_date {
+            None
+        } else {
+            Some(
+                args.date
+                    .unwrap_or_else(|| chrono::Local::now().timestamp()),
+            )
+        },
+        Some(String::from("UTF-8")),
+    );
+    torrent.set_web_seeds(args.web_seed.clone());
+    torrent.set_http_seeds(args.http_seed.clone());
+    torrent.set_nodes(args.node.clone());
+    torrent.set_extra_keys(args.root_key.iter().cloned().collect());
+
+    if !args.force && !args.dry_run && Path::new(&torrent_path).exists() {
+        return Err(TrError::OutputExists(torrent_path));
+    }
+    // checked before hashing, the order is written when the file list is known
+    if let Some(order_path) = export_order
+        && !args.force
+        && Path::new(order_path).exists()
+    {
+        return Err(TrError::OutputExists(order_path.to_string()));
+    }
+
+    // the output may be written inside the target, keep it (or a stale copy) out of it
+    let mut tr_config = tr_config.clone();
+    tr_config.output_path = canonical_output_path(&torrent_path);
+    let tr_config = &tr_config;
+
+    torrent.preflight_torrent(input, tr_config)?;
+    if !quiet && let Some(info) = torrent.get_info() {
+        eprintln!(
+            "Piece Length: {} bytes [{}]{}",
+            info.piece_length,
+            utils::human_size(info.piece_length),
+            if tr_config.auto_piece_size {
+                " (auto)"
+            } else {
+                ""
+            }
+        );
+    }
+
+    if (args.max_path_length.is_some() || args.max_path_depth.is_some())
+        && let Some(info) = torrent.get_info()
+    {
+        let limits = PathLimits {
+            max_length: args.max_path_length,
+            max_depth: args.max_path_depth,
+        };
+        let findings = check_paths(info, &limits);
+        if !findings.is_empty() {
+            for finding in &findings {
+                eprintln!("Error: {finding}");
+            }
+            return Err(TrError::InvalidPath(format!(
+                "{} path limit violations",
+                findings.len()
+            )));
+        }
+    }
+
+    if let (Some(template), Some(info)) = (&args.comment, torrent.get_info()) {
+        let size = info.total_size();
+        // the date written to the torrent (--date), today if it has none
+        let date = torrent
+            .creation_date()
+            .and_then(|ts| chrono::Local.timestamp_opt(ts, 0).single())
+            .unwrap_or_else(chrono::Local::now);
+        let vars = [
+            ("path", input.to_string()),
+            ("name", info.name.clone().unwrap_or_default()),
+            ("date", date.format("%Y-%m-%d").to_string()),
+            ("size", utils::human_size(size)),
+            ("bytes", size.to_string()),
+            (
+                "files",
+                info.tr_files()?
+                    .iter()
+                    .filter(|f| !f.padding)
+                    .count()
+                    .to_string(),
+            ),
+        ];
+        torrent.set_comment(Some(utils::fill_template(template, &vars)));
+    }
+
+    check_torrent_size(&torrent, quiet);
+    check_client_compat(&torrent, args.strict_compat)?;
+
+    if args.dry_run {
+        if !quiet {
+            eprintln!("I: Dry run, nothing hashed or written.");
+        }
+        return Ok(());
+    }
+
+    let _auto_yield = start_auto_yield(config.auto_yield, input, quiet);
+    let perf = torrent.create_torrent(input, tr_config, quiet)?;
+    if !quiet {
+        print_cache_reuse(&perf, &torrent);
+    }
+    if args.perf_summary {
+        perf.print_summary();
+    }
+
+    let file_sha256 = torrent.write_to_file(torrent_path, args.force)?;
+    // only next to a written torrent, a dry run or failed hash leaves no order file behind
+    if let (Some(order_path), Some(info)) = (export_order, torrent.get_info()) {
+        match &info.files {
+            Some(files) => {
+                let mut order = String::new();
+                for file in files.iter().filter(|f| !f.padding) {
+                    order.push_str(&file.path.join("/"));
+                    order.push('\n');
+                }
+                utils::check_write(Path::new(order_path))?;
+                write(order_path, order)?;
+                if !quiet {
+                    eprintln!("I: File order written to '{order_path}'.");
+                }
+            }
+            None => warn(
+                WarningKind::Output,
+                "Single-file target, no file order to export.",
+            ),
+        }
+    }
+    if !quiet {
+        print_info_hashes(&torrent);
+        println!("File SHA-256: {file_sha256}");
+    }
+    Ok(())
+}
+
 fn main() {
-    let args: Args = argh::from_env();
+    crash::install_hook();
+    let mut args: Args = argh::from_env();
 
     if args.version {
         println!("{NAME_VERSION}");
         return;
     }
+    if let Some(SubCommand::Version(version_args)) = &args.command {
+        commands::version(version_args, &args.config);
+        return;
+    }
 
-    let mut config: Config = std::fs::read_to_string(&args.config)
+    let mut config: Config = read_to_string(&args.config)
         .map_err(|_| ())
         .and_then(|content| {
             toml::from_str::<Config>(&content)
                 .map_err(|_| ())
                 .inspect(|_| {
                     if !args.quiet {
-                        println!("I: Config loaded.");
+                        eprintln!("I: Config loaded.");
                     }
                 })
         })
         .unwrap_or_default();
 
-    config.wait_exit = args.wait_exit || config.wait_exit;
+    // dropped onto the exe: keep the console open and ask what to do
+    let dropped = cfg!(windows) && wizard::launched_by_drop(&args);
+    config.wait_exit = args.wait_exit || config.wait_exit || dropped;
+    if dropped {
+        match wizard::drop_menu(&mut args) {
+            Ok(true) => {}
+            Ok(false) => return,
+            Err(e) => {
+                eprintln!("Error: {e}");
+                wait_for_enter(config.wait_exit);
+                exit(1);
+            }
+        }
+    }
+    config.auto_yield = args.auto_yield || config.auto_yield;
+    config.read_buffer = args.read_buffer.or(config.read_buffer);
+    config.sort_locale = args.sort_locale.or(config.sort_locale);
+    if let Some(substitutions) = config.path_substitutions.take() {
+        let mut valid = Vec::new();
+        for (from, to) in substitutions {
+            let mut chars = from.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => valid.push((c, to)),
+                _ => warn(
+                    WarningKind::Config,
+                    format!("Ignoring path substitution for '{from}', not a single character"),
+                ),
+            }
+        }
+        tr_file::set_path_substitutions(valid);
+    }
+    if let Some(mode) = args.progress {
+        utils::set_progress_mode(mode);
+    }
+    if args.verbose {
+        utils::set_resource_report(RESOURCE_REPORT_INTERVAL);
+    }
+    if let Some(format) = args.format {
+        output::set_format(format);
+    }
+    if let Some(dir) = args.state_dir.clone().or(config.state_dir.take()) {
+        state::set_state_dir(PathBuf::from(dir));
+    }
+    if let Some(limit) = args.max_open_files.or(config.max_open_files) {
+        throttle::set_open_file_limit(limit);
+    }
+    if args.pin_threads && !numa::SUPPORTED {
+        warn(
+            WarningKind::Config,
+            "--pin-threads needs a Linux build with the numa feature, threads are not pinned",
+        );
+    }
+    numa::set_pin_threads(args.pin_threads);
+    if let Some(format) = args.date_format.clone() {
+        datetime::set_date_format(format);
+    }
+    let proxy = match args.proxy.clone().or(config.proxy.take()) {
+        Some(proxy) => match net::parse_proxy(&proxy) {
+            Ok(proxy) => Some(proxy),
+            Err(e) => {
+                eprintln!("Error: {e}");
+                wait_for_enter(config.wait_exit);
+                exit(1);
+            }
+        },
+        None => None,
+    };
+    net::set_net_config(net::NetConfig {
+        proxy,
+        user_agent: args.user_agent.clone().or(config.user_agent.take()),
+        timeout: config.network_timeout,
+    });
 
     config.n_jobs = args.n_jobs.unwrap_or(config.n_jobs).clamp(
         1,
@@ -180,23 +1225,184 @@ fn main() {
             .unwrap_or(1),
     );
 
+    if args.assert_readonly {
+        if let Some(reason) = readonly_conflict(&args) {
+            eprintln!("Error: --assert-readonly is set, but {reason}.");
+            wait_for_enter(config.wait_exit);
+            exit(1);
+        }
+        utils::set_read_only();
+        if !args.quiet {
+            eprintln!("I: Read-only mode, no file will be written.");
+        }
+    }
+
+    if matches!(args.command, Some(SubCommand::Wizard(_))) {
+        if let Err(e) = wizard::run(&mut args, &mut config) {
+            eprintln!("Error: {e}");
+            wait_for_enter(config.wait_exit);
+            exit(1);
+        }
+        args.command = None;
+    }
+
+    if let Some(command) = args.command {
+        if let Err(e) = commands::run(command, &config, args.quiet) {
+            eprintln!("Error: {e}");
+            wait_for_enter(config.wait_exit);
+            exit(1);
+        }
+        wait_for_enter(config.wait_exit);
+        return;
+    }
+
+    if args.batch_stdin {
+        if !args.input.is_empty() || args.order.is_some() {
+            eprintln!(
+                "Error: --batch-stdin reads targets from stdin, no input or --order allowed."
+            );
+            wait_for_enter(config.wait_exit);
+            exit(1);
+        }
+        if let Some(output) = &args.output
+            && !Path::new(output).is_dir()
+        {
+            eprintln!("Error: Output '{output}' must be an existing directory with --batch-stdin.");
+            wait_for_enter(config.wait_exit);
+            exit(1);
+        }
+        if let Some(order_dir) = &args.export_order
+            && !Path::new(order_dir).is_dir()
+        {
+            eprintln!(
+                "Error: --export-order '{order_dir}' must be an existing directory with --batch-stdin."
+            );
+            wait_for_enter(config.wait_exit);
+            exit(1);
+        }
+        if !args.quiet {
+            eprintln!("I: Batch create mode.");
+        }
+        let mut tr_config = match build_tr_config(&args, &mut config) {
+            Ok(tr_config) => tr_config,
+            Err(e) => {
+                eprintln!("Error: {e}");
+                warnings::print_summary();
+                exit(1);
+            }
+        };
+        let mut inputs = Vec::new();
+        for line in stdin().lock().lines() {
+            match line {
+                Ok(line) if !line.trim().is_empty() => inputs.push(line.trim().to_string()),
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("Error reading stdin: {e}");
+                    warnings::print_summary();
+                    exit(1);
+                }
+            }
+        }
+        let torrent_paths = batch_torrent_paths(&inputs, args.output.as_deref());
+        // the -j threads are the budget of the whole batch, each job hashes with its share
+        let requested = args.parallel_jobs.unwrap_or(1).max(1);
+        let parallel = requested.min(config.n_jobs).min(inputs.len().max(1));
+        if requested > config.n_jobs {
+            warn(
+                WarningKind::Config,
+                format!(
+                    "--parallel-jobs {requested} exceeds the {} threads of -j, running {parallel} at once.",
+                    config.n_jobs
+                ),
+            );
+        }
+        tr_config.n_jobs = config.n_jobs / parallel;
+        if parallel > 1 && !args.quiet {
+            eprintln!(
+                "I: Creating {parallel} torrents at once, {} hashing threads each.",
+                tr_config.n_jobs
+            );
+        }
+        // one listener for the whole batch: listeners of parallel jobs would save and restore
+        // each other's terminal mode, and the first to finish would resume the others
+        let _pause_listener = PauseListener::start(&Progress::lines(args.quiet));
+        tr_config.pause_keys = false;
+        let jobs: Vec<(&String, String)> = inputs.iter().zip(torrent_paths).collect();
+        let next = AtomicUsize::new(0);
+        let (created, failed) = (AtomicUsize::new(0), AtomicUsize::new(0));
+        thread::scope(|s| {
+            for _ in 0..parallel {
+                s.spawn(|| {
+                    while let Some((input, torrent_path)) =
+                        jobs.get(next.fetch_add(1, Ordering::Relaxed))
+                    {
+                        // interleaved progress of several jobs is unreadable, report each once
+                        let quiet = args.quiet || parallel > 1;
+                        let export_order = args
+                            .export_order
+                            .as_ref()
+                            .map(|dir| batch_order_path(dir, torrent_path));
+                        let result = create_torrent_file(
+                            input,
+                            torrent_path.clone(),
+                            export_order.as_deref(),
+                            &args,
+                            &config,
+                            &tr_config,
+                            quiet,
+                        );
+                        match result {
+                            Ok(()) => {
+                                created.fetch_add(1, Ordering::Relaxed);
+                                if parallel > 1 && !args.quiet {
+                                    println!("Torrent: {torrent_path}");
+                                }
+                            }
+                            Err(e) => {
+                                eprintln!("Error creating torrent for '{input}': {e}");
+                                failed.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                    }
+                });
+            }
+        });
+        let (created, failed) = (created.into_inner(), failed.into_inner());
+        warnings::print_summary();
+        if !args.quiet {
+            println!("Batch: {created} created, {failed} failed");
+        }
+        if failed > 0 {
+            exit(1);
+        }
+        return;
+    }
+
     match args.input.len() {
         1 => {
             let input = &args.input[0];
             if input.ends_with(".torrent") {
                 // show info
                 if !args.quiet {
-                    println!("I: Info mode.");
-                    println!("Torrent: {input}");
+                    eprintln!("I: Info mode.");
+                    eprintln!("Torrent: {input}");
                 }
-                match Torrent::read_torrent(input.clone()) {
-                    Ok(torrent) => {
-                        if args.print_tree {
-                            torrent.print_file_tree();
-                        } else {
-                            println!("{torrent}");
-                        }
+                if output::format() != Format::Human {
+                    if let Err(e) = commands::print_info_reports(&args.input) {
+                        eprintln!("Error reading torrent file: {e}");
+                        wait_for_enter(config.wait_exit);
+                        exit(1);
                     }
+                    return;
+                }
+                let mut text = Vec::new();
+                match commands::write_info(
+                    &mut text,
+                    input,
+                    args.print_tree,
+                    config.sort_locale.unwrap_or(SortLocale::Natural),
+                ) {
+                    Ok(()) => page(&text),
                     Err(e) => {
                         eprintln!("Error reading torrent file: {e}");
                         wait_for_enter(config.wait_exit);
@@ -206,59 +1412,21 @@ fn main() {
             } else {
                 // create mode
                 if !args.quiet {
-                    println!("I: Create mode.");
+                    eprintln!("I: Create mode.");
                 }
-                config.piece_size = args.piece_size.unwrap_or(config.piece_size);
-
-                let tr_config = TrConfig {
-                    piece_length: 1usize
-                        << match config.piece_size {
-                            14..=27 => config.piece_size,
-                            _ => {
-                                eprintln!("Error: Piece size must be between 14 and 27.");
-                                wait_for_enter(config.wait_exit);
-                                exit(1);
-                            }
-                        },
-                    private: args.private || config.private,
-                    n_jobs: config.n_jobs,
-                    walk_mode: match args.walk_mode.unwrap_or(config.walk_mode) {
-                        0 => WalkMode::Default,
-                        1 => WalkMode::Alphabetical,
-                        2 => WalkMode::BreadthFirstAlphabetical,
-                        3 => WalkMode::BreadthFirstLevel,
-                        4 => WalkMode::FileSize,
-                        _ => {
-                            eprintln!("Error: Invalid walk mode.");
-                            wait_for_enter(config.wait_exit);
-                            exit(1);
-                        }
-                    },
-                    source: args.source.or(config.source).filter(|s| !s.is_empty()),
-                };
-
-                config.tracker_list = if !args.announce.is_empty() {
-                    if args.announce.iter().any(|s| s.is_empty()) {
-                        Vec::new()
-                    } else {
-                        args.announce.clone()
+                let tr_config = match build_tr_config(&args, &mut config) {
+                    Ok(tr_config) => tr_config,
+                    Err(e) => {
+                        eprintln!("Error: {e}");
+                        wait_for_enter(config.wait_exit);
+                        exit(1);
                     }
-                } else {
-                    config.tracker_list
                 };
 
                 let torrent_path = match args.output {
                     Some(ref path) => {
                         if path.ends_with(".torrent") {
-                            let path_obj = Path::new(path);
-                            if path_obj.is_absolute() || path.contains(MAIN_SEPARATOR) {
-                                path.clone()
-                            } else {
-                                let target_path = Path::new(input);
-                                let parent_path =
-                                    target_path.parent().unwrap_or_else(|| Path::new("."));
-                                parent_path.join(path).to_string_lossy().to_string()
-                            }
+                            output_torrent_path(input, path)
                         } else {
                             eprint!("Error: Output path must end with .torrent");
                             wait_for_enter(config.wait_exit);
@@ -268,57 +1436,19 @@ fn main() {
                     None => format!("{input}.torrent"),
                 };
 
-                if !args.quiet {
-                    println!("Target:  {input}");
-                    println!("Torrent: {torrent_path}");
-                    println!(
-                        "Piece Length: {} bytes [{}]",
-                        tr_config.piece_length,
-                        utils::human_size(tr_config.piece_length)
-                    );
-                    if tr_config.private {
-                        println!("Private Torrent");
-                    }
-                }
-
-                let announce_list: Vec<Vec<String>> = config
-                    .tracker_list
-                    .iter()
-                    .map(|url| vec![url.clone()])
-                    .collect();
-
-                let mut torrent = Torrent::new(
-                    if announce_list.is_empty() {
-                        None
-                    } else {
-                        Some(announce_list[0][0].clone())
-                    },
-                    if announce_list.is_empty() {
-                        None
-                    } else {
-                        Some(announce_list)
-                    },
-                    args.comment,
-                    Some(NAME_VERSION.to_string()),
-                    if args.no_date {
-                        None
-                    } else {
-                        Some(chrono::Local::now().timestamp())
-                    },
-                    Some(String::from("UTF-8")),
-                );
-
-                if let Err(e) = torrent.create_torrent(input.clone(), &tr_config, args.quiet) {
+                if let Err(e) = create_torrent_file(
+                    input,
+                    torrent_path,
+                    args.export_order.as_deref(),
+                    &args,
+                    &config,
+                    &tr_config,
+                    args.quiet,
+                ) {
                     eprintln!("Error creating torrent: {e}");
                     wait_for_enter(config.wait_exit);
                     exit(1);
                 }
-
-                if let Err(e) = torrent.write_to_file(torrent_path, args.force) {
-                    eprintln!("Error writing torrent file: {e}");
-                    wait_for_enter(config.wait_exit);
-                    exit(1);
-                }
             }
         }
         2 => {
@@ -333,12 +1463,12 @@ fn main() {
                 exit(1);
             };
             if !args.quiet {
-                println!("I: Verify mode.");
-                println!("Target:  {target_path}");
-                println!("Torrent: {torrent_path}");
+                eprintln!("I: Verify mode.");
+                eprintln!("Target:  {target_path}");
+                eprintln!("Torrent: {torrent_path}");
             }
 
-            let torrent = match Torrent::read_torrent(torrent_path) {
+            let torrent = match Torrent::read_torrent(torrent_path.clone()) {
                 Ok(t) => t,
                 Err(e) => {
                     eprintln!("Error reading torrent file: {e}");
@@ -354,30 +1484,229 @@ fn main() {
                     exit(1);
                 }
             };
-            let base_path = Path::new(&target_path);
-            let name = base_path.file_name().and_then(|n| n.to_str()).unwrap_or("");
-            let tr_name = tr_info.get_name().unwrap_or(String::from("<unknown>"));
-            if name != tr_name {
-                eprintln!("Error: Target name '{name}' does not match torrent name '{tr_name}'");
+            if let Err(e) = tr_info.check_target(Path::new(&target_path)) {
+                eprintln!("Error: {e}");
                 wait_for_enter(config.wait_exit);
                 exit(1);
-            } else {
-                let full_path = base_path.parent().unwrap_or_else(|| Path::new(""));
-                if !full_path.join(&tr_name).exists() {
-                    eprintln!(
-                        "Error: Target path '{}' does not exist",
-                        full_path.join(&tr_name).display()
+            }
+
+            let selected = match &args.trust_resume {
+                Some(resume_path) => match ResumeData::read_resume(resume_path) {
+                    Ok(ResumeData {
+                        pieces: Some(pieces),
+                        ..
+                    }) => Some(
+                        pieces
+                            .into_iter()
+                            .map(|have| have != args.check_incomplete)
+                            .collect::<Vec<bool>>(),
+                    ),
+                    Ok(_) => {
+                        eprintln!("Error: Resume file '{resume_path}' has no piece bitfield");
+                        wait_for_enter(config.wait_exit);
+                        exit(1);
+                    }
+                    Err(e) => {
+                        eprintln!("Error reading resume file: {e}");
+                        wait_for_enter(config.wait_exit);
+                        exit(1);
+                    }
+                },
+                None => None,
+            };
+
+            // a first run has nothing to compare with yet
+            let compare_with = args.compare_with.as_deref().filter(|path| {
+                let exists = Path::new(path).exists();
+                if !exists {
+                    warn(
+                        WarningKind::Output,
+                        format!("Previous report '{path}' does not exist, nothing to compare."),
                     );
+                }
+                exists
+            });
+            let previous = match compare_with.map(VerifyReport::read) {
+                Some(Ok(previous)) => Some(previous),
+                Some(Err(e)) => {
+                    eprintln!("Error reading previous report: {e}");
                     wait_for_enter(config.wait_exit);
                     exit(1);
                 }
-            }
+                None => None,
+            };
 
-            if let Err(e) = tr_info.verify(target_path, config.n_jobs, args.quiet) {
-                eprintln!("Error during verification: {e}");
+            // next to the torrent is where earlier versions kept it
+            let legacy_state = format!("{torrent_path}.verify-state");
+            let state_path = match (&args.state, args.max_duration) {
+                (Some(path), _) => Some(path.clone()),
+                (None, Some(_)) if Path::new(&legacy_state).exists() => Some(legacy_state),
+                (None, Some(_)) => match state::state_file(&state::verify_state_name(
+                    &torrent.info_hash().unwrap_or_default(),
+                )) {
+                    Ok(path) => Some(path.to_string_lossy().to_string()),
+                    Err(e) => {
+                        eprintln!("Error: Failed to create the state directory: {e}");
+                        wait_for_enter(config.wait_exit);
+                        exit(1);
+                    }
+                },
+                (None, None) => None,
+            };
+            if state_path.is_some() && selected.is_some() {
+                eprintln!(
+                    "Error: --trust-resume cannot be combined with --state or --max-duration."
+                );
                 wait_for_enter(config.wait_exit);
                 exit(1);
             }
+            let format = if args.json {
+                Format::Json
+            } else {
+                output::format()
+            };
+            let human = format == Format::Human;
+            if !human && args.perf_summary {
+                // both go to stdout
+                eprintln!("Error: --json and --format cannot be combined with --perf-summary.");
+                wait_for_enter(config.wait_exit);
+                exit(1);
+            }
+            let mut state = match &state_path {
+                Some(path) => match VerifyState::load(
+                    path,
+                    torrent.info_hash().unwrap_or_default(),
+                    tr_info.piece_count(),
+                ) {
+                    Ok(state) => Some(state),
+                    Err(e) => {
+                        eprintln!("Error reading verify state: {e}");
+                        wait_for_enter(config.wait_exit);
+                        exit(1);
+                    }
+                },
+                None => None,
+            };
+            if let (Some(state), Some(path)) = (&state, &state_path)
+                && state.sessions > 0
+                && !args.quiet
+            {
+                eprintln!(
+                    "I: Continuing from '{path}', {} of {} pieces checked in {} sessions.",
+                    state.checked_count(),
+                    state.pieces.len(),
+                    state.sessions
+                );
+            }
+            let state_selected = state.as_ref().map(VerifyState::unchecked);
+            if let Some(max_duration) = args.max_duration {
+                pause::set_deadline(Instant::now() + max_duration);
+            }
+
+            if !args.quiet {
+                for tr_file in tr_info.tr_files().iter().flat_map(|files| files.iter()) {
+                    if let Some(local_path) = tr_file.local_path() {
+                        eprintln!(
+                            "I: '{}' is looked up as '{}'.",
+                            tr_file.path.join("/"),
+                            local_path.display()
+                        );
+                    }
+                }
+            }
+            let _auto_yield = start_auto_yield(config.auto_yield, &target_path, args.quiet);
+            crash::set_context(format!(
+                "verifying '{target_path}' against '{torrent_path}'"
+            ));
+            let options = VerifyOptions {
+                n_jobs: config.n_jobs,
+                read_buffer: config.read_buffer,
+                selected: state_selected.as_deref().or(selected.as_deref()),
+                order: args.verify_order.unwrap_or(VerifyOrder::Torrent),
+                paranoid: args.paranoid,
+                quiet: args.quiet,
+                pause_keys: true,
+            };
+            match tr_info.verify(&target_path, &options) {
+                Ok((mut summary, perf)) => {
+                    if human {
+                        summary.print();
+                    }
+                    if let Some(selected) = &selected
+                        && human
+                    {
+                        report_bitfield(selected, summary.failed_pieces, args.check_incomplete);
+                    }
+                    if let (Some(state), Some(state_selected), Some(path)) =
+                        (&mut state, &state_selected, &state_path)
+                    {
+                        state.record(state_selected, &summary);
+                        // the report covers all sessions so far
+                        summary.failed_pieces = state.failed_count();
+                        summary.failed_files = state.failed_files.clone();
+                        summary.failed_indices = state.failed_indices();
+                        summary.unchecked = state
+                            .unchecked()
+                            .iter()
+                            .enumerate()
+                            .filter_map(|(i, &unchecked)| unchecked.then_some(i))
+                            .collect();
+                        if human {
+                            if !state.is_complete() {
+                                println!(
+                                    "\nProgress saved to '{path}': {} of {} pieces checked, run again to continue.",
+                                    state.checked_count(),
+                                    state.pieces.len()
+                                );
+                            } else if state.sessions > 1 {
+                                println!(
+                                    "\nAll pieces checked over {} sessions: {} pieces and {} files failed.",
+                                    state.sessions,
+                                    state.failed_count(),
+                                    state.failed_files.len()
+                                );
+                            }
+                        }
+                        if let Err(e) = state.save(path) {
+                            eprintln!("Error writing verify state: {e}");
+                            wait_for_enter(config.wait_exit);
+                            exit(1);
+                        }
+                    }
+                    let verdict = summary.ensure_passed();
+                    let report = VerifyReport::new(&torrent_path, &target_path, summary);
+                    if let Some(previous) = &previous
+                        && human
+                    {
+                        report.print_delta(previous);
+                    }
+                    if !human && let Err(e) = report.write_as(format, &mut stdout().lock()) {
+                        eprintln!("Error: {e}");
+                        wait_for_enter(config.wait_exit);
+                        exit(1);
+                    }
+                    if let Some(report_path) = &args.report
+                        && let Err(e) = report.write(report_path)
+                    {
+                        eprintln!("Error writing report: {e}");
+                        wait_for_enter(config.wait_exit);
+                        exit(1);
+                    }
+                    if args.perf_summary {
+                        perf.print_summary();
+                    }
+                    // the result is printed above, scripts tell it from errors by the exit code
+                    if verdict.is_err() {
+                        wait_for_enter(config.wait_exit);
+                        exit(2);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error during verification: {e}");
+                    wait_for_enter(config.wait_exit);
+                    exit(1);
+                }
+            }
         }
         _ => {
             eprintln!(
@@ -390,3 +1719,67 @@ fn main() {
 
     wait_for_enter(config.wait_exit);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn joined(dir: &str, name: &str) -> String {
+        Path::new(dir).join(name).to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn batch_paths_sit_next_to_their_targets() {
+        assert_eq!(
+            batch_torrent_paths(&strings(&["/x/a/data", "/x/b/file.bin"]), None),
+            [
+                joined("/x/a", "data.torrent"),
+                joined("/x/b", "file.bin.torrent")
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_path_collisions_get_parent_prefix_then_suffix() {
+        let inputs = strings(&["/x/a/data", "/x/b/data", "/x/c/other", "/y/a/data"]);
+        assert_eq!(
+            batch_torrent_paths(&inputs, Some("out")),
+            [
+                joined("out", "a.data.torrent"),
+                joined("out", "b.data.torrent"),
+                joined("out", "other.torrent"),
+                joined("out", "a.data.2.torrent"),
+            ]
+        );
+    }
+
+    #[test]
+    fn announce_tiers_group_plain_urls_by_mode() {
+        let list = [
+            TrackerEntry::Url(String::from("http://a")),
+            TrackerEntry::Tier(strings(&["http://b", "http://c"])),
+            TrackerEntry::Url(String::from("http://d")),
+            TrackerEntry::Tier(Vec::new()),
+        ];
+        assert_eq!(
+            announce_tiers(&list, TrackerTiers::Separate),
+            [
+                strings(&["http://a"]),
+                strings(&["http://b", "http://c"]),
+                strings(&["http://d"])
+            ]
+        );
+        // the shared tier stays where the first plain URL was
+        assert_eq!(
+            announce_tiers(&list, TrackerTiers::Single),
+            [
+                strings(&["http://a", "http://d"]),
+                strings(&["http://b", "http://c"])
+            ]
+        );
+    }
+}
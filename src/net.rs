@@ -0,0 +1,212 @@
+use std::env;
+use std::io::{Error as ioError, Read, Result as ioResult, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::NAME_VERSION;
+use crate::utils::percent_decode;
+
+const PROXY_SCHEMES: [&str; 6] = [
+    "http://",
+    "https://",
+    "socks4://",
+    "socks4a://",
+    "socks5://",
+    "socks5h://",
+];
+
+/// Proxy, user agent and timeout for every network access, from the options or the config.
+pub struct NetConfig {
+    /// HTTP(S) or SOCKS proxy URL for HTTP(S) requests, SOCKS5 ones also carry peer connections.
+    pub proxy: Option<String>,
+    pub user_agent: Option<String>,
+    /// How long to wait for a server to accept a connection or answer, instead of the
+    /// default of each request.
+    pub timeout: Option<Duration>,
+}
+
+static NET_CONFIG: OnceLock<NetConfig> = OnceLock::new();
+
+/// Proxy variables of the environment curl reads, the first set one is used.
+const PROXY_VARS: [&str; 6] = [
+    "ALL_PROXY",
+    "all_proxy",
+    "HTTPS_PROXY",
+    "https_proxy",
+    "HTTP_PROXY",
+    "http_proxy",
+];
+
+/// Checks a `--proxy` URL, e.g. `socks5h://127.0.0.1:9050` or `http://proxy:3128`.
+pub fn parse_proxy(s: &str) -> Result<String, String> {
+    if PROXY_SCHEMES.iter().any(|scheme| s.starts_with(scheme)) {
+        Ok(s.to_string())
+    } else {
+        Err(format!(
+            "Invalid proxy '{s}', expected an http, https, socks4, socks4a, socks5 or socks5h URL"
+        ))
+    }
+}
+
+pub fn set_net_config(config: NetConfig) {
+    let _ = NET_CONFIG.set(config);
+}
+
+/// The `--proxy` URL, if one is set.
+pub fn proxy() -> Option<&'static str> {
+    NET_CONFIG.get()?.proxy.as_deref()
+}
+
+/// The `--proxy` URL, else the one curl takes from the environment. Traffic curl does not
+/// carry (peers, the DHT, UDP trackers) goes by this one, so an environment proxy is
+/// not bypassed either.
+pub fn any_proxy() -> Option<String> {
+    proxy().map(str::to_string).or_else(|| {
+        PROXY_VARS
+            .iter()
+            .filter_map(|var| env::var(var).ok())
+            .find(|value| !value.is_empty())
+    })
+}
+
+pub fn user_agent() -> &'static str {
+    NET_CONFIG
+        .get()
+        .and_then(|config| config.user_agent.as_deref())
+        .unwrap_or(NAME_VERSION)
+}
+
+/// The configured network timeout, `default` if there is none.
+pub fn timeout(default: Duration) -> Duration {
+    NET_CONFIG
+        .get()
+        .and_then(|config| config.timeout)
+        .unwrap_or(default)
+}
+
+/// The system `curl` with the user agent, connect timeout and proxy applied. Without
+/// `--proxy`, curl reads `HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY` and `NO_PROXY` itself.
+pub fn curl() -> Command {
+    let mut command = Command::new("curl");
+    command.args(["-fsSL", "-A", user_agent()]);
+    if let Some(timeout) = NET_CONFIG.get().and_then(|config| config.timeout) {
+        command.args(["--connect-timeout", &timeout.as_secs_f64().to_string()]);
+    }
+    if let Some(proxy) = proxy() {
+        command.args(["--proxy", proxy]);
+    }
+    command
+}
+
+/// Host, port and credentials of a `socks5://` or `socks5h://` proxy.
+struct Socks5 {
+    server: String,
+    /// User name and password, percent-decoded.
+    auth: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+fn socks5() -> Option<Socks5> {
+    let proxy = any_proxy()?;
+    let rest = proxy
+        .strip_prefix("socks5h://")
+        .or_else(|| proxy.strip_prefix("socks5://"))?;
+    let rest = rest.trim_end_matches('/');
+    let (auth, server) = match rest.rsplit_once('@') {
+        Some((auth, server)) => {
+            let (user, password) = auth.split_once(':').unwrap_or((auth, ""));
+            (
+                Some((percent_decode(user), percent_decode(password))),
+                server,
+            )
+        }
+        None => (None, rest),
+    };
+    Some(Socks5 {
+        server: server.to_string(),
+        auth,
+    })
+}
+
+/// Whether TCP connections can go through the proxy, which is the case for SOCKS5.
+pub fn tcp_proxied() -> bool {
+    socks5().is_some()
+}
+
+fn socks_error(msg: &str) -> ioError {
+    ioError::other(format!("SOCKS5 proxy: {msg}"))
+}
+
+/// Connects to `addr`, through the proxy if it is a SOCKS5 one (RFC 1928, with the
+/// username and password of RFC 1929 if the proxy URL has them).
+pub fn tcp_connect(addr: SocketAddr, timeout: Duration) -> ioResult<TcpStream> {
+    let Some(socks) = socks5() else {
+        return TcpStream::connect_timeout(&addr, timeout);
+    };
+    let server = socks
+        .server
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| socks_error("host does not resolve"))?;
+    let mut stream = TcpStream::connect_timeout(&server, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let method = if socks.auth.is_some() { 2 } else { 0 };
+    stream.write_all(&[5, 1, method])?;
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply)?;
+    if reply[0] != 5 || reply[1] != method {
+        return Err(socks_error("no acceptable authentication method"));
+    }
+    if let Some((user, password)) = &socks.auth {
+        // RFC 1929 sends each with a one-byte length
+        let (Ok(user_len), Ok(password_len)) =
+            (u8::try_from(user.len()), u8::try_from(password.len()))
+        else {
+            return Err(socks_error("user name or password longer than 255 bytes"));
+        };
+        let mut request = vec![1, user_len];
+        request.extend_from_slice(user);
+        request.push(password_len);
+        request.extend_from_slice(password);
+        stream.write_all(&request)?;
+        stream.read_exact(&mut reply)?;
+        if reply[1] != 0 {
+            return Err(socks_error("authentication failed"));
+        }
+    }
+
+    let mut request = vec![5, 1, 0];
+    match addr {
+        SocketAddr::V4(v4) => {
+            request.push(1);
+            request.extend_from_slice(&v4.ip().octets());
+        }
+        SocketAddr::V6(v6) => {
+            request.push(4);
+            request.extend_from_slice(&v6.ip().octets());
+        }
+    }
+    request.extend_from_slice(&addr.port().to_be_bytes());
+    stream.write_all(&request)?;
+    let mut head = [0u8; 4];
+    stream.read_exact(&mut head)?;
+    if head[1] != 0 {
+        return Err(socks_error(&format!("connect refused (reply {})", head[1])));
+    }
+    // the address the proxy bound, not needed
+    let bound_len = match head[3] {
+        1 => 4,
+        4 => 16,
+        3 => {
+            let mut len = [0u8; 1];
+            stream.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        _ => return Err(socks_error("invalid reply")),
+    };
+    stream.read_exact(&mut vec![0; bound_len + 2])?;
+    Ok(stream)
+}
@@ -0,0 +1,109 @@
+use std::cell::Cell;
+use std::sync::OnceLock;
+
+static PIN_THREADS: OnceLock<bool> = OnceLock::new();
+static NODES: OnceLock<Vec<Vec<usize>>> = OnceLock::new();
+
+thread_local! {
+    static CURRENT_NODE: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Whether this build can pin threads, needs Linux and the `numa` feature.
+pub const SUPPORTED: bool = cfg!(all(feature = "numa", target_os = "linux"));
+
+/// Enables pinning of the hashing threads, must be called before any pool is built.
+pub fn set_pin_threads(enabled: bool) {
+    let _ = PIN_THREADS.set(enabled && SUPPORTED);
+}
+
+pub fn pin_threads() -> bool {
+    PIN_THREADS.get().copied().unwrap_or(false)
+}
+
+/// CPUs of each NUMA node, a single node when the topology is unknown.
+fn nodes() -> &'static [Vec<usize>] {
+    NODES.get_or_init(|| {
+        let nodes = read_nodes();
+        if nodes.is_empty() {
+            vec![Vec::new()]
+        } else {
+            nodes
+        }
+    })
+}
+
+pub fn node_count() -> usize {
+    if pin_threads() { nodes().len() } else { 1 }
+}
+
+/// Node the calling thread is pinned to, 0 for threads that are not pinned.
+pub fn current_node() -> usize {
+    CURRENT_NODE.with(Cell::get)
+}
+
+/// Pins the calling pool thread: threads go round-robin over the nodes, then over the
+/// CPUs of their node. Buffers it allocates afterwards are placed on that node by the
+/// kernel's first-touch policy.
+pub fn pin_current_thread(thread_index: usize) {
+    let nodes = nodes();
+    let node = thread_index % nodes.len();
+    let cpus = &nodes[node];
+    if cpus.is_empty() {
+        return;
+    }
+    if set_affinity(cpus[(thread_index / nodes.len()) % cpus.len()]) {
+        CURRENT_NODE.with(|current| current.set(node));
+    }
+}
+
+/// Parses `/sys/devices/system/node/node<N>/cpulist`, e.g. `0-7,16-23`.
+#[cfg(all(feature = "numa", target_os = "linux"))]
+fn read_nodes() -> Vec<Vec<usize>> {
+    let mut nodes: Vec<(usize, Vec<usize>)> = std::fs::read_dir("/sys/devices/system/node")
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let id = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("node")?
+                .parse()
+                .ok()?;
+            let list = std::fs::read_to_string(entry.path().join("cpulist")).ok()?;
+            let mut cpus = Vec::new();
+            for range in list.trim().split(',').filter(|r| !r.is_empty()) {
+                match range.split_once('-') {
+                    Some((start, end)) => {
+                        cpus.extend(start.parse::<usize>().ok()?..=end.parse().ok()?)
+                    }
+                    None => cpus.push(range.parse().ok()?),
+                }
+            }
+            Some((id, cpus))
+        })
+        .filter(|(_, cpus)| !cpus.is_empty())
+        .collect();
+    nodes.sort_by_key(|(id, _)| *id);
+    nodes.into_iter().map(|(_, cpus)| cpus).collect()
+}
+
+#[cfg(not(all(feature = "numa", target_os = "linux")))]
+fn read_nodes() -> Vec<Vec<usize>> {
+    Vec::new()
+}
+
+#[cfg(all(feature = "numa", target_os = "linux"))]
+fn set_affinity(cpu: usize) -> bool {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        libc::CPU_ZERO(&mut set);
+        libc::CPU_SET(cpu, &mut set);
+        libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) == 0
+    }
+}
+
+#[cfg(not(all(feature = "numa", target_os = "linux")))]
+fn set_affinity(_cpu: usize) -> bool {
+    false
+}
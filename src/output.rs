@@ -0,0 +1,138 @@
+use std::io::{Result as ioResult, Write};
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+use serde::Serialize;
+
+use crate::utils::{TrError, TrResult};
+
+/// How results are printed, set by `--format`.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Text for reading in a terminal, the output of earlier versions.
+    Human,
+    Json,
+    Csv,
+    Markdown,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" | "text" => Ok(Format::Human),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            "markdown" | "md" => Ok(Format::Markdown),
+            _ => Err(format!(
+                "Invalid format '{s}', expected human, json, csv or markdown"
+            )),
+        }
+    }
+}
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+
+/// Sets the format of info, verify, lint and fingerprint results, from `--format`.
+pub fn set_format(format: Format) {
+    let _ = FORMAT.set(format);
+}
+
+/// The `--format` given, else human.
+pub fn format() -> Format {
+    FORMAT.get().copied().unwrap_or(Format::Human)
+}
+
+/// Rows written by the csv and markdown formats.
+pub struct Table {
+    pub header: Vec<&'static str>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn new(header: Vec<&'static str>) -> Self {
+        Table {
+            header,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Writes RFC 4180 CSV, quoting fields holding a comma, quote or line break.
+    pub fn write_csv(&self, out: &mut dyn Write) -> ioResult<()> {
+        let line = |fields: Vec<String>| -> String {
+            fields
+                .into_iter()
+                .map(|field| {
+                    if field.contains([',', '"', '\n', '\r']) {
+                        format!("\"{}\"", field.replace('"', "\"\""))
+                    } else {
+                        field
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        writeln!(
+            out,
+            "{}",
+            line(self.header.iter().map(|h| h.to_string()).collect())
+        )?;
+        for row in &self.rows {
+            writeln!(out, "{}", line(row.clone()))?;
+        }
+        Ok(())
+    }
+
+    /// Writes a GitHub-flavored Markdown table, `|` escaped and line breaks as spaces.
+    pub fn write_markdown(&self, out: &mut dyn Write) -> ioResult<()> {
+        let line = |cells: Vec<String>| -> String {
+            let cells: Vec<String> = cells
+                .into_iter()
+                .map(|cell| cell.replace('|', "\\|").replace(['\r', '\n'], " "))
+                .collect();
+            format!("| {} |", cells.join(" | "))
+        };
+        writeln!(
+            out,
+            "{}",
+            line(self.header.iter().map(|h| h.to_string()).collect())
+        )?;
+        writeln!(out, "|{}", "---|".repeat(self.header.len()))?;
+        for row in &self.rows {
+            writeln!(out, "{}", line(row.clone()))?;
+        }
+        Ok(())
+    }
+}
+
+/// A result that can be printed in every `Format`. JSON is the serde form, CSV and
+/// Markdown write `table`, which holds the list part of the result such as the files.
+pub trait OutputFormat: Serialize {
+    fn write_human(&self, out: &mut dyn Write) -> ioResult<()>;
+
+    fn table(&self) -> Table;
+
+    fn write_markdown(&self, out: &mut dyn Write) -> ioResult<()> {
+        self.table().write_markdown(out)
+    }
+
+    fn write_as(&self, format: Format, out: &mut dyn Write) -> TrResult<()> {
+        match format {
+            Format::Human => self.write_human(out)?,
+            Format::Json => {
+                serde_json::to_writer_pretty(&mut *out, self)
+                    .map_err(|e| TrError::ParseError(format!("Failed to encode JSON: {e}")))?;
+                writeln!(out)?;
+            }
+            Format::Csv => self.table().write_csv(out)?,
+            Format::Markdown => self.write_markdown(out)?,
+        }
+        Ok(())
+    }
+
+    /// Writes to stdout in the `--format` given.
+    fn print(&self) -> TrResult<()> {
+        self.write_as(format(), &mut std::io::stdout().lock())
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::tr_file::TrFile;
+use crate::tr_info::{SHA1_HASH_SIZE, TrInfo};
+use crate::utils::TrResult;
+
+/// Files and pieces two torrents have in common, as seen from torrent A.
+pub struct Overlap {
+    pub matched_files: usize,
+    pub matched_size: usize,
+    /// Pieces of A whose bytes all come from files B has as well.
+    pub covered_pieces: usize,
+    pub covered_size: usize,
+    /// Runs of pieces with equal hashes at the same position of the same file,
+    /// as (A pieces, first B piece).
+    pub identical: Vec<(Range<usize>, usize)>,
+    pub same_piece_length: bool,
+}
+
+impl Overlap {
+    pub fn identical_pieces(&self) -> usize {
+        self.identical.iter().map(|(range, _)| range.len()).sum()
+    }
+}
+
+/// Matches files by their path below the torrent name and size, falling back to the
+/// file name and size when that is unique in B, then compares the pieces they cover.
+/// Padding files are never matched, their zeros need no source in B.
+pub fn overlap(a: &TrInfo, b: &TrInfo) -> TrResult<Overlap> {
+    let (a_files, b_files) = (a.tr_files()?, b.tr_files()?);
+    let b_content = || b_files.iter().enumerate().filter(|(_, f)| !f.padding);
+    let by_path: HashMap<(&[String], usize), usize> = b_content()
+        .map(|(i, f)| ((f.path.as_slice(), f.length), i))
+        .collect();
+    let mut by_name: HashMap<(Option<&String>, usize), Option<usize>> = HashMap::new();
+    for (i, f) in b_content() {
+        by_name
+            .entry((f.path.last(), f.length))
+            .and_modify(|unique| *unique = None)
+            .or_insert(Some(i));
+    }
+    let matches: Vec<Option<usize>> = a_files
+        .iter()
+        .map(|f| {
+            if f.padding {
+                return None;
+            }
+            by_path
+                .get(&(f.path.as_slice(), f.length))
+                .copied()
+                .or_else(|| by_name.get(&(f.path.last(), f.length)).copied().flatten())
+        })
+        .collect();
+
+    let (a_starts, b_starts) = (file_starts(&a_files), file_starts(&b_files));
+    let a_total = a.total_size();
+    let same_piece_length = a.piece_length == b.piece_length;
+    let mut covered_pieces = 0;
+    let mut covered_size = 0;
+    let mut identical: Vec<(Range<usize>, usize)> = Vec::new();
+    for (piece, a_hash) in a.pieces.chunks_exact(SHA1_HASH_SIZE).enumerate() {
+        let start = piece * a.piece_length;
+        let end = (start + a.piece_length).min(a_total);
+        let files = files_in(&a_starts, &a_files, start..end).filter(|&i| !a_files[i].padding);
+        if !files.clone().all(|i| matches[i].is_some()) {
+            continue;
+        }
+        covered_pieces += 1;
+        covered_size += end - start;
+
+        // a piece inside one file, padding aside, lines up with a B piece when the file
+        // starts at the same offset modulo the piece length in both torrents
+        let [file] = files.collect::<Vec<_>>()[..] else {
+            continue;
+        };
+        let Some(b_file) = matches[file] else {
+            continue;
+        };
+        let b_start = b_starts[b_file] + (start - a_starts[file]);
+        if !same_piece_length || !b_start.is_multiple_of(b.piece_length) {
+            continue;
+        }
+        let b_piece = b_start / b.piece_length;
+        let b_end = (b_start + b.piece_length).min(b.total_size());
+        if b_end - b_start != end - start
+            || b.pieces
+                .get(b_piece * SHA1_HASH_SIZE..(b_piece + 1) * SHA1_HASH_SIZE)
+                != Some(a_hash)
+        {
+            continue;
+        }
+        match identical.last_mut() {
+            Some((range, b_first)) if range.end == piece && *b_first + range.len() == b_piece => {
+                range.end += 1
+            }
+            _ => identical.push((piece..piece + 1, b_piece)),
+        }
+    }
+
+    Ok(Overlap {
+        matched_files: matches.iter().flatten().count(),
+        matched_size: a_files
+            .iter()
+            .zip(&matches)
+            .filter(|(_, m)| m.is_some())
+            .map(|(f, _)| f.length)
+            .sum(),
+        covered_pieces,
+        covered_size,
+        identical,
+        same_piece_length,
+    })
+}
+
+fn file_starts(files: &[TrFile]) -> Vec<usize> {
+    files
+        .iter()
+        .scan(0, |offset, f| {
+            let start = *offset;
+            *offset += f.length;
+            Some(start)
+        })
+        .collect()
+}
+
+/// Indices of the files holding bytes of `range`.
+fn files_in(
+    starts: &[usize],
+    files: &[TrFile],
+    range: Range<usize>,
+) -> impl Iterator<Item = usize> + Clone {
+    let first = starts.partition_point(|&start| start <= range.start).max(1) - 1;
+    (first..files.len())
+        .take_while(move |&i| starts[i] < range.end)
+        .filter(move |&i| starts[i] + files[i].length > range.start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, length: usize) -> TrFile {
+        TrFile {
+            length,
+            path: path.split('/').map(String::from).collect(),
+            padding: path.starts_with(".pad/"),
+            symlink: None,
+        }
+    }
+
+    fn info(files: Vec<TrFile>, pieces: &[u8]) -> TrInfo {
+        TrInfo {
+            collections: None,
+            files: Some(files),
+            length: None,
+            name: Some(String::from("t")),
+            piece_length: 16,
+            pieces: pieces
+                .iter()
+                .flat_map(|&hash| [hash; SHA1_HASH_SIZE])
+                .collect(),
+            private: false,
+            source: None,
+            meta_version: None,
+            v2: None,
+            extra: Default::default(),
+        }
+    }
+
+    #[test]
+    fn padding_files_are_not_matched() {
+        let a = info(
+            vec![file("x", 10), file(".pad/6", 6), file("y", 16)],
+            &[1, 2],
+        );
+        let b = info(
+            vec![file("x", 10), file(".pad/6", 6), file("z", 16)],
+            &[1, 3],
+        );
+        let result = overlap(&a, &b).unwrap();
+        assert_eq!(result.matched_files, 1);
+        assert_eq!(result.matched_size, 10);
+        // the piece of x and its padding is covered and identical, the one of y is not
+        assert_eq!(result.covered_pieces, 1);
+        assert_eq!(result.covered_size, 16);
+        assert_eq!(result.identical, vec![(0..1, 0)]);
+    }
+
+    #[test]
+    fn files_match_by_path_or_unique_name() {
+        let a = info(vec![file("d/x", 16), file("e/y", 16)], &[1, 2]);
+        let b = info(vec![file("other/y", 16), file("d/x", 16)], &[2, 1]);
+        let result = overlap(&a, &b).unwrap();
+        assert_eq!(result.matched_files, 2);
+        assert_eq!(result.covered_pieces, 2);
+        assert_eq!(result.identical, vec![(0..1, 1), (1..2, 0)]);
+    }
+}
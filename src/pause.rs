@@ -0,0 +1,252 @@
+use std::io::IsTerminal;
+#[cfg(unix)]
+use std::sync::atomic::AtomicI32;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::utils::Progress;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static DEADLINE: OnceLock<Instant> = OnceLock::new();
+
+/// Blocks the calling hashing thread while hashing is paused.
+pub fn wait_if_paused() {
+    while PAUSED.load(Ordering::Relaxed) {
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Stops hashing of pieces not started by `deadline`, for `--max-duration`.
+pub fn set_deadline(deadline: Instant) {
+    let _ = DEADLINE.set(deadline);
+}
+
+/// Whether the time limit ran out; pieces already being read are finished.
+pub fn time_up() -> bool {
+    DEADLINE
+        .get()
+        .is_some_and(|deadline| Instant::now() >= *deadline)
+}
+
+fn toggle() {
+    PAUSED.fetch_xor(true, Ordering::Relaxed);
+}
+
+/// Listens for `p` on the terminal (and SIGUSR1 on unix) while hashing runs.
+pub struct PauseListener {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl PauseListener {
+    pub fn start(progress: &Progress) -> Self {
+        #[cfg(unix)]
+        install_signal_handler();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = Arc::clone(&stop);
+            let progress = progress.clone();
+            let read_keys = std::io::stdin().is_terminal();
+            thread::spawn(move || listen(&stop, progress, read_keys))
+        };
+        PauseListener {
+            stop,
+            handle: Some(handle),
+        }
+    }
+}
+
+impl Drop for PauseListener {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        PAUSED.store(false, Ordering::Relaxed);
+    }
+}
+
+fn listen(stop: &AtomicBool, progress: Progress, read_keys: bool) {
+    let mut keys = if read_keys { KeyReader::open() } else { None };
+    let mut was_paused = PAUSED.load(Ordering::Relaxed);
+    while !stop.load(Ordering::Relaxed) {
+        match keys.as_mut() {
+            Some(reader) => {
+                if reader.poll_key() == Some(b'p') {
+                    toggle();
+                }
+            }
+            None => thread::sleep(POLL_INTERVAL),
+        }
+
+        let paused = PAUSED.load(Ordering::Relaxed);
+        if paused != was_paused {
+            let msg = if paused {
+                "I: Hashing paused, press p to resume."
+            } else {
+                "I: Hashing resumed."
+            };
+            progress.println(msg);
+            was_paused = paused;
+        }
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_sigusr1(_: libc::c_int) {
+    toggle();
+}
+
+#[cfg(unix)]
+fn install_signal_handler() {
+    let handler: extern "C" fn(libc::c_int) = on_sigusr1;
+    unsafe {
+        libc::signal(libc::SIGUSR1, handler as libc::sighandler_t);
+    }
+}
+
+/// Terminal in no-echo mode while keys are read, -1 otherwise, and its settings before.
+/// A kill skips `Drop`, so the handlers of `restore_on_kill` put the settings back.
+#[cfg(unix)]
+static RAW_TTY: AtomicI32 = AtomicI32::new(-1);
+#[cfg(unix)]
+static ORIG_TERMIOS: OnceLock<libc::termios> = OnceLock::new();
+
+#[cfg(unix)]
+const KILL_SIGNALS: [libc::c_int; 4] = [libc::SIGINT, libc::SIGTERM, libc::SIGHUP, libc::SIGQUIT];
+
+#[cfg(unix)]
+extern "C" fn on_kill(signal: libc::c_int) {
+    let fd = RAW_TTY.swap(-1, Ordering::SeqCst);
+    if let (true, Some(orig)) = (fd >= 0, ORIG_TERMIOS.get()) {
+        unsafe {
+            libc::tcsetattr(fd, libc::TCSANOW, orig);
+        }
+    }
+    // die of the signal as without the handler, so the exit status stays the same
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
+
+/// Installs `on_kill` for the signals that end the process, except where they are ignored,
+/// as for a background job without a terminal.
+#[cfg(unix)]
+fn restore_on_kill() {
+    let handler: extern "C" fn(libc::c_int) = on_kill;
+    for signal in KILL_SIGNALS {
+        unsafe {
+            if libc::signal(signal, handler as libc::sighandler_t) == libc::SIG_IGN {
+                libc::signal(signal, libc::SIG_IGN);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+struct KeyReader {
+    tty: std::fs::File,
+    orig: libc::termios,
+}
+
+#[cfg(unix)]
+impl KeyReader {
+    fn open() -> Option<Self> {
+        use std::os::fd::AsRawFd;
+
+        let tty = std::fs::File::open("/dev/tty").ok()?;
+        let fd = tty.as_raw_fd();
+        let mut orig: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(fd, &mut orig) } != 0 {
+            return None;
+        }
+        let _ = ORIG_TERMIOS.set(orig);
+        restore_on_kill();
+        // no line buffering and no echo, signals (Ctrl-C) keep working
+        let mut raw = orig;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 0;
+        raw.c_cc[libc::VTIME] = 0;
+        if unsafe { libc::tcsetattr(fd, libc::TCSANOW, &raw) } != 0 {
+            return None;
+        }
+        RAW_TTY.store(fd, Ordering::SeqCst);
+        Some(KeyReader { tty, orig })
+    }
+
+    fn poll_key(&mut self) -> Option<u8> {
+        use std::io::Read;
+        use std::os::fd::AsRawFd;
+
+        let mut pfd = libc::pollfd {
+            fd: self.tty.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut pfd, 1, POLL_INTERVAL.as_millis() as libc::c_int) } <= 0 {
+            return None;
+        }
+        let mut key = [0u8; 1];
+        match self.tty.read(&mut key) {
+            Ok(1) => Some(key[0]),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for KeyReader {
+    fn drop(&mut self) {
+        use std::os::fd::AsRawFd;
+
+        RAW_TTY.store(-1, Ordering::SeqCst);
+        unsafe {
+            libc::tcsetattr(self.tty.as_raw_fd(), libc::TCSANOW, &self.orig);
+        }
+    }
+}
+
+#[cfg(windows)]
+unsafe extern "C" {
+    fn _kbhit() -> i32;
+    fn _getch() -> i32;
+}
+
+#[cfg(windows)]
+struct KeyReader;
+
+#[cfg(windows)]
+impl KeyReader {
+    fn open() -> Option<Self> {
+        Some(KeyReader)
+    }
+
+    fn poll_key(&mut self) -> Option<u8> {
+        if unsafe { _kbhit() } != 0 {
+            u8::try_from(unsafe { _getch() }).ok()
+        } else {
+            thread::sleep(POLL_INTERVAL);
+            None
+        }
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+struct KeyReader;
+
+#[cfg(not(any(unix, windows)))]
+impl KeyReader {
+    fn open() -> Option<Self> {
+        None
+    }
+
+    fn poll_key(&mut self) -> Option<u8> {
+        None
+    }
+}
@@ -0,0 +1,150 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use crate::tr_file::TrFile;
+use crate::utils::human_size;
+
+const MAX_DISPLAYED_SLOW_FILES: usize = 10;
+const MIN_SLOW_FILE_SIZE: u64 = 1 << 20; // ignore files too small to measure
+const SLOW_FILE_RATIO: f64 = 0.5; // slower than half the overall rate
+
+/// Bytes read and time spent reading, per file of one hashing job.
+pub struct PerfStats {
+    rel_paths: Vec<String>,
+    full_paths: Vec<PathBuf>,
+    bytes: Vec<AtomicU64>,
+    nanos: Vec<AtomicU64>,
+    /// Pieces taken from the piece cache instead of read, `None` when hashed without one.
+    pub reused_pieces: Option<usize>,
+}
+
+struct Throughput {
+    bytes: u64,
+    nanos: u64,
+}
+
+impl Throughput {
+    fn add(&mut self, bytes: u64, nanos: u64) {
+        self.bytes += bytes;
+        self.nanos += nanos;
+    }
+
+    fn rate(&self) -> f64 {
+        if self.nanos == 0 {
+            0.0
+        } else {
+            self.bytes as f64 / (self.nanos as f64 / 1e9)
+        }
+    }
+
+    fn line(&self) -> String {
+        format!(
+            "{} in {:.2?} ({}/s)",
+            human_size(self.bytes as usize),
+            Duration::from_nanos(self.nanos),
+            human_size(self.rate() as usize)
+        )
+    }
+}
+
+impl PerfStats {
+    pub fn new(tr_files: &[TrFile], full_paths: Vec<PathBuf>) -> Self {
+        PerfStats {
+            rel_paths: tr_files.iter().map(|f| f.path.join("/")).collect(),
+            full_paths,
+            bytes: tr_files.iter().map(|_| AtomicU64::new(0)).collect(),
+            nanos: tr_files.iter().map(|_| AtomicU64::new(0)).collect(),
+            reused_pieces: None,
+        }
+    }
+
+    pub fn record(&self, file_index: usize, bytes: usize, elapsed: Duration) {
+        self.bytes[file_index].fetch_add(bytes as u64, Ordering::Relaxed);
+        self.nanos[file_index].fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    fn file(&self, i: usize) -> Throughput {
+        Throughput {
+            bytes: self.bytes[i].load(Ordering::Relaxed),
+            nanos: self.nanos[i].load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn print_summary(&self) {
+        println!("Performance Summary:");
+        let mut total = Throughput { bytes: 0, nanos: 0 };
+        let mut by_entry: BTreeMap<&str, Throughput> = BTreeMap::new();
+        let mut by_device: BTreeMap<String, Throughput> = BTreeMap::new();
+        for i in 0..self.rel_paths.len() {
+            let file = self.file(i);
+            if file.bytes == 0 {
+                continue;
+            }
+            total.add(file.bytes, file.nanos);
+            let entry = self.rel_paths[i].split('/').next().unwrap_or("");
+            by_entry
+                .entry(entry)
+                .or_insert(Throughput { bytes: 0, nanos: 0 })
+                .add(file.bytes, file.nanos);
+            if let Some(device) = device_id(&self.full_paths[i]) {
+                by_device
+                    .entry(device)
+                    .or_insert(Throughput { bytes: 0, nanos: 0 })
+                    .add(file.bytes, file.nanos);
+            }
+        }
+        println!("  Total read: {}", total.line());
+
+        if by_entry.len() > 1 {
+            println!("  By top-level entry:");
+            for (entry, stat) in &by_entry {
+                println!("    {entry}: {}", stat.line());
+            }
+        }
+        if by_device.len() > 1 {
+            println!("  By device:");
+            for (device, stat) in &by_device {
+                println!("    {device}: {}", stat.line());
+            }
+        }
+
+        let threshold = total.rate() * SLOW_FILE_RATIO;
+        let mut slow: Vec<(usize, f64)> = (0..self.rel_paths.len())
+            .map(|i| (i, self.file(i)))
+            .filter(|(_, f)| f.bytes >= MIN_SLOW_FILE_SIZE && f.rate() < threshold)
+            .map(|(i, f)| (i, f.rate()))
+            .collect();
+        slow.sort_by(|a, b| a.1.total_cmp(&b.1));
+        if !slow.is_empty() {
+            println!("  Slow files (below {}/s):", human_size(threshold as usize));
+            for (i, _) in slow.iter().take(MAX_DISPLAYED_SLOW_FILES) {
+                let name = if self.rel_paths[*i].is_empty() {
+                    self.full_paths[*i].display().to_string()
+                } else {
+                    self.rel_paths[*i].clone()
+                };
+                println!("    - {name}: {}", self.file(*i).line());
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn device_id(path: &std::path::Path) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let dev = std::fs::metadata(path).ok()?.dev();
+    Some(format!("dev {}:{}", libc::major(dev), libc::minor(dev)))
+}
+
+#[cfg(not(unix))]
+fn device_id(path: &std::path::Path) -> Option<String> {
+    use std::path::Component;
+
+    match path.canonicalize().ok()?.components().next()? {
+        Component::Prefix(prefix) => Some(prefix.as_os_str().to_string_lossy().to_string()),
+        _ => None,
+    }
+}
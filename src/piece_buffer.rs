@@ -0,0 +1,152 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::numa;
+
+// totals over all pools of the process, for the resource report
+static BUFFERS: AtomicUsize = AtomicUsize::new(0);
+static BUFFER_BYTES: AtomicUsize = AtomicUsize::new(0);
+static IN_USE: AtomicUsize = AtomicUsize::new(0);
+
+/// Buffers checked out, buffers allocated and their bytes, over all pools.
+pub fn usage() -> (usize, usize, usize) {
+    (
+        IN_USE.load(Ordering::Relaxed),
+        BUFFERS.load(Ordering::Relaxed),
+        BUFFER_BYTES.load(Ordering::Relaxed),
+    )
+}
+
+/// Read buffers of one fixed size, shared by the hashing threads of a single job. With
+/// pinned threads there is one free list per NUMA node, so buffers stay on the node
+/// whose thread allocated them.
+pub struct PieceBufferPool {
+    size: usize,
+    free: Vec<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl PieceBufferPool {
+    pub fn new(size: usize) -> Self {
+        PieceBufferPool {
+            size,
+            free: (0..numa::node_count())
+                .map(|_| Mutex::new(Vec::new()))
+                .collect(),
+        }
+    }
+
+    /// Takes a buffer from the pool, allocating one if all are in use.
+    pub fn get(&self) -> PieceBuffer<'_> {
+        let node = numa::current_node().min(self.free.len() - 1);
+        let buf = self.free[node]
+            .lock()
+            .ok()
+            .and_then(|mut free| free.pop())
+            .unwrap_or_else(|| {
+                BUFFERS.fetch_add(1, Ordering::Relaxed);
+                BUFFER_BYTES.fetch_add(self.size, Ordering::Relaxed);
+                vec![0u8; self.size]
+            });
+        IN_USE.fetch_add(1, Ordering::Relaxed);
+        PieceBuffer {
+            buf,
+            node,
+            pool: self,
+        }
+    }
+
+    fn put(&self, buf: Vec<u8>, node: usize) {
+        IN_USE.fetch_sub(1, Ordering::Relaxed);
+        match self.free[node].lock() {
+            Ok(mut free) => free.push(buf),
+            Err(_) => release(1, self.size),
+        }
+    }
+}
+
+impl Drop for PieceBufferPool {
+    fn drop(&mut self) {
+        for free in &self.free {
+            let count = free.lock().map_or(0, |free| free.len());
+            release(count, self.size);
+        }
+    }
+}
+
+fn release(count: usize, size: usize) {
+    BUFFERS.fetch_sub(count, Ordering::Relaxed);
+    BUFFER_BYTES.fetch_sub(count * size, Ordering::Relaxed);
+}
+
+/// A buffer checked out from a [`PieceBufferPool`], returned to it on drop.
+pub struct PieceBuffer<'a> {
+    buf: Vec<u8>,
+    node: usize,
+    pool: &'a PieceBufferPool,
+}
+
+impl Deref for PieceBuffer<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+impl DerefMut for PieceBuffer<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf
+    }
+}
+
+impl Drop for PieceBuffer<'_> {
+    fn drop(&mut self) {
+        self.pool.put(std::mem::take(&mut self.buf), self.node);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_have_pool_size() {
+        let pool = PieceBufferPool::new(16);
+        let buf = pool.get();
+        assert_eq!(buf.len(), 16);
+    }
+
+    #[test]
+    fn buffers_are_reused() {
+        let pool = PieceBufferPool::new(8);
+        let ptr = {
+            let mut buf = pool.get();
+            buf[0] = 1;
+            buf.as_ptr()
+        };
+        let buf = pool.get();
+        assert_eq!(buf.as_ptr(), ptr);
+        assert_eq!(buf.len(), 8);
+    }
+
+    #[test]
+    fn concurrent_buffers_are_distinct() {
+        let pool = PieceBufferPool::new(4);
+        let a = pool.get();
+        let b = pool.get();
+        assert_ne!(a.as_ptr(), b.as_ptr());
+        drop(a);
+        drop(b);
+        assert_eq!(pool.free[0].lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn pools_of_different_jobs_do_not_share_sizes() {
+        let large = PieceBufferPool::new(32);
+        drop(large.get());
+        let small = PieceBufferPool::new(4);
+        assert_eq!(small.get().len(), 4);
+        assert_eq!(large.get().len(), 32);
+    }
+}
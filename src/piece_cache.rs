@@ -0,0 +1,114 @@
+use std::collections::HashMap;
+use std::fs::{metadata, read_to_string};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::UNIX_EPOCH;
+
+use sha1::{Digest, Sha1};
+
+use crate::tr_info::{PieceHash, SHA1_HASH_SIZE};
+use crate::utils::{TrResult, write_replacing};
+
+/// Serializes saves of parallel batch jobs sharing one cache file.
+static SAVE_LOCK: Mutex<()> = Mutex::new(());
+
+/// On-disk map from piece identity to piece hash. A piece is identified by the
+/// (path, size, mtime) of every file it spans plus the offsets read from them, so a
+/// piece is only reused when all of its bytes come from unchanged files.
+pub struct PieceCache {
+    path: Option<PathBuf>,
+    entries: HashMap<PieceHash, PieceHash>,
+    dirty: bool,
+}
+
+impl PieceCache {
+    /// Loads the cache, a missing file starts an empty one; malformed lines are ignored.
+    pub fn load(path: &str) -> TrResult<Self> {
+        let mut entries = HashMap::new();
+        if Path::new(path).exists() {
+            for line in read_to_string(path)?.lines() {
+                let Some((key, hash)) = line.split_once(' ') else {
+                    continue;
+                };
+                let (mut key_arr, mut hash_arr) = ([0u8; SHA1_HASH_SIZE], [0u8; SHA1_HASH_SIZE]);
+                if hex::decode_to_slice(key, &mut key_arr).is_ok()
+                    && hex::decode_to_slice(hash, &mut hash_arr).is_ok()
+                {
+                    entries.insert(key_arr, hash_arr);
+                }
+            }
+        }
+        Ok(PieceCache {
+            path: Some(PathBuf::from(path)),
+            entries,
+            dirty: false,
+        })
+    }
+
+    /// Cache that lives only for this run, `save` is a no-op.
+    pub fn in_memory() -> Self {
+        PieceCache {
+            path: None,
+            entries: HashMap::new(),
+            dirty: false,
+        }
+    }
+
+    /// Identity of a file as `path|size|mtime`, `None` if it cannot be read; empty for
+    /// padding files.
+    pub fn file_identity(path: &Path) -> Option<String> {
+        // padding files, given with an empty path, are zeros whatever happens on disk
+        if path.as_os_str().is_empty() {
+            return Some(String::new());
+        }
+        let meta = metadata(path).ok()?;
+        let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        let path = path.canonicalize().ok()?;
+        Some(format!(
+            "{}|{}|{}",
+            path.display(),
+            meta.len(),
+            mtime.as_nanos()
+        ))
+    }
+
+    /// Key of a piece made of `(file identity, offset, length)` segments.
+    pub fn piece_key<'a>(segments: impl Iterator<Item = (&'a str, usize, usize)>) -> PieceHash {
+        let mut hasher = Sha1::new();
+        for (identity, offset, length) in segments {
+            hasher.update(format!("{identity}|{offset}|{length}\n"));
+        }
+        let mut key = [0u8; SHA1_HASH_SIZE];
+        key.copy_from_slice(&hasher.finalize());
+        key
+    }
+
+    pub fn get(&self, key: &PieceHash) -> Option<PieceHash> {
+        self.entries.get(key).copied()
+    }
+
+    pub fn insert(&mut self, key: PieceHash, hash: PieceHash) {
+        if self.entries.insert(key, hash) != Some(hash) {
+            self.dirty = true;
+        }
+    }
+
+    /// Writes the cache back if anything changed, through a temporary file. Entries saved
+    /// meanwhile by another job of the same batch are kept.
+    pub fn save(&self) -> TrResult<()> {
+        let Some(path) = self.path.as_ref().filter(|_| self.dirty) else {
+            return Ok(());
+        };
+        let _guard = SAVE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut entries = PieceCache::load(&path.to_string_lossy())?.entries;
+        entries.extend(&self.entries);
+        let mut out = String::with_capacity(entries.len() * (SHA1_HASH_SIZE * 4 + 2));
+        for (key, hash) in &entries {
+            out.push_str(&hex::encode(key));
+            out.push(' ');
+            out.push_str(&hex::encode(hash));
+            out.push('\n');
+        }
+        write_replacing(path, out)
+    }
+}
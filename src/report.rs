@@ -0,0 +1,557 @@
+use std::collections::HashSet;
+use std::fs::{read_to_string, remove_file};
+use std::io::{Result as ioResult, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::datetime::format_timestamp;
+use crate::output::{OutputFormat, Table};
+use crate::torrent::Torrent;
+use crate::tr_info::{FailedFile, VerifySummary};
+use crate::utils::{TrError, TrResult, check_write, human_size, write_replacing};
+
+/// Verify result as written by `--report` and `--json`, read back by `--compare-with`.
+#[derive(Serialize, Deserialize)]
+pub struct VerifyReport {
+    pub torrent: String,
+    pub target: String,
+    pub date: i64,
+    pub total_pieces: usize,
+    pub failed_pieces: usize,
+    pub failed_files: Vec<String>,
+    /// Pieces left for a later session by `--max-duration`, 0 for a complete verify.
+    #[serde(default)]
+    pub unchecked_pieces: usize,
+    #[serde(default)]
+    pub total_files: usize,
+    #[serde(default)]
+    pub failed_piece_indices: Vec<usize>,
+    /// Pieces and files left out by `--trust-resume`, neither passed nor failed.
+    #[serde(default)]
+    pub skipped_pieces: usize,
+    #[serde(default)]
+    pub skipped_files: usize,
+    /// `failed_files` with their length and why they failed; older reports only have
+    /// the paths.
+    #[serde(default)]
+    pub failures: Vec<FailedFile>,
+}
+
+impl VerifyReport {
+    pub fn new(torrent: &str, target: &str, summary: VerifySummary) -> Self {
+        VerifyReport {
+            torrent: torrent.to_string(),
+            target: target.to_string(),
+            date: chrono::Local::now().timestamp(),
+            total_pieces: summary.total_pieces,
+            failed_pieces: summary.failed_pieces,
+            failed_files: summary
+                .failed_files
+                .iter()
+                .map(|f| f.path.clone())
+                .collect(),
+            unchecked_pieces: summary.unchecked.len(),
+            total_files: summary.total_files,
+            failed_piece_indices: summary.failed_indices,
+            skipped_pieces: summary.skipped_pieces.unwrap_or(0),
+            skipped_files: summary.skipped_files.unwrap_or(0),
+            failures: summary.failed_files,
+        }
+    }
+
+    pub fn to_json(&self) -> TrResult<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| TrError::ParseError(format!("Failed to encode report: {e}")))
+    }
+
+    pub fn read(path: &str) -> TrResult<Self> {
+        serde_json::from_str(&read_to_string(path)?)
+            .map_err(|e| TrError::ParseError(format!("Invalid report '{path}': {e}")))
+    }
+
+    pub fn write(&self, path: &str) -> TrResult<()> {
+        write_replacing(Path::new(path), self.to_json()? + "\n")
+    }
+
+    /// Prints the files failing now that passed in `previous`, and the ones that recovered.
+    pub fn print_delta(&self, previous: &VerifyReport) {
+        let _ = self.write_delta(&mut std::io::stdout().lock(), previous);
+    }
+
+    fn write_delta(&self, out: &mut dyn Write, previous: &VerifyReport) -> ioResult<()> {
+        let before: HashSet<&String> = previous.failed_files.iter().collect();
+        let now: HashSet<&String> = self.failed_files.iter().collect();
+        let newly_failing: Vec<&String> = self
+            .failed_files
+            .iter()
+            .filter(|f| !before.contains(f))
+            .collect();
+        let recovered: Vec<&String> = previous
+            .failed_files
+            .iter()
+            .filter(|f| !now.contains(f))
+            .collect();
+
+        writeln!(
+            out,
+            "\nChanges since the report of {}:",
+            format_timestamp(previous.date)
+        )?;
+        if newly_failing.is_empty() && recovered.is_empty() {
+            return writeln!(out, "  No changes, {} files still failing.", now.len());
+        }
+        for file in &newly_failing {
+            writeln!(out, "  + {file} (newly failing)")?;
+        }
+        for file in &recovered {
+            writeln!(out, "  - {file} (recovered)")?;
+        }
+        writeln!(
+            out,
+            "  {} newly failing, {} recovered, {} still failing.",
+            newly_failing.len(),
+            recovered.len(),
+            now.len() - newly_failing.len()
+        )
+    }
+}
+
+impl OutputFormat for VerifyReport {
+    fn write_human(&self, out: &mut dyn Write) -> ioResult<()> {
+        writeln!(out, "Verification Result: {}", self.torrent)?;
+        writeln!(
+            out,
+            "Pieces: {} total, {} failed, {} unchecked, {} skipped",
+            self.total_pieces, self.failed_pieces, self.unchecked_pieces, self.skipped_pieces
+        )?;
+        writeln!(
+            out,
+            "Files:  {} total, {} failed, {} skipped",
+            self.total_files,
+            self.failed_files.len(),
+            self.skipped_files
+        )?;
+        for file in &self.failures {
+            writeln!(out, "- {} [{}]", file.path, file.reason.label())?;
+        }
+        Ok(())
+    }
+
+    /// The failed files.
+    fn table(&self) -> Table {
+        let mut table = Table::new(vec!["path", "length", "reason"]);
+        for file in &self.failures {
+            table.rows.push(vec![
+                file.path.clone(),
+                file.length.to_string(),
+                file.reason.label().to_string(),
+            ]);
+        }
+        table
+    }
+
+    fn write_markdown(&self, out: &mut dyn Write) -> ioResult<()> {
+        writeln!(out, "## Verification of {}\n", self.torrent)?;
+        writeln!(out, "- Target: {}", self.target)?;
+        writeln!(out, "- Date: {}", format_timestamp(self.date))?;
+        writeln!(
+            out,
+            "- Pieces: {} total, {} failed, {} unchecked, {} skipped",
+            self.total_pieces, self.failed_pieces, self.unchecked_pieces, self.skipped_pieces
+        )?;
+        writeln!(
+            out,
+            "- Files: {} total, {} failed, {} skipped",
+            self.total_files,
+            self.failed_files.len(),
+            self.skipped_files
+        )?;
+        if !self.failures.is_empty() {
+            writeln!(out)?;
+            self.table().write_markdown(out)?;
+        }
+        Ok(())
+    }
+}
+
+/// One file of an `InfoReport`, `path` relative to the torrent name; a single-file
+/// torrent has one file named like the torrent.
+#[derive(Serialize)]
+pub struct InfoFile {
+    pub path: String,
+    pub length: usize,
+}
+
+/// Info mode result of one torrent for `--format` other than human, which prints the
+/// torrent itself.
+#[derive(Serialize)]
+pub struct InfoReport {
+    pub torrent: String,
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info_hash_v1: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub info_hash_v2: Option<String>,
+    pub magnet: Option<String>,
+    pub trackers: Vec<Vec<String>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub web_seeds: Vec<String>,
+    pub comment: Option<String>,
+    pub created_by: Option<String>,
+    pub creation_date: Option<i64>,
+    pub private: bool,
+    pub source: Option<String>,
+    pub piece_length: usize,
+    pub piece_count: usize,
+    pub total_size: usize,
+    /// Padding files left out.
+    pub files: Vec<InfoFile>,
+}
+
+impl InfoReport {
+    pub fn new(torrent_path: &str, torrent: &Torrent) -> Self {
+        let info = torrent.get_info();
+        let name = info.and_then(|info| info.name.clone());
+        let files = torrent
+            .files()
+            .iter()
+            .filter(|file| !file.padding)
+            .map(|file| InfoFile {
+                path: if file.path.is_empty() {
+                    name.clone().unwrap_or_default()
+                } else {
+                    file.path.join("/")
+                },
+                length: file.length,
+            })
+            .collect();
+        InfoReport {
+            torrent: torrent_path.to_string(),
+            name,
+            info_hash_v1: torrent.info_hash_v1(),
+            info_hash_v2: torrent.info_hash_v2(),
+            magnet: torrent.magnet_link(),
+            trackers: torrent.trackers(),
+            web_seeds: torrent.web_seeds().to_vec(),
+            comment: torrent.comment().map(str::to_string),
+            created_by: torrent.created_by().map(str::to_string),
+            creation_date: torrent.creation_date(),
+            private: info.is_some_and(|info| info.private),
+            source: info.and_then(|info| info.source.clone()),
+            piece_length: info.map_or(0, |info| info.piece_length),
+            piece_count: torrent.piece_count(),
+            total_size: torrent.total_size(),
+            files,
+        }
+    }
+}
+
+/// `InfoReport`s of the torrents given, a JSON array and one file table for all.
+#[derive(Serialize)]
+#[serde(transparent)]
+pub struct InfoReports(pub Vec<InfoReport>);
+
+impl OutputFormat for InfoReports {
+    fn write_human(&self, out: &mut dyn Write) -> ioResult<()> {
+        for report in &self.0 {
+            writeln!(out, "{}", report.torrent)?;
+            if let Some(name) = &report.name {
+                writeln!(out, "  Name: {name}")?;
+            }
+            if let Some(hash) = report
+                .info_hash_v1
+                .as_ref()
+                .or(report.info_hash_v2.as_ref())
+            {
+                writeln!(out, "  Info hash: {hash}")?;
+            }
+            writeln!(
+                out,
+                "  Size: {} in {} files",
+                human_size(report.total_size),
+                report.files.len()
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The files of every torrent.
+    fn table(&self) -> Table {
+        let mut table = Table::new(vec!["torrent", "path", "length"]);
+        for report in &self.0 {
+            for file in &report.files {
+                table.rows.push(vec![
+                    report.torrent.clone(),
+                    file.path.clone(),
+                    file.length.to_string(),
+                ]);
+            }
+        }
+        table
+    }
+
+    /// One section per torrent for upload descriptions and forum posts: the details, the
+    /// trackers and a file table.
+    fn write_markdown(&self, out: &mut dyn Write) -> ioResult<()> {
+        for (i, report) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(out)?;
+            }
+            report.write_markdown(out)?;
+        }
+        Ok(())
+    }
+}
+
+impl InfoReport {
+    fn write_markdown(&self, out: &mut dyn Write) -> ioResult<()> {
+        let name = self.name.as_deref().unwrap_or(&self.torrent);
+        writeln!(out, "## {name}\n")?;
+
+        let mut details = Table::new(vec!["", ""]);
+        let mut row = |label: &str, value: String| {
+            details.rows.push(vec![format!("**{label}**"), value]);
+        };
+        if let Some(v1) = &self.info_hash_v1 {
+            row("Info hash", format!("`{v1}`"));
+        }
+        if let Some(v2) = &self.info_hash_v2 {
+            row("Info hash v2", format!("`{v2}`"));
+        }
+        row(
+            "Size",
+            format!(
+                "{} ({} bytes) in {} files",
+                human_size(self.total_size),
+                self.total_size,
+                self.files.len()
+            ),
+        );
+        row(
+            "Pieces",
+            format!("{} of {}", self.piece_count, human_size(self.piece_length)),
+        );
+        row(
+            "Private",
+            String::from(if self.private { "yes" } else { "no" }),
+        );
+        if let Some(source) = &self.source {
+            row("Source", source.clone());
+        }
+        if let Some(created_by) = &self.created_by {
+            row("Created by", created_by.clone());
+        }
+        if let Some(date) = self.creation_date {
+            row("Created", format_timestamp(date));
+        }
+        if let Some(comment) = &self.comment {
+            row("Comment", comment.clone());
+        }
+        if let Some(magnet) = &self.magnet
+            && !self.private
+        {
+            row("Magnet", format!("`{magnet}`"));
+        }
+        details.write_markdown(out)?;
+
+        if !self.trackers.is_empty() {
+            writeln!(out, "\n### Trackers\n")?;
+            for (tier_id, tier) in self.trackers.iter().enumerate() {
+                for url in tier {
+                    // announce URLs of private trackers hold the passkey
+                    let url = if self.private {
+                        format!("{} (passkey hidden)", announce_host(url))
+                    } else {
+                        url.clone()
+                    };
+                    writeln!(out, "- Tier {tier_id}: {url}")?;
+                }
+            }
+        }
+        if !self.web_seeds.is_empty() {
+            writeln!(out, "\n### Web Seeds\n")?;
+            for url in &self.web_seeds {
+                writeln!(out, "- {url}")?;
+            }
+        }
+
+        writeln!(out, "\n### Files\n")?;
+        let mut files = Table::new(vec!["Path", "Size"]);
+        for file in &self.files {
+            files
+                .rows
+                .push(vec![file.path.clone(), human_size(file.length)]);
+        }
+        files.write_markdown(out)
+    }
+}
+
+/// Scheme and host of an announce URL, without the path and query carrying a passkey.
+fn announce_host(url: &str) -> String {
+    let (scheme, rest) = url.split_once("://").unwrap_or(("", url));
+    let authority = rest.split(['/', '?']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    if scheme.is_empty() {
+        host.to_string()
+    } else {
+        format!("{scheme}://{host}")
+    }
+}
+
+/// Progress of a verify spread over sessions by `--max-duration`, kept in `--state`.
+#[derive(Serialize, Deserialize)]
+pub struct VerifyState {
+    pub info_hash: String,
+    /// One character per piece: `.` not checked yet, `+` passed, `-` failed.
+    pub pieces: String,
+    pub failed_files: Vec<FailedFile>,
+    pub sessions: usize,
+}
+
+impl VerifyState {
+    pub fn new(info_hash: String, piece_count: usize) -> Self {
+        VerifyState {
+            info_hash,
+            pieces: ".".repeat(piece_count),
+            failed_files: Vec::new(),
+            sessions: 0,
+        }
+    }
+
+    /// Reads the state left by an earlier session, or starts a new one if there is none.
+    pub fn load(path: &str, info_hash: String, piece_count: usize) -> TrResult<Self> {
+        let content = match read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(VerifyState::new(info_hash, piece_count));
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let state: VerifyState = serde_json::from_str(&content)
+            .map_err(|e| TrError::ParseError(format!("Invalid verify state '{path}': {e}")))?;
+        if state.info_hash != info_hash || state.pieces.len() != piece_count {
+            return Err(TrError::InvalidTorrent(format!(
+                "verify state '{path}' belongs to another torrent"
+            )));
+        }
+        Ok(state)
+    }
+
+    /// Pieces still to check, as a piece selection for `verify`.
+    pub fn unchecked(&self) -> Vec<bool> {
+        self.pieces.bytes().map(|p| p == b'.').collect()
+    }
+
+    /// Marks the pieces checked by this session.
+    pub fn record(&mut self, selected: &[bool], summary: &VerifySummary) {
+        let failed: HashSet<usize> = summary.failed_indices.iter().copied().collect();
+        let unchecked: HashSet<usize> = summary.unchecked.iter().copied().collect();
+        self.pieces = self
+            .pieces
+            .bytes()
+            .enumerate()
+            .map(
+                |(i, p)| match (selected[i], failed.contains(&i), unchecked.contains(&i)) {
+                    (false, _, _) | (true, _, true) => p as char,
+                    (true, true, false) => '-',
+                    (true, false, false) => '+',
+                },
+            )
+            .collect();
+        for file in &summary.failed_files {
+            if !self.failed_files.iter().any(|f| f.path == file.path) {
+                self.failed_files.push(file.clone());
+            }
+        }
+        self.sessions += 1;
+    }
+
+    pub fn checked_count(&self) -> usize {
+        self.pieces.bytes().filter(|&p| p != b'.').count()
+    }
+
+    pub fn failed_indices(&self) -> Vec<usize> {
+        self.pieces
+            .bytes()
+            .enumerate()
+            .filter_map(|(i, p)| (p == b'-').then_some(i))
+            .collect()
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.pieces.bytes().filter(|&p| p == b'-').count()
+    }
+
+    pub fn is_complete(&self) -> bool {
+        !self.pieces.contains('.')
+    }
+
+    /// Saves the state for the next session, or removes it once every piece is checked.
+    pub fn save(&self, path: &str) -> TrResult<()> {
+        check_write(Path::new(path))?;
+        if self.is_complete() {
+            return match remove_file(path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            };
+        }
+        let json = serde_json::to_string(self)
+            .map_err(|e| TrError::ParseError(format!("Failed to encode verify state: {e}")))?;
+        write_replacing(Path::new(path), json + "\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(failed: &[&str]) -> VerifyReport {
+        VerifyReport {
+            torrent: String::from("t.torrent"),
+            target: String::from("t"),
+            date: 1700000000,
+            total_pieces: 10,
+            failed_pieces: failed.len(),
+            failed_files: failed.iter().map(|f| f.to_string()).collect(),
+            unchecked_pieces: 0,
+            total_files: 4,
+            failed_piece_indices: Vec::new(),
+            skipped_pieces: 0,
+            skipped_files: 0,
+            failures: Vec::new(),
+        }
+    }
+
+    fn delta(now: &[&str], before: &[&str]) -> String {
+        let mut out = Vec::new();
+        report(now).write_delta(&mut out, &report(before)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn delta_lists_new_and_recovered_failures() {
+        let header = format!(
+            "\nChanges since the report of {}:\n",
+            format_timestamp(1700000000)
+        );
+        assert_eq!(
+            delta(&["a", "c"], &["a", "b"]),
+            format!(
+                "{header}  + c (newly failing)\n  - b (recovered)\n  1 newly failing, 1 recovered, 1 still failing.\n"
+            )
+        );
+        assert_eq!(
+            delta(&["a"], &["a"]),
+            format!("{header}  No changes, 1 files still failing.\n")
+        );
+        assert_eq!(
+            delta(&[], &["a"]),
+            format!(
+                "{header}  - a (recovered)\n  0 newly failing, 1 recovered, 0 still failing.\n"
+            )
+        );
+    }
+}
@@ -0,0 +1,83 @@
+use std::fmt::{Display, Formatter, Result as fmtResult};
+
+use crate::piece_buffer;
+use crate::utils::human_size;
+
+/// Resource usage of this process at one moment, for tuning `-j`, `--read-buffer` and
+/// `--max-open-files` to container limits.
+pub struct Usage {
+    /// `None` where the platform does not expose it.
+    pub open_files: Option<usize>,
+    pub rss: Option<usize>,
+    pub buffers_in_use: usize,
+    pub buffers: usize,
+    pub buffer_bytes: usize,
+}
+
+impl Usage {
+    pub fn sample() -> Self {
+        let (buffers_in_use, buffers, buffer_bytes) = piece_buffer::usage();
+        Usage {
+            open_files: open_files(),
+            rss: rss(),
+            buffers_in_use,
+            buffers,
+            buffer_bytes,
+        }
+    }
+}
+
+impl Display for Usage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        let unknown = || String::from("n/a");
+        write!(
+            f,
+            "Resources: {} open files, RSS {}, read buffers {} of {} in use ({})",
+            self.open_files.map_or_else(unknown, |n| n.to_string()),
+            self.rss.map_or_else(unknown, human_size),
+            self.buffers_in_use,
+            self.buffers,
+            human_size(self.buffer_bytes)
+        )
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn open_files() -> Option<usize> {
+    // the directory handle of read_dir itself is listed too
+    Some(
+        std::fs::read_dir("/proc/self/fd")
+            .ok()?
+            .count()
+            .saturating_sub(1),
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn open_files() -> Option<usize> {
+    Some(std::fs::read_dir("/dev/fd").ok()?.count().saturating_sub(1))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn open_files() -> Option<usize> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn rss() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let kib = status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))?
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<usize>()
+        .ok()?;
+    Some(kib << 10)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn rss() -> Option<usize> {
+    None
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as fmtResult};
+use std::fs::read;
+
+use crate::bencode::{Bencode, parse_bencode};
+use crate::datetime::format_timestamp;
+use crate::utils::{TrError, TrResult, human_size};
+
+const BITFIELD_WIDTH: usize = 64;
+
+/// Client-side resume data, as written by libtorrent based clients (`.fastresume`).
+pub struct ResumeData {
+    pub file_format: Option<String>,
+    pub info_hash: Option<String>,
+    pub name: Option<String>,
+    pub save_path: Option<String>,
+    pub pieces: Option<Vec<bool>>,
+    pub trackers: Vec<Vec<String>>,
+    pub added_time: Option<i64>,
+    pub completed_time: Option<i64>,
+    pub total_uploaded: Option<i64>,
+    pub total_downloaded: Option<i64>,
+}
+
+fn get_string(dict: &HashMap<String, Bencode>, key: &str) -> Option<String> {
+    match dict.get(key) {
+        Some(Bencode::Bytes(b)) => Some(String::from_utf8_lossy(b).to_string()),
+        _ => None,
+    }
+}
+
+fn get_int(dict: &HashMap<String, Bencode>, key: &str) -> Option<i64> {
+    match dict.get(key) {
+        Some(Bencode::Int(i)) => Some(*i as i64),
+        Some(Bencode::UInt(i)) => Some(*i),
+        _ => None,
+    }
+}
+
+impl ResumeData {
+    pub fn read_resume(path: &str) -> TrResult<Self> {
+        let data = read(path)?;
+        let mut pos = 0;
+        let dict = match parse_bencode(&data, &mut pos)? {
+            Bencode::Dict(m) => m,
+            _ => {
+                return Err(TrError::InvalidTorrent(String::from(
+                    "resume data root is not a dictionary",
+                )));
+            }
+        };
+
+        let seed_mode = get_int(&dict, "seed_mode").is_some_and(|i| i != 0);
+        let pieces = match dict.get("pieces") {
+            // one byte per piece, bit 0 set when the piece is complete
+            Some(Bencode::Bytes(b)) => Some(b.iter().map(|&p| seed_mode || p & 1 != 0).collect()),
+            _ => None,
+        };
+
+        let mut trackers = Vec::new();
+        if let Some(Bencode::List(tiers)) = dict.get("trackers") {
+            for tier in tiers {
+                if let Bencode::List(urls) = tier {
+                    trackers.push(
+                        urls.iter()
+                            .filter_map(|url| match url {
+                                Bencode::Bytes(b) => Some(String::from_utf8_lossy(b).to_string()),
+                                _ => None,
+                            })
+                            .collect(),
+                    );
+                }
+            }
+        }
+
+        Ok(ResumeData {
+            file_format: get_string(&dict, "file-format"),
+            info_hash: match dict.get("info-hash") {
+                Some(Bencode::Bytes(b)) if !b.iter().all(|&x| x == 0) => Some(hex::encode(b)),
+                _ => None,
+            },
+            name: get_string(&dict, "name").or_else(|| get_string(&dict, "qBt-name")),
+            save_path: get_string(&dict, "save_path").or_else(|| get_string(&dict, "qBt-savePath")),
+            pieces,
+            trackers,
+            added_time: get_int(&dict, "added_time"),
+            completed_time: get_int(&dict, "completed_time").filter(|&t| t > 0),
+            total_uploaded: get_int(&dict, "total_uploaded"),
+            total_downloaded: get_int(&dict, "total_downloaded"),
+        })
+    }
+}
+
+fn format_time(ts: i64) -> String {
+    format!("{ts} [{}]", format_timestamp(ts))
+}
+
+/// Condenses the bitfield to a fixed width: `#` all complete, `-` none, `+` partial.
+fn bitfield_bar(pieces: &[bool]) -> String {
+    let width = pieces.len().min(BITFIELD_WIDTH);
+    (0..width)
+        .map(|i| {
+            let chunk = &pieces[i * pieces.len() / width..(i + 1) * pieces.len() / width];
+            match chunk.iter().filter(|&&p| p).count() {
+                0 => '-',
+                n if n == chunk.len() => '#',
+                _ => '+',
+            }
+        })
+        .collect()
+}
+
+impl Display for ResumeData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        writeln!(f, "Resume Info:")?;
+        if let Some(file_format) = &self.file_format {
+            writeln!(f, "  File format: {file_format}")?;
+        }
+        if let Some(info_hash) = &self.info_hash {
+            writeln!(f, "  Info hash: {info_hash}")?;
+        }
+        if let Some(name) = &self.name {
+            writeln!(f, "  Name: {name}")?;
+        }
+        if let Some(save_path) = &self.save_path {
+            writeln!(f, "  Save path: {save_path}")?;
+        }
+        if let Some(pieces) = &self.pieces {
+            let have = pieces.iter().filter(|&&p| p).count();
+            let percent = if pieces.is_empty() {
+                0.0
+            } else {
+                have as f64 * 100.0 / pieces.len() as f64
+            };
+            writeln!(
+                f,
+                "  Pieces: {have}/{} complete ({percent:.2}%)",
+                pieces.len()
+            )?;
+            if !pieces.is_empty() {
+                writeln!(f, "  Bitfield: [{}]", bitfield_bar(pieces))?;
+            }
+        }
+        if !self.trackers.is_empty() {
+            writeln!(f, "  Trackers:")?;
+            for (tier_id, tier) in self.trackers.iter().enumerate() {
+                for url in tier {
+                    writeln!(f, "    Tier {tier_id}: {url}")?;
+                }
+            }
+        }
+        if let Some(added_time) = self.added_time {
+            writeln!(f, "  Added: {}", format_time(added_time))?;
+        }
+        if let Some(completed_time) = self.completed_time {
+            writeln!(f, "  Completed: {}", format_time(completed_time))?;
+        }
+        if let Some(uploaded) = self.total_uploaded {
+            writeln!(
+                f,
+                "  Uploaded: {uploaded} [{}]",
+                human_size(uploaded.max(0) as usize)
+            )?;
+        }
+        if let Some(downloaded) = self.total_downloaded {
+            writeln!(
+                f,
+                "  Downloaded: {downloaded} [{}]",
+                human_size(downloaded.max(0) as usize)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{remove_file, write};
+
+    use super::*;
+
+    fn read_bytes(name: &str, data: &[u8]) -> TrResult<ResumeData> {
+        let path = std::env::temp_dir().join(format!("tur-{name}-{}", std::process::id()));
+        write(&path, data).unwrap();
+        let resume = ResumeData::read_resume(&path.to_string_lossy());
+        let _ = remove_file(&path);
+        resume
+    }
+
+    #[test]
+    fn fastresume_fields_are_read() {
+        let mut data = b"d10:added_timei1700000000e14:completed_timei0e".to_vec();
+        data.extend_from_slice(b"11:file-format22:libtorrent resume file9:info-hash20:");
+        data.extend_from_slice(&[0xab; 20]);
+        data.extend_from_slice(b"6:pieces3:\x01\x00\x03");
+        data.extend_from_slice(b"8:qBt-name4:Data12:qBt-savePath5:/dl/x");
+        data.extend_from_slice(b"14:total_uploadedi42e8:trackersll8:http://ai1eel8:http://beee");
+        let resume = read_bytes("resume", &data).unwrap();
+        assert_eq!(resume.info_hash, Some("ab".repeat(20)));
+        assert_eq!(resume.name.as_deref(), Some("Data"));
+        assert_eq!(resume.save_path.as_deref(), Some("/dl/x"));
+        assert_eq!(resume.pieces, Some(vec![true, false, true]));
+        assert_eq!(
+            resume.trackers,
+            [
+                vec![String::from("http://a")],
+                vec![String::from("http://b")]
+            ]
+        );
+        assert_eq!(resume.added_time, Some(1700000000));
+        assert_eq!(resume.completed_time, None);
+        assert_eq!(resume.total_uploaded, Some(42));
+        assert_eq!(resume.total_downloaded, None);
+    }
+
+    #[test]
+    fn seed_mode_completes_every_piece() {
+        let mut data = b"d9:info-hash20:".to_vec();
+        data.extend_from_slice(&[0; 20]);
+        data.extend_from_slice(b"4:name1:n6:pieces2:\x00\x029:seed_modei1ee");
+        let resume = read_bytes("resume-seed", &data).unwrap();
+        assert_eq!(resume.pieces, Some(vec![true, true]));
+        assert_eq!(resume.info_hash, None);
+        assert_eq!(resume.name.as_deref(), Some("n"));
+    }
+
+    #[test]
+    fn root_must_be_a_dict() {
+        assert!(read_bytes("resume-list", b"le").is_err());
+    }
+}
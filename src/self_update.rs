@@ -0,0 +1,135 @@
+use std::env::consts::{ARCH, EXE_SUFFIX, OS};
+use std::env::current_exe;
+use std::fs::{remove_file, rename, write};
+use std::io::Error as ioError;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::net;
+use crate::utils::{TrError, TrResult, check_write};
+
+const RELEASES_URL: &str = "https://api.github.com/repos/lapluis/TorrentUtilsR/releases/latest";
+const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> TrResult<&Asset> {
+        self.assets.iter().find(|a| a.name == name).ok_or_else(|| {
+            TrError::MissingField(format!("release {} has no asset '{name}'", self.tag_name))
+        })
+    }
+}
+
+/// Release asset built for this platform, e.g. `TorrentUtilsR-x86_64-linux` or
+/// `TorrentUtilsR-x86_64-windows.exe`.
+fn asset_name() -> String {
+    format!("{}-{ARCH}-{OS}{EXE_SUFFIX}", env!("CARGO_PKG_NAME"))
+}
+
+/// Downloads over HTTPS with the system `curl`, shipped with Windows 10 and later and
+/// about every Linux, so the feature adds no TLS stack to the build.
+fn fetch(url: &str) -> TrResult<Vec<u8>> {
+    let output = net::curl()
+        .args(["--proto", "=https", url])
+        .output()
+        .map_err(|e| ioError::other(format!("Failed to run curl: {e}")))?;
+    if !output.status.success() {
+        return Err(ioError::other(format!(
+            "Download of {url} failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(output.stdout)
+}
+
+fn parse_version(s: &str) -> Option<Vec<u64>> {
+    s.trim_start_matches('v')
+        .split('.')
+        .map(|part| part.parse().ok())
+        .collect()
+}
+
+/// Checks the latest release and, unless `check_only`, replaces the running executable
+/// with its binary for this platform once the SHA-256 listed in `SHA256SUMS` matches.
+pub fn self_update(check_only: bool, force: bool) -> TrResult<()> {
+    let release: Release = serde_json::from_slice(&fetch(RELEASES_URL)?)
+        .map_err(|e| TrError::ParseError(format!("Invalid release feed: {e}")))?;
+    let current = env!("CARGO_PKG_VERSION");
+    let latest = release.tag_name.trim_start_matches('v');
+    let newer = match (parse_version(latest), parse_version(current)) {
+        (Some(latest), Some(current)) => latest > current,
+        _ => latest != current,
+    };
+    if !newer && !force {
+        println!("Up to date: {current} is the latest release.");
+        return Ok(());
+    }
+    println!("Latest release: {latest}, running: {current}.");
+    if check_only {
+        return Ok(());
+    }
+
+    let name = asset_name();
+    let binary_asset = release.asset(&name)?;
+    // without a checksum nothing vouches for the download, refuse rather than guess
+    let sums = String::from_utf8(fetch(
+        &release.asset(CHECKSUMS_ASSET)?.browser_download_url,
+    )?)?;
+    let expected = sums
+        .lines()
+        .find_map(|line| {
+            let (hash, file) = line.split_once(char::is_whitespace)?;
+            (file.trim().trim_start_matches('*') == name).then(|| hash.to_lowercase())
+        })
+        .ok_or_else(|| TrError::MissingField(format!("{CHECKSUMS_ASSET} does not list {name}")))?;
+    let binary = fetch(&binary_asset.browser_download_url)?;
+    let actual = hex::encode(Sha256::digest(&binary));
+    if actual != expected {
+        return Err(ioError::other(format!(
+            "Checksum mismatch for {name}: expected {expected}, got {actual}"
+        ))
+        .into());
+    }
+
+    replace_exe(&binary)?;
+    println!("Updated to {latest}.");
+    Ok(())
+}
+
+/// Swaps the running executable for `binary`. Windows refuses to overwrite a running
+/// executable but lets it be renamed, so the old one is moved aside first.
+fn replace_exe(binary: &[u8]) -> TrResult<()> {
+    let exe = current_exe()?.canonicalize()?;
+    let new = exe.with_extension("new");
+    let old = exe.with_extension("old");
+    check_write(&exe)?;
+    write(&new, binary)?;
+    #[cfg(unix)]
+    {
+        use std::fs::{Permissions, set_permissions};
+        use std::os::unix::fs::PermissionsExt;
+        set_permissions(&new, Permissions::from_mode(0o755))?;
+    }
+    let _ = remove_file(&old);
+    rename(&exe, &old)?;
+    if let Err(e) = rename(&new, &exe) {
+        let _ = rename(&old, &exe);
+        return Err(e.into());
+    }
+    // still in use on Windows, removed by the next update
+    let _ = remove_file(&old);
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use std::env;
+use std::fs::{create_dir_all, read_dir, remove_file};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use crate::utils::{TrResult, check_write};
+
+const APP_DIR: &str = "TorrentUtilsR";
+/// File name of the tracker cache of `check-trackers`.
+pub const TRACKER_CACHE: &str = "tracker-cache.json";
+/// File name of the piece cache used by `--piece-cache default`.
+pub const PIECE_CACHE: &str = "pieces.piece-cache";
+
+static STATE_DIR: OnceLock<PathBuf> = OnceLock::new();
+
+/// Sets the directory of verify states, the tracker cache and the default piece cache,
+/// from `--state-dir` or the config.
+pub fn set_state_dir(dir: PathBuf) {
+    let _ = STATE_DIR.set(dir);
+}
+
+/// The configured state directory, else `TorrentUtilsR` in the user cache directory:
+/// `$XDG_CACHE_HOME` or `~/.cache` on Linux, `~/Library/Caches` on macOS and
+/// `%LOCALAPPDATA%` on Windows. Falls back to the temp directory without a home.
+pub fn state_dir() -> PathBuf {
+    STATE_DIR.get().cloned().unwrap_or_else(default_state_dir)
+}
+
+fn default_state_dir() -> PathBuf {
+    let non_empty = |var: &str| env::var_os(var).filter(|value| !value.is_empty());
+    let cache_dir = if cfg!(windows) {
+        non_empty("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        non_empty("HOME").map(|home| Path::new(&home).join("Library").join("Caches"))
+    } else {
+        non_empty("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| non_empty("HOME").map(|home| Path::new(&home).join(".cache")))
+    };
+    cache_dir.unwrap_or_else(env::temp_dir).join(APP_DIR)
+}
+
+/// Path of `name` in the state directory, which is created if needed.
+pub fn state_file(name: &str) -> TrResult<PathBuf> {
+    let dir = state_dir();
+    if !dir.is_dir() {
+        check_write(&dir)?;
+        create_dir_all(&dir)?;
+    }
+    Ok(dir.join(name))
+}
+
+/// Name of the verify state of the torrent with this info-hash.
+pub fn verify_state_name(info_hash: &str) -> String {
+    format!("{info_hash}.verify-state")
+}
+
+/// One file of the state directory.
+pub struct StateEntry {
+    pub path: PathBuf,
+    pub kind: &'static str,
+    pub size: u64,
+    /// Time since the last change.
+    pub age: Duration,
+}
+
+fn kind_of(name: &str) -> &'static str {
+    if name == TRACKER_CACHE {
+        "tracker cache"
+    } else if name.ends_with(".verify-state") {
+        "verify state"
+    } else if name.ends_with(".piece-cache") {
+        "piece cache"
+    } else {
+        "other"
+    }
+}
+
+/// Files of the state directory, by name. A missing directory holds none.
+pub fn entries() -> TrResult<Vec<StateEntry>> {
+    let dir = match read_dir(state_dir()) {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let now = SystemTime::now();
+    let mut entries = Vec::new();
+    for entry in dir {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        entries.push(StateEntry {
+            path: entry.path(),
+            kind: kind_of(&name),
+            size: metadata.len(),
+            age: metadata
+                .modified()
+                .ok()
+                .and_then(|modified| now.duration_since(modified).ok())
+                .unwrap_or_default(),
+        });
+    }
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+/// Removes the state files unchanged for at least `older_than`, all of them without,
+/// and returns the removed ones. Files of other programs are left alone, in case the
+/// directory is shared.
+pub fn clean(older_than: Option<Duration>) -> TrResult<Vec<StateEntry>> {
+    let mut removed = Vec::new();
+    for entry in entries()? {
+        if entry.kind == "other" || older_than.is_some_and(|min_age| entry.age < min_age) {
+            continue;
+        }
+        check_write(&entry.path)?;
+        remove_file(&entry.path)?;
+        removed.push(entry);
+    }
+    Ok(removed)
+}
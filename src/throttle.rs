@@ -0,0 +1,205 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const SLOT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const THROTTLED_DELAY: Duration = Duration::from_millis(100);
+const IDLE_SAMPLES_TO_RESUME: usize = 3;
+#[cfg(target_os = "linux")]
+const FOREIGN_IO_THRESHOLD: u64 = 4 << 20; // bytes per sample from other processes
+
+static LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+static OPEN_FILE_LIMIT: AtomicUsize = AtomicUsize::new(usize::MAX);
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Caps the content files open at once. A hashing thread keeps one file open while it
+/// reads a piece, so this caps the threads reading at once, for containers with a low
+/// open file limit.
+pub fn set_open_file_limit(limit: usize) {
+    OPEN_FILE_LIMIT.store(limit.max(1), Ordering::Relaxed);
+}
+
+/// Held by a hashing thread while it reads a piece.
+pub struct Slot;
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        ACTIVE.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Waits until the current thread limit and the open file limit allow another hashing
+/// thread to read.
+pub fn acquire_slot() -> Slot {
+    loop {
+        let limit = LIMIT.load(Ordering::Relaxed);
+        let active = ACTIVE.load(Ordering::SeqCst);
+        if active < limit.min(OPEN_FILE_LIMIT.load(Ordering::Relaxed))
+            && ACTIVE
+                .compare_exchange(active, active + 1, Ordering::SeqCst, Ordering::SeqCst)
+                .is_ok()
+        {
+            if limit != usize::MAX {
+                thread::sleep(THROTTLED_DELAY);
+            }
+            return Slot;
+        }
+        thread::sleep(SLOT_POLL_INTERVAL);
+    }
+}
+
+/// Throttles hashing to a single thread while other processes keep the disk busy.
+pub struct AutoYield {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl AutoYield {
+    pub fn start(target_path: &Path, quiet: bool) -> Option<Self> {
+        let mut sampler = LoadSampler::new(target_path)?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || {
+                let mut idle_samples = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    thread::sleep(SAMPLE_INTERVAL);
+                    let throttled = LIMIT.load(Ordering::Relaxed) != usize::MAX;
+                    if sampler.busy() {
+                        idle_samples = 0;
+                        if !throttled {
+                            LIMIT.store(1, Ordering::Relaxed);
+                            if !quiet {
+                                eprintln!("I: Disk busy, hashing throttled.");
+                            }
+                        }
+                    } else if throttled {
+                        idle_samples += 1;
+                        if idle_samples >= IDLE_SAMPLES_TO_RESUME {
+                            LIMIT.store(usize::MAX, Ordering::Relaxed);
+                            if !quiet {
+                                eprintln!("I: Disk idle, hashing at full speed.");
+                            }
+                        }
+                    }
+                }
+            })
+        };
+        Some(AutoYield {
+            stop,
+            handle: Some(handle),
+        })
+    }
+}
+
+impl Drop for AutoYield {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        LIMIT.store(usize::MAX, Ordering::Relaxed);
+    }
+}
+
+#[cfg(target_os = "linux")]
+struct LoadSampler {
+    device: Option<(u32, u32)>,
+    last_disk: Option<u64>,
+    last_own: Option<u64>,
+}
+
+#[cfg(target_os = "linux")]
+impl LoadSampler {
+    fn new(target_path: &Path) -> Option<Self> {
+        use std::os::unix::fs::MetadataExt;
+
+        let dev = std::fs::metadata(target_path).ok()?.dev();
+        let mut sampler = LoadSampler {
+            device: Some((libc::major(dev), libc::minor(dev))),
+            last_disk: None,
+            last_own: None,
+        };
+        sampler.last_disk = sampler.disk_bytes();
+        if sampler.last_disk.is_none() {
+            // virtual or network filesystem, fall back to the load average
+            sampler.device = None;
+        }
+        sampler.last_own = own_bytes();
+        Some(sampler)
+    }
+
+    fn disk_bytes(&self) -> Option<u64> {
+        let (major, minor) = self.device?;
+        let stats = std::fs::read_to_string("/proc/diskstats").ok()?;
+        stats.lines().find_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10
+                || fields[0].parse::<u32>().ok()? != major
+                || fields[1].parse::<u32>().ok()? != minor
+            {
+                return None;
+            }
+            let sectors_read = fields[5].parse::<u64>().ok()?;
+            let sectors_written = fields[9].parse::<u64>().ok()?;
+            Some((sectors_read + sectors_written) * 512)
+        })
+    }
+
+    fn busy(&mut self) -> bool {
+        if self.device.is_none() {
+            return load_busy();
+        }
+        let disk = self.disk_bytes();
+        let own = own_bytes();
+        let busy = match (self.last_disk, disk, self.last_own, own) {
+            (Some(last_disk), Some(disk), Some(last_own), Some(own)) => {
+                let foreign = (disk.saturating_sub(last_disk)).saturating_sub(own - last_own);
+                foreign > FOREIGN_IO_THRESHOLD
+            }
+            _ => load_busy(),
+        };
+        self.last_disk = disk;
+        self.last_own = own;
+        busy
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn own_bytes() -> Option<u64> {
+    let io = std::fs::read_to_string("/proc/self/io").ok()?;
+    let field = |name: &str| {
+        io.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    };
+    Some(field("read_bytes:")? + field("write_bytes:")?)
+}
+
+#[cfg(target_os = "linux")]
+fn load_busy() -> bool {
+    let cpus = thread::available_parallelism()
+        .map(|p| p.get())
+        .unwrap_or(1);
+    std::fs::read_to_string("/proc/loadavg")
+        .ok()
+        .and_then(|s| s.split_whitespace().next()?.parse::<f64>().ok())
+        .is_some_and(|load| load > cpus as f64)
+}
+
+#[cfg(not(target_os = "linux"))]
+struct LoadSampler;
+
+#[cfg(not(target_os = "linux"))]
+impl LoadSampler {
+    fn new(_target_path: &Path) -> Option<Self> {
+        None
+    }
+
+    fn busy(&mut self) -> bool {
+        false
+    }
+}
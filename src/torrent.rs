@@ -1,19 +1,49 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
 use std::fmt::{Display, Formatter, Result as fmtResult};
-use std::fs::{File, read};
-use std::io::{Error as ioError, ErrorKind, Result as ioResult, Write, stdout};
+use std::fs::read;
+use std::io::{self, Result as ioResult, Write};
 use std::path::Path;
 
-use chrono::{Local, TimeZone};
+use chrono::Local;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
 
-use crate::bencode::{bencode_int, bencode_string};
+use crate::bencode::{
+    Bencode, ByteCounter, ExtraKeys, ExtraValue, parse_dict_spans, patch_dict, write_extra_before,
+    write_int, write_string, write_string_list,
+};
+use crate::datetime::format_timestamp;
+use crate::glob::Glob;
+use crate::perf::PerfStats;
+use crate::piece_cache::PieceCache;
 use crate::tr_file::{Node, TrFile};
-use crate::tr_info::{TrConfig, TrInfo};
-use crate::utils::{TrError, TrResult, human_size};
+use crate::tr_info::{
+    KNOWN_INFO_KEYS, LinkPolicy, MetaVersion, OfflinePolicy, SHA1_HASH_SIZE, SortLocale, TrConfig,
+    TrInfo, WalkMode,
+};
+use crate::utils::{TrError, TrResult, base32_encode, human_size, url_encode, write_replacing};
+use crate::warnings::{WarningKind, warn};
+use crate::{DEF_PIECE_SIZE, MAX_PIECE_SIZE, MIN_PIECE_SIZE, NAME_VERSION, crash};
 
 const MAX_DISPLAYED_ANNOUNCES: usize = 20;
 const MAX_DISPLAYED_FILES: usize = 100;
 
+/// Top-level keys with a field in `Torrent`, all others are extra.
+pub const KNOWN_ROOT_KEYS: [&str; 11] = [
+    "announce",
+    "announce-list",
+    "comment",
+    "created by",
+    "creation date",
+    "encoding",
+    "hash",
+    "httpseeds",
+    "info",
+    "nodes",
+    "url-list",
+];
+
+/// A .torrent file: the root keys and the parsed info dictionary.
 pub struct Torrent {
     announce: Option<String>,
     announce_list: Option<Vec<Vec<String>>>,
@@ -23,9 +53,18 @@ pub struct Torrent {
     encoding: Option<String>,
     hash: Option<String>,
     info: Option<TrInfo>,
+    info_raw: Option<Vec<u8>>,
+    /// DHT bootstrap nodes (BEP 5) as host and port, for trackerless torrents.
+    nodes: Option<Vec<(String, u16)>>,
+    /// HTTP seeds (BEP 17), servers answering piece requests through a script.
+    httpseeds: Option<Vec<String>>,
+    /// Web seeds (BEP 19), HTTP servers holding the content.
+    url_list: Option<Vec<String>>,
+    extra: ExtraKeys,
 }
 
 impl Torrent {
+    /// Torrent without an info dictionary, set one with `set_info` or `preflight_torrent`.
     pub fn new(
         announce: Option<String>,
         announce_list: Option<Vec<Vec<String>>>,
@@ -43,132 +82,156 @@ impl Torrent {
             encoding,
             hash: None,
             info: None,
+            info_raw: None,
+            nodes: None,
+            httpseeds: None,
+            url_list: None,
+            extra: ExtraKeys::new(),
         }
     }
 
+    pub fn preflight_torrent(&mut self, target_path: &str, tr_config: &TrConfig) -> TrResult<()> {
+        self.set_info(TrInfo::preflight(target_path, tr_config)?);
+        Ok(())
+    }
+
+    pub fn set_info(&mut self, info: TrInfo) {
+        self.info_raw = None;
+        self.hash = Some(info.hash());
+        self.info = Some(info);
+        self.sync_piece_layers();
+    }
+
+    /// Keeps the top-level `piece layers` in step with the v2 hashes of a created info
+    /// dict, and drops one carried over from a v2 torrent by `new_like`.
+    fn sync_piece_layers(&mut self) {
+        match self.info.as_ref().and_then(TrInfo::piece_layers) {
+            Some(layers) => self
+                .extra
+                .insert(String::from("piece layers"), ExtraValue::Raw(layers)),
+            None => self.extra.remove("piece layers"),
+        };
+    }
+
+    /// Empty torrent carrying over the trackers, DHT nodes, web and HTTP seeds, comment,
+    /// encoding and extra keys of this one.
+    pub fn new_like(&self, created_by: Option<String>, creation_date: Option<i64>) -> Self {
+        let mut torrent = Torrent::new(
+            self.announce.clone(),
+            self.announce_list.clone(),
+            self.comment.clone(),
+            created_by,
+            creation_date,
+            self.encoding.clone(),
+        );
+        torrent.nodes = self.nodes.clone();
+        torrent.httpseeds = self.httpseeds.clone();
+        torrent.url_list = self.url_list.clone();
+        torrent.extra = self.extra.clone();
+        torrent
+    }
+
+    /// Hashes the content of a preflighted torrent, through `tr_config.piece_cache` if set.
     pub fn create_torrent(
         &mut self,
-        target_path: String,
+        target_path: &str,
         tr_config: &TrConfig,
         quiet: bool,
-    ) -> TrResult<()> {
-        let info = TrInfo::new(target_path, tr_config, quiet)?;
+    ) -> TrResult<PerfStats> {
+        let mut cache = match &tr_config.piece_cache {
+            Some(cache_path) => Some(PieceCache::load(cache_path)?),
+            None => None,
+        };
+        let perf = self.hash_with_cache(target_path, tr_config, cache.as_mut(), quiet)?;
+        if let Some(cache) = cache {
+            cache.save()?;
+        }
+        Ok(perf)
+    }
+
+    pub fn hash_with_cache(
+        &mut self,
+        target_path: &str,
+        tr_config: &TrConfig,
+        cache: Option<&mut PieceCache>,
+        quiet: bool,
+    ) -> TrResult<PerfStats> {
+        let info = self
+            .info
+            .as_mut()
+            .ok_or_else(|| TrError::MissingField(String::from("info")))?;
+        let perf = info.hash_pieces(target_path, tr_config, cache, quiet)?;
         self.hash = Some(info.hash());
-        self.info = Some(info);
-        Ok(())
+        self.sync_piece_layers();
+        Ok(perf)
     }
 
-    pub fn write_to_file(&self, torrent_path: String, force: bool) -> ioResult<()> {
+    /// Size in bytes of the encoded .torrent file, `None` without an info dict.
+    pub fn encoded_len(&self) -> Option<usize> {
+        let mut counter = ByteCounter::default();
+        self.encode(&mut counter).ok()?;
+        Some(counter.0)
+    }
+
+    /// Writes the torrent through `write_replacing`, so an existing file is only replaced
+    /// once the new one is complete, then reads it back and checks that the file holds
+    /// exactly what was encoded and parses to the same torrent. Returns the SHA-256 of the
+    /// file.
+    pub fn write_to_file(&self, torrent_path: String, force: bool) -> TrResult<String> {
         if !force && Path::new(&torrent_path).exists() {
-            return Err(ioError::new(
-                ErrorKind::AlreadyExists,
-                "File already exists, use -f to overwrite",
-            ));
+            return Err(TrError::OutputExists(torrent_path));
         }
-        let mut file = File::create(torrent_path)?;
-        file.write_all(&self.bencode())?;
-        Ok(())
+        let mut data = Vec::new();
+        self.encode(&mut data)?;
+        let expected = hex::encode(Sha256::digest(&data));
+        write_replacing(Path::new(&torrent_path), data)?;
+
+        let written = hex::encode(Sha256::digest(read(&torrent_path)?));
+        if written != expected {
+            return Err(TrError::InvalidTorrent(format!(
+                "{torrent_path} does not hold the bytes written, the write was corrupted"
+            )));
+        }
+        if Torrent::read_torrent(torrent_path.clone())?.sha256()? != expected {
+            return Err(TrError::InvalidTorrent(format!(
+                "{torrent_path} does not encode back to the bytes written"
+            )));
+        }
+        Ok(written)
     }
 
+    /// SHA-256 of the encoded .torrent file.
+    fn sha256(&self) -> TrResult<String> {
+        let mut hasher = Sha256::new();
+        self.encode(&mut hasher)?;
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    /// Reads and parses a .torrent file.
     pub fn read_torrent(tr_path: String) -> TrResult<Self> {
-        enum Bencode<'a> {
-            Int(usize),
-            UInt(i64),
-            Bytes(&'a [u8]),
-            List(Vec<Bencode<'a>>),
-            Dict(HashMap<String, Bencode<'a>>),
-        }
-
-        let bcode = read(&tr_path)?;
-        let mut pos = 0;
-
-        fn parse_bencode<'a>(data: &'a [u8], pos: &mut usize) -> TrResult<Bencode<'a>> {
-            match data.get(*pos) {
-                Some(b'i') => {
-                    *pos += 1;
-                    let start = *pos;
-                    while *pos < data.len() && data[*pos] != b'e' {
-                        *pos += 1;
-                    }
-                    if *pos >= data.len() {
-                        return Err("unterminated integer".into());
-                    }
-                    let num_str = std::str::from_utf8(&data[start..*pos])
-                        .map_err(|_| "invalid utf8 in int")?;
-                    *pos += 1;
-                    if num_str.starts_with("-") {
-                        let val = num_str.parse::<i64>().map_err(|_| "invalid int")?;
-                        Ok(Bencode::UInt(val))
-                    } else {
-                        let val = num_str.parse::<usize>().map_err(|_| "invalid int")?;
-                        Ok(Bencode::Int(val))
-                    }
-                }
-                Some(b'l') => {
-                    *pos += 1;
-                    let mut items = Vec::new();
-                    while data.get(*pos) != Some(&b'e') {
-                        items.push(parse_bencode(data, pos)?);
-                    }
-                    *pos += 1;
-                    Ok(Bencode::List(items))
-                }
-                Some(b'd') => {
-                    *pos += 1;
-                    let mut map = HashMap::new();
-                    while data.get(*pos) != Some(&b'e') {
-                        let key = match parse_bencode(data, pos)? {
-                            Bencode::Bytes(b) => String::from_utf8(b.to_vec()).map_err(|_| {
-                                TrError::InvalidTorrent(String::from("invalid utf8 key"))
-                            })?,
-                            _ => {
-                                return Err(TrError::InvalidTorrent(String::from(
-                                    "dict key not string",
-                                )));
-                            }
-                        };
-                        let val = parse_bencode(data, pos)?;
-                        map.insert(key, val);
-                    }
-                    *pos += 1;
-                    Ok(Bencode::Dict(map))
-                }
-                Some(b'0'..=b'9') => {
-                    let start = *pos;
-                    while *pos < data.len() && data[*pos] != b':' {
-                        *pos += 1;
-                    }
-                    if *pos >= data.len() {
-                        return Err(TrError::InvalidTorrent(String::from(
-                            "truncated string length",
-                        )));
-                    }
-                    let len_str = std::str::from_utf8(&data[start..*pos])
-                        .map_err(|_| "invalid utf8 length")?;
-                    let len = len_str.parse::<usize>().map_err(|_| "bad string length")?;
-                    *pos += 1;
-                    let end = *pos + len;
-                    if end > data.len() {
-                        return Err(TrError::InvalidTorrent(String::from("truncated string")));
-                    }
-                    let slice = &data[*pos..end];
-                    *pos = end;
-                    Ok(Bencode::Bytes(slice))
-                }
-                Some(_) => Err("unknown token".into()),
-                None => Err("unexpected EOF".into()),
-            }
-        }
+        crash::set_context(format!("reading '{tr_path}'"));
+        Torrent::from_bytes(&read(&tr_path)?)
+    }
 
-        let root = parse_bencode(&bcode, &mut pos)?;
-        let tr_dict = match root {
-            Bencode::Dict(m) => m,
+    /// Parses a bencoded torrent, e.g. one assembled from metadata fetched from peers.
+    pub fn from_bytes(bcode: &[u8]) -> TrResult<Self> {
+        // keep the info dict exactly as stored: the info-hashes, magnet links and edits of
+        // foreign torrents with unknown keys or unsorted keys must not see a re-encoding;
+        // other top-level values are kept raw too, for the lists and dicts among the extras
+        let (tr_dict, raw_values) = match bcode.first() {
+            Some(b'd') => parse_dict_spans(bcode, &mut 0)?,
             _ => {
                 return Err(TrError::InvalidTorrent(String::from(
                     "torrent root is not a dictionary",
                 )));
             }
         };
+        let info_raw = raw_values.get(&b"info"[..]).map(|raw| raw.to_vec());
+        // spans of the info values, for the lists and dicts among its extras
+        let info_raw_values = match &info_raw {
+            Some(raw) => parse_dict_spans(raw, &mut 0)?.1,
+            None => Default::default(),
+        };
 
         let info_dict = match tr_dict.get("info") {
             Some(Bencode::Dict(m)) => m,
@@ -206,7 +269,30 @@ impl Torrent {
                                 )));
                             }
                         };
-                        out.push(TrFile { length, path });
+                        let attr = match m.get("attr") {
+                            Some(Bencode::Bytes(attr)) => *attr,
+                            _ => b"",
+                        };
+                        let symlink = match m.get("symlink path") {
+                            Some(Bencode::List(parts)) if attr.contains(&b'l') => Some(
+                                parts
+                                    .iter()
+                                    .filter_map(|part| match part {
+                                        Bencode::Bytes(b) => {
+                                            Some(String::from_utf8_lossy(b).into_owned())
+                                        }
+                                        _ => None,
+                                    })
+                                    .collect(),
+                            ),
+                            _ => None,
+                        };
+                        out.push(TrFile {
+                            length,
+                            path,
+                            padding: attr.contains(&b'p'),
+                            symlink,
+                        });
                     }
                 }
                 Some(out)
@@ -214,26 +300,67 @@ impl Torrent {
             _ => None,
         };
 
+        let meta_version = match info_dict.get("meta version") {
+            Some(Bencode::Int(i)) => Some(*i),
+            // BEP 52 asks for both, but some creators only write the file tree
+            _ if info_dict.contains_key("file tree") => Some(2),
+            _ => None,
+        };
+
+        // 0 is an empty single file, a missing length is only valid next to `files` or in
+        // a v2-only torrent
+        let length = match info_dict.get("length") {
+            Some(Bencode::Int(i)) => Some(*i),
+            Some(_) => {
+                return Err(TrError::InvalidTorrent(String::from(
+                    "length is not a non-negative integer",
+                )));
+            }
+            None if tr_files.is_none() && meta_version != Some(2) => {
+                return Err(TrError::InvalidTorrent(String::from(
+                    "neither length nor files, the content size is unknown",
+                )));
+            }
+            None => None,
+        };
+
+        let piece_length = match info_dict.get("piece length") {
+            Some(Bencode::Int(i)) if *i > 0 => *i,
+            Some(_) => {
+                return Err(TrError::InvalidTorrent(String::from(
+                    "piece length is not a positive integer",
+                )));
+            }
+            None => {
+                return Err(TrError::InvalidTorrent(String::from(
+                    "piece length missing",
+                )));
+            }
+        };
+
         let tr_info = TrInfo {
-            files: tr_files,
-            length: match info_dict.get("length") {
-                Some(Bencode::Int(i)) => Some(*i),
+            collections: match info_dict.get("collections") {
+                Some(Bencode::List(items)) => {
+                    let mut collections = Vec::new();
+                    for item in items {
+                        if let Bencode::Bytes(b) = item {
+                            collections.push(String::from_utf8(b.to_vec())?);
+                        }
+                    }
+                    Some(collections)
+                }
                 _ => None,
             },
+            files: tr_files,
+            length,
             name: match info_dict.get("name") {
                 Some(Bencode::Bytes(b)) => Some(String::from_utf8(b.to_vec())?),
                 _ => None,
             },
-            piece_length: match info_dict.get("piece length") {
-                Some(Bencode::Int(i)) => *i,
-                _ => {
-                    return Err(TrError::InvalidTorrent(String::from(
-                        "piece length missing",
-                    )));
-                }
-            },
+            piece_length,
             pieces: match info_dict.get("pieces") {
                 Some(Bencode::Bytes(b)) => b.to_vec(),
+                _ if meta_version == Some(2) => Vec::new(),
                 _ => return Err(TrError::InvalidTorrent(String::from("pieces missing"))),
             },
             private: match info_dict.get("private") {
@@ -244,8 +371,23 @@ impl Torrent {
                 Some(Bencode::Bytes(b)) => Some(String::from_utf8(b.to_vec())?),
                 _ => None,
             },
+            meta_version,
+            v2: None,
+            extra: info_dict
+                .iter()
+                .filter(|(key, _)| !KNOWN_INFO_KEYS.contains(&key.as_str()))
+                .filter_map(|(key, value)| {
+                    let value = ExtraValue::from_bencode(value).or_else(|| {
+                        let raw = info_raw_values.get(key.as_bytes())?;
+                        Some(ExtraValue::Raw(raw.to_vec()))
+                    })?;
+                    Some((key.clone(), value))
+                })
+                .collect(),
         };
 
+        tr_info.check_layout()?;
+
         Ok(Torrent {
             announce: match tr_dict.get("announce") {
                 Some(Bencode::Bytes(b)) => Some(String::from_utf8(b.to_vec())?),
@@ -305,83 +447,666 @@ impl Torrent {
                 _ => None,
             },
             info: Some(tr_info),
+            info_raw,
+            nodes: read_nodes(tr_dict.get("nodes")),
+            httpseeds: read_seeds(tr_dict.get("httpseeds"), "HTTP seed")?,
+            url_list: read_seeds(tr_dict.get("url-list"), "Web seed")?,
+            extra: tr_dict
+                .iter()
+                // `nodes` that is not a list is kept as it is
+                .filter(|(key, value)| {
+                    !KNOWN_ROOT_KEYS.contains(&key.as_str())
+                        || *key == "nodes" && !matches!(value, Bencode::List(_))
+                })
+                .filter_map(|(key, value)| {
+                    let value = ExtraValue::from_bencode(value).or_else(|| {
+                        let raw = raw_values.get(key.as_bytes())?;
+                        Some(ExtraValue::Raw(raw.to_vec()))
+                    })?;
+                    Some((key.clone(), value))
+                })
+                .collect(),
         })
     }
 
+    pub fn trackers(&self) -> Vec<Vec<String>> {
+        match (&self.announce_list, &self.announce) {
+            (Some(announce_list), _) => announce_list.clone(),
+            (None, Some(announce)) => vec![vec![announce.clone()]],
+            (None, None) => Vec::new(),
+        }
+    }
+
+    /// Sets `announce-list` and `announce` (the first URL), empty tiers are dropped.
+    pub fn set_trackers(&mut self, tiers: Vec<Vec<String>>) {
+        let tiers: Vec<Vec<String>> = tiers.into_iter().filter(|t| !t.is_empty()).collect();
+        self.announce = tiers.first().map(|tier| tier[0].clone());
+        self.announce_list = if tiers.is_empty() { None } else { Some(tiers) };
+    }
+
+    pub fn nodes(&self) -> &[(String, u16)] {
+        self.nodes.as_deref().unwrap_or_default()
+    }
+
+    /// Sets the DHT bootstrap nodes (BEP 5), `nodes` is left out if there are none.
+    pub fn set_nodes(&mut self, nodes: Vec<(String, u16)>) {
+        if !nodes.is_empty() {
+            self.extra.remove("nodes");
+        }
+        self.nodes = (!nodes.is_empty()).then_some(nodes);
+    }
+
+    pub fn http_seeds(&self) -> &[String] {
+        self.httpseeds.as_deref().unwrap_or_default()
+    }
+
+    /// Sets the HTTP seeds (BEP 17), `httpseeds` is left out if there are none.
+    pub fn set_http_seeds(&mut self, urls: Vec<String>) {
+        self.httpseeds = (!urls.is_empty()).then_some(urls);
+    }
+
+    pub fn web_seeds(&self) -> &[String] {
+        self.url_list.as_deref().unwrap_or_default()
+    }
+
+    /// Sets the web seeds (BEP 19), `url-list` is left out if there are none.
+    pub fn set_web_seeds(&mut self, urls: Vec<String>) {
+        self.url_list = (!urls.is_empty()).then_some(urls);
+    }
+
+    /// Adds top-level keys, replacing extra keys of the same name.
+    pub fn set_extra_keys(&mut self, keys: ExtraKeys) {
+        self.extra.extend(keys);
+    }
+
+    pub fn set_comment(&mut self, comment: Option<String>) {
+        self.comment = comment;
+    }
+
+    pub fn set_creation_date(&mut self, creation_date: Option<i64>) {
+        self.creation_date = creation_date;
+    }
+
+    /// Changes the private flag in the info dict, which changes the info-hash; the pieces
+    /// are kept.
+    pub fn set_private(&mut self, private: bool) -> TrResult<()> {
+        self.info_changed("private", private.then(|| b"i1e".to_vec()), |info| {
+            info.private = private
+        })
+    }
+
+    /// Changes the source in the info dict, which changes the info-hash; the pieces are kept.
+    pub fn set_source(&mut self, source: Option<String>) -> TrResult<()> {
+        let value = source.as_ref().map(|source| {
+            let mut value = Vec::new();
+            write_string(&mut value, source).expect("writing to a Vec cannot fail");
+            value
+        });
+        self.info_changed("source", value, |info| info.source = source)
+    }
+
+    /// Sets `key` of the info dict to the bencoded `value` and `apply`s the change to the
+    /// parsed fields. The info dict read from a file is patched in place, so `file tree`
+    /// and unknown keys are kept byte for byte; if it cannot be, nothing changes. One
+    /// without stored bytes is encoded from the fields.
+    fn info_changed(
+        &mut self,
+        key: &str,
+        value: Option<Vec<u8>>,
+        apply: impl FnOnce(&mut TrInfo),
+    ) -> TrResult<()> {
+        if let Some(raw) = &self.info_raw {
+            self.info_raw = Some(patch_dict(raw, key, value.as_deref())?);
+        }
+        if let Some(info) = self.info.as_mut() {
+            apply(info);
+        }
+        self.hash = match &self.info_raw {
+            Some(raw) => Some(hex::encode(Sha1::digest(raw))),
+            None => self.info.as_ref().map(TrInfo::hash),
+        };
+        Ok(())
+    }
+
+    pub fn comment(&self) -> Option<&str> {
+        self.comment.as_deref()
+    }
+
+    pub fn created_by(&self) -> Option<&str> {
+        self.created_by.as_deref()
+    }
+
+    pub fn creation_date(&self) -> Option<i64> {
+        self.creation_date
+    }
+
+    /// The info dictionary, `None` only before `set_info` on a new torrent.
     pub fn get_info(&self) -> Option<&TrInfo> {
         self.info.as_ref()
     }
 
-    fn bencode(&self) -> Vec<u8> {
-        let mut bcode: Vec<u8> = Vec::new();
-        bcode.push(b'd');
-        if self.announce.is_some() {
-            bcode.extend(bencode_string("announce"));
-            bcode.extend(bencode_string(self.announce.as_ref().unwrap()));
-        }
-        if self.announce_list.is_some() {
-            bcode.extend(bencode_string("announce-list"));
-            bcode.push(b'l');
-            for tier in self.announce_list.as_ref().unwrap() {
-                bcode.push(b'l');
-                for url in tier {
-                    bcode.extend(bencode_string(url));
-                }
-                bcode.push(b'e');
+    /// The info dict as stored in the file read, with `set_private` and `set_source`
+    /// patched in; `None` for created torrents.
+    pub fn info_raw(&self) -> Option<&[u8]> {
+        self.info_raw.as_deref()
+    }
+
+    /// Identifying hash: the v1 info-hash, or the v2 one for v2-only torrents.
+    pub fn info_hash(&self) -> Option<String> {
+        self.info_hash_v1().or_else(|| self.info_hash_v2())
+    }
+
+    pub fn total_size(&self) -> usize {
+        self.info.as_ref().map_or(0, |info| info.total_size())
+    }
+
+    pub fn piece_count(&self) -> usize {
+        self.info.as_ref().map_or(0, TrInfo::piece_count)
+    }
+
+    pub fn files(&self) -> Cow<'_, [TrFile]> {
+        self.info
+            .as_ref()
+            .and_then(|info| info.tr_files().ok())
+            .unwrap_or_default()
+    }
+
+    fn hash_info<H: Write>(&self, hasher: &mut H) -> Option<()> {
+        match &self.info_raw {
+            Some(raw) => hasher.write_all(raw).ok(),
+            None => self.info.as_ref()?.encode(hasher).ok(),
+        }
+    }
+
+    pub fn info_hash_v1(&self) -> Option<String> {
+        self.info_hash_v1_bytes().map(hex::encode)
+    }
+
+    fn info_hash_v1_bytes(&self) -> Option<[u8; SHA1_HASH_SIZE]> {
+        let info = self.info.as_ref()?;
+        if info.pieces.is_empty() {
+            return None;
+        }
+        let mut hasher = Sha1::new();
+        self.hash_info(&mut hasher)?;
+        Some(hasher.finalize().into())
+    }
+
+    pub fn info_hash_v2(&self) -> Option<String> {
+        if self.info.as_ref()?.meta_version != Some(2) {
+            return None;
+        }
+        let mut hasher = Sha256::new();
+        self.hash_info(&mut hasher)?;
+        Some(hex::encode(hasher.finalize()))
+    }
+
+    pub fn is_hybrid(&self) -> bool {
+        self.info_hash_v1().is_some() && self.info_hash_v2().is_some()
+    }
+
+    pub fn magnet_link(&self) -> Option<String> {
+        let mut params: Vec<String> = Vec::new();
+        if let Some(v1) = self.info_hash_v1() {
+            params.push(format!("xt=urn:btih:{v1}"));
+        }
+        if let Some(v2) = self.info_hash_v2() {
+            // multihash prefix: 0x12 = sha2-256, 0x20 = 32 bytes
+            params.push(format!("xt=urn:btmh:1220{v2}"));
+        }
+        if params.is_empty() {
+            return None;
+        }
+        if let Some(name) = self.info.as_ref().and_then(|info| info.name.as_ref()) {
+            params.push(format!("dn={}", url_encode(name)));
+        }
+        let mut trackers: Vec<&String> = Vec::new();
+        if let Some(announce_list) = &self.announce_list {
+            trackers.extend(announce_list.iter().flatten());
+        } else if let Some(announce) = &self.announce {
+            trackers.push(announce);
+        }
+        for tracker in trackers {
+            params.push(format!("tr={}", url_encode(tracker)));
+        }
+        for url in self.web_seeds() {
+            params.push(format!("ws={}", url_encode(url)));
+        }
+        Some(format!("magnet:?{}", params.join("&")))
+    }
+
+    /// Writes the bencoded torrent; fails for a torrent without an info dict.
+    pub fn encode<W: Write>(&self, out: &mut W) -> ioResult<()> {
+        let mut extra = self.extra.iter().peekable();
+        out.write_all(b"d")?;
+        if let Some(announce) = &self.announce {
+            write_extra_before(out, &mut extra, Some("announce"))?;
+            write_string(out, "announce")?;
+            write_string(out, announce)?;
+        }
+        if let Some(announce_list) = &self.announce_list {
+            write_extra_before(out, &mut extra, Some("announce-list"))?;
+            write_string(out, "announce-list")?;
+            out.write_all(b"l")?;
+            for tier in announce_list {
+                write_string_list(out, tier)?;
             }
-            bcode.push(b'e');
+            out.write_all(b"e")?;
+        }
+        if let Some(comment) = &self.comment {
+            write_extra_before(out, &mut extra, Some("comment"))?;
+            write_string(out, "comment")?;
+            write_string(out, comment)?;
         }
-        if self.comment.is_some() {
-            bcode.extend(bencode_string("comment"));
-            bcode.extend(bencode_string(self.comment.as_ref().unwrap()));
+        if let Some(created_by) = &self.created_by {
+            write_extra_before(out, &mut extra, Some("created by"))?;
+            write_string(out, "created by")?;
+            write_string(out, created_by)?;
         }
-        if self.created_by.is_some() {
-            bcode.extend(bencode_string("created by"));
-            bcode.extend(bencode_string(self.created_by.as_ref().unwrap()));
+        if let Some(creation_date) = self.creation_date {
+            write_extra_before(out, &mut extra, Some("creation date"))?;
+            write_string(out, "creation date")?;
+            write_int(out, creation_date)?;
         }
-        if self.creation_date.is_some() {
-            bcode.extend(bencode_string("creation date"));
-            bcode.extend(bencode_int(self.creation_date.unwrap()));
+        if let Some(encoding) = &self.encoding {
+            write_extra_before(out, &mut extra, Some("encoding"))?;
+            write_string(out, "encoding")?;
+            write_string(out, encoding)?;
         }
-        if self.encoding.is_some() {
-            bcode.extend(bencode_string("encoding"));
-            bcode.extend(bencode_string(self.encoding.as_ref().unwrap()));
+        if let Some(httpseeds) = &self.httpseeds {
+            write_extra_before(out, &mut extra, Some("httpseeds"))?;
+            write_string(out, "httpseeds")?;
+            write_string_list(out, httpseeds)?;
         }
-        if self.info.is_some() {
-            bcode.extend(bencode_string("info"));
-            bcode.extend(self.info.as_ref().unwrap().bencode());
+        if let Some(raw) = &self.info_raw {
+            write_extra_before(out, &mut extra, Some("info"))?;
+            write_string(out, "info")?;
+            out.write_all(raw)?;
+        } else if let Some(info) = &self.info {
+            write_extra_before(out, &mut extra, Some("info"))?;
+            write_string(out, "info")?;
+            info.encode(out)?;
         } else {
-            eprintln!("Warning: info dict is missing, creating empty bencode");
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "torrent has no info dict to encode",
+            ));
+        }
+        if let Some(hash) = &self.hash {
+            write_extra_before(out, &mut extra, Some("hash"))?;
+            write_string(out, "hash")?;
+            write_string(out, hash)?;
+        }
+        if let Some(nodes) = &self.nodes {
+            write_extra_before(out, &mut extra, Some("nodes"))?;
+            write_string(out, "nodes")?;
+            out.write_all(b"l")?;
+            for (host, port) in nodes {
+                out.write_all(b"l")?;
+                write_string(out, host)?;
+                write_int(out, i64::from(*port))?;
+                out.write_all(b"e")?;
+            }
+            out.write_all(b"e")?;
         }
-        if self.hash.is_some() {
-            bcode.extend(bencode_string("hash"));
-            bcode.extend(bencode_string(self.hash.as_ref().unwrap()));
+        if let Some(url_list) = &self.url_list {
+            write_extra_before(out, &mut extra, Some("url-list"))?;
+            write_string(out, "url-list")?;
+            write_string_list(out, url_list)?;
         }
-        bcode.push(b'e');
-        bcode
+        write_extra_before(out, &mut extra, None)?;
+        out.write_all(b"e")
     }
 
-    pub fn print_file_tree(&self) {
+    pub fn write_file_tree<W: Write>(&self, out: &mut W, locale: SortLocale) -> ioResult<()> {
         match &self.info {
             Some(info) => {
                 if let Some(name) = &info.name {
-                    println!("{name}");
+                    writeln!(out, "{name}")?;
                 }
-                let _ = stdout().flush();
                 if let Some(files) = &info.files {
                     let file_tree = Node::build_tree(files);
-                    file_tree.print_tree();
+                    file_tree.write_tree(out, locale)?;
                 } else if let Some(length) = info.length {
-                    println!("  [Single file, {} ({})]", length, human_size(length));
+                    writeln!(out, "  [Single file, {} ({})]", length, human_size(length))?;
                 } else {
-                    println!("  [No files information available]");
+                    writeln!(out, "  [No files information available]")?;
                 }
             }
             None => {
-                println!("[No torrent info available]");
+                writeln!(out, "[No torrent info available]")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Creates a torrent from a file or directory, for library users who would otherwise fill
+/// in `Torrent::new` and every field of `TrConfig`.
+///
+/// ```no_run
+/// use torrent_utils::torrent::TorrentBuilder;
+///
+/// let torrent = TorrentBuilder::new()
+///     .announce("https://tracker.example/announce")
+///     .comment("weekly backup")
+///     .piece_length(1 << 22)
+///     .private(true)
+///     .build("backup")?;
+/// torrent.write_to_file("backup.torrent".to_string(), false)?;
+/// # Ok::<(), torrent_utils::utils::TrError>(())
+/// ```
+pub struct TorrentBuilder {
+    announce_list: Vec<Vec<String>>,
+    web_seeds: Vec<String>,
+    http_seeds: Vec<String>,
+    nodes: Vec<(String, u16)>,
+    comment: Option<String>,
+    created_by: Option<String>,
+    creation_date: Option<i64>,
+    tr_config: TrConfig,
+    quiet: bool,
+}
+
+impl Default for TorrentBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TorrentBuilder {
+    /// Same defaults as the command line: 16 MiB pieces, v1, public, dated now, one thread,
+    /// and no progress output or pause keys.
+    pub fn new() -> Self {
+        TorrentBuilder {
+            announce_list: Vec::new(),
+            web_seeds: Vec::new(),
+            http_seeds: Vec::new(),
+            nodes: Vec::new(),
+            comment: None,
+            created_by: Some(NAME_VERSION.to_string()),
+            creation_date: Some(Local::now().timestamp()),
+            tr_config: TrConfig {
+                piece_length: 1usize << DEF_PIECE_SIZE,
+                auto_piece_size: false,
+                meta_version: MetaVersion::V1,
+                pad_files: false,
+                private: false,
+                n_jobs: 1,
+                read_buffer: None,
+                walk_mode: WalkMode::Default,
+                sort_locale: None,
+                stable_order_check: false,
+                file_order: None,
+                link_policy: LinkPolicy::Follow,
+                offline_files: OfflinePolicy::Abort,
+                output_path: None,
+                exclude_torrents: false,
+                skip_junk: false,
+                exclude: Vec::new(),
+                include: Vec::new(),
+                min_file_size: None,
+                max_file_size: None,
+                piece_cache: None,
+                source: None,
+                collections: Vec::new(),
+                info_keys: ExtraKeys::new(),
+                pause_keys: false,
+            },
+            quiet: true,
+        }
+    }
+
+    /// Adds a tracker as its own tier, the first one also becomes `announce`.
+    pub fn announce(mut self, url: impl Into<String>) -> Self {
+        self.announce_list.push(vec![url.into()]);
+        self
+    }
+
+    /// Adds a tier of trackers of which clients use the first that responds (BEP 12).
+    pub fn announce_tier(mut self, urls: Vec<String>) -> Self {
+        if !urls.is_empty() {
+            self.announce_list.push(urls);
+        }
+        self
+    }
+
+    /// Adds a web seed (BEP 19), an HTTP(S) URL of the content. Clients append the torrent
+    /// name to a URL ending with `/`, and the file paths for a directory target.
+    pub fn web_seed(mut self, url: impl Into<String>) -> Self {
+        self.web_seeds.push(url.into());
+        self
+    }
+
+    /// Adds an HTTP seed (BEP 17), the URL of a script serving pieces by info-hash.
+    pub fn http_seed(mut self, url: impl Into<String>) -> Self {
+        self.http_seeds.push(url.into());
+        self
+    }
+
+    /// Adds a DHT bootstrap node (BEP 5), for torrents without trackers.
+    pub fn node(mut self, host: impl Into<String>, port: u16) -> Self {
+        self.nodes.push((host.into(), port));
+        self
+    }
+
+    pub fn comment(mut self, comment: impl Into<String>) -> Self {
+        self.comment = Some(comment.into());
+        self
+    }
+
+    /// `None` leaves `created by` out.
+    pub fn created_by(mut self, created_by: Option<String>) -> Self {
+        self.created_by = created_by;
+        self
+    }
+
+    /// Unix timestamp, `None` leaves the creation date out.
+    pub fn creation_date(mut self, creation_date: Option<i64>) -> Self {
+        self.creation_date = creation_date;
+        self
+    }
+
+    /// Piece length in bytes, a power of two from 16 KiB to 128 MiB.
+    pub fn piece_length(mut self, piece_length: usize) -> Self {
+        self.tr_config.piece_length = piece_length;
+        self.tr_config.auto_piece_size = false;
+        self
+    }
+
+    /// Picks the piece length from the content size instead: the smallest giving at most
+    /// 2000 pieces, capped at 16 MiB.
+    pub fn auto_piece_length(mut self) -> Self {
+        self.tr_config.auto_piece_size = true;
+        self
+    }
+
+    pub fn private(mut self, private: bool) -> Self {
+        self.tr_config.private = private;
+        self
+    }
+
+    pub fn source(mut self, source: impl Into<String>) -> Self {
+        self.tr_config.source = Some(source.into());
+        self
+    }
+
+    pub fn meta_version(mut self, meta_version: MetaVersion) -> Self {
+        self.tr_config.meta_version = meta_version;
+        self
+    }
+
+    /// Pads every v1 file but the last to a piece boundary with BEP 47 padding files.
+    pub fn pad_files(mut self, pad_files: bool) -> Self {
+        self.tr_config.pad_files = pad_files;
+        self
+    }
+
+    /// Leaves files and directories matching `glob` out of a directory target.
+    pub fn exclude(mut self, glob: Glob) -> Self {
+        self.tr_config.exclude.push(glob);
+        self
+    }
+
+    /// Leaves files of a directory target outside `min..=max` bytes out.
+    pub fn file_size_range(mut self, min: Option<usize>, max: Option<usize>) -> Self {
+        self.tr_config.min_file_size = min;
+        self.tr_config.max_file_size = max;
+        self
+    }
+
+    /// What to do with cloud placeholder files, abort by default.
+    pub fn offline_files(mut self, policy: OfflinePolicy) -> Self {
+        self.tr_config.offline_files = policy;
+        self
+    }
+
+    /// Leaves OS litter such as `.DS_Store` and `Thumbs.db` out of a directory target.
+    pub fn skip_junk(mut self, skip_junk: bool) -> Self {
+        self.tr_config.skip_junk = skip_junk;
+        self
+    }
+
+    /// Keeps only the files of a directory target matching `glob` or another include.
+    pub fn include(mut self, glob: Glob) -> Self {
+        self.tr_config.include.push(glob);
+        self
+    }
+
+    pub fn walk_mode(mut self, walk_mode: WalkMode) -> Self {
+        self.tr_config.walk_mode = walk_mode;
+        self
+    }
+
+    /// Hashing threads.
+    pub fn n_jobs(mut self, n_jobs: usize) -> Self {
+        self.tr_config.n_jobs = n_jobs.max(1);
+        self
+    }
+
+    /// Per-read chunk size in bytes.
+    pub fn read_buffer(mut self, read_buffer: usize) -> Self {
+        self.tr_config.read_buffer = Some(read_buffer);
+        self
+    }
+
+    /// Reuses piece hashes of unchanged files from this cache file and updates it.
+    pub fn piece_cache(mut self, path: impl Into<String>) -> Self {
+        self.tr_config.piece_cache = Some(path.into());
+        self
+    }
+
+    /// Where the torrent will be written, left out of the content if inside the target.
+    pub fn output_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.tr_config.output_path = path.as_ref().canonicalize().ok();
+        self
+    }
+
+    /// Suppresses hashing progress, on by default; pass `false` to draw it.
+    pub fn quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// Lets `p` on the terminal and SIGUSR1 pause hashing, off by default. Takes over the
+    /// controlling terminal and installs process-wide signal handlers while hashing.
+    pub fn pause_keys(mut self, pause_keys: bool) -> Self {
+        self.tr_config.pause_keys = pause_keys;
+        self
+    }
+
+    /// Walks and hashes `target_path`, a file or directory.
+    pub fn build(self, target_path: &str) -> TrResult<Torrent> {
+        check_piece_length(self.tr_config.piece_length)?;
+        let mut torrent = Torrent::new(
+            self.announce_list.first().map(|tier| tier[0].clone()),
+            (!self.announce_list.is_empty()).then_some(self.announce_list),
+            self.comment,
+            self.created_by,
+            self.creation_date,
+            Some(String::from("UTF-8")),
+        );
+        torrent.set_web_seeds(self.web_seeds);
+        torrent.set_http_seeds(self.http_seeds);
+        torrent.set_nodes(self.nodes);
+        torrent.preflight_torrent(target_path, &self.tr_config)?;
+        torrent.create_torrent(target_path, &self.tr_config, self.quiet)?;
+        Ok(torrent)
+    }
+}
+
+/// Reads `url-list` or `httpseeds`: a list of URLs, or a single URL as a plain string.
+fn read_seeds(value: Option<&Bencode>, what: &str) -> TrResult<Option<Vec<String>>> {
+    let urls = match value {
+        Some(Bencode::Bytes(b)) if !b.is_empty() => vec![String::from_utf8(b.to_vec())?],
+        Some(Bencode::List(items)) => {
+            let mut urls = Vec::new();
+            for item in items {
+                match item {
+                    Bencode::Bytes(b) => urls.push(String::from_utf8(b.to_vec())?),
+                    _ => {
+                        return Err(TrError::InvalidTorrent(format!(
+                            "{what} URL is not a string"
+                        )));
+                    }
+                }
             }
+            urls
+        }
+        _ => Vec::new(),
+    };
+    Ok((!urls.is_empty()).then_some(urls))
+}
+
+/// DHT nodes as `[host, port]` pairs. Entries that are not are skipped with a warning
+/// rather than making the torrent unreadable; `None` unless `value` is a list.
+fn read_nodes(value: Option<&Bencode>) -> Option<Vec<(String, u16)>> {
+    let items = match value {
+        Some(Bencode::List(items)) => items,
+        Some(_) => {
+            warn(
+                WarningKind::Data,
+                "'nodes' is not a list of DHT nodes, kept unchanged.",
+            );
+            return None;
+        }
+        None => return None,
+    };
+    let mut nodes = Vec::new();
+    for (i, item) in items.iter().enumerate() {
+        let node = match item {
+            Bencode::List(pair) => match pair.as_slice() {
+                [Bencode::Bytes(host), Bencode::Int(port)] => {
+                    match (String::from_utf8(host.to_vec()), u16::try_from(*port)) {
+                        (Ok(host), Ok(port)) => Some((host, port)),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            },
+            _ => None,
+        };
+        match node {
+            Some(node) => nodes.push(node),
+            None => warn(
+                WarningKind::Data,
+                format!("DHT node {i} is not a host and port, skipped."),
+            ),
         }
     }
+    (!nodes.is_empty()).then_some(nodes)
+}
+
+/// Piece lengths accepted for new torrents: powers of two from 16 KiB to 128 MiB.
+pub fn check_piece_length(piece_length: usize) -> TrResult<()> {
+    if !piece_length.is_power_of_two()
+        || !(MIN_PIECE_SIZE..=MAX_PIECE_SIZE).contains(&(piece_length.trailing_zeros() as u8))
+    {
+        return Err(TrError::ParseError(format!(
+            "piece length {piece_length} is not a power of two between 1 << {MIN_PIECE_SIZE} and 1 << {MAX_PIECE_SIZE}"
+        )));
+    }
+    Ok(())
 }
 
 impl Display for Torrent {
@@ -423,6 +1148,29 @@ impl Display for Torrent {
                     }
                 }
 
+                if let Some(url_list) = &self.url_list {
+                    writeln!(f, "  Web Seeds:")?;
+                    for url in url_list {
+                        writeln!(f, "    {url}")?;
+                    }
+                }
+                if let Some(httpseeds) = &self.httpseeds {
+                    writeln!(f, "  HTTP Seeds:")?;
+                    for url in httpseeds {
+                        writeln!(f, "    {url}")?;
+                    }
+                }
+                if let Some(nodes) = &self.nodes {
+                    writeln!(f, "  DHT Nodes:")?;
+                    for (host, port) in nodes {
+                        if host.contains(':') {
+                            writeln!(f, "    [{host}]:{port}")?;
+                        } else {
+                            writeln!(f, "    {host}:{port}")?;
+                        }
+                    }
+                }
+
                 if let Some(comment) = &self.comment {
                     writeln!(f, "  Comment: {comment}")?;
                 }
@@ -430,11 +1178,7 @@ impl Display for Torrent {
                     writeln!(f, "  Created by: {created_by}")?;
                 }
                 if let Some(date) = self.creation_date {
-                    let dt = Local
-                        .timestamp_opt(date, 0)
-                        .unwrap()
-                        .format("%Y-%m-%d %H:%M:%S");
-                    writeln!(f, "  Creation date: {date} [{dt}]")?;
+                    writeln!(f, "  Creation date: {date} [{}]", format_timestamp(date))?;
                 }
                 if let Some(encoding) = &self.encoding {
                     writeln!(f, "  Encoding: {encoding}")?;
@@ -442,11 +1186,44 @@ impl Display for Torrent {
                 if let Some(hash) = &self.hash {
                     writeln!(f, "  Hash: {hash}")?;
                 }
+                if !self.extra.is_empty() {
+                    writeln!(f, "  Extra keys:")?;
+                    for (key, value) in &self.extra {
+                        writeln!(f, "    {key}: {value}")?;
+                    }
+                }
+                if let Some(v1) = self.info_hash_v1_bytes() {
+                    writeln!(f, "  Info hash v1: {}", hex::encode(v1))?;
+                    writeln!(f, "  Info hash v1 (base32): {}", base32_encode(&v1))?;
+                }
+                if let Some(v2) = self.info_hash_v2() {
+                    writeln!(f, "  Info hash v2: {v2}")?;
+                    writeln!(
+                        f,
+                        "  Info hash v2 (truncated): {}",
+                        &v2[..SHA1_HASH_SIZE * 2]
+                    )?;
+                    if self.is_hybrid() {
+                        writeln!(f, "  Hybrid: v1 + v2")?;
+                    }
+                }
+                if let Some(magnet) = self.magnet_link() {
+                    writeln!(f, "  Magnet: {magnet}")?;
+                }
 
                 writeln!(f, "  Private: {}", info.private)?;
                 if let Some(source) = &info.source {
                     writeln!(f, "  Source: {}", source)?;
                 }
+                if let Some(collections) = &info.collections {
+                    writeln!(f, "  Collections: {}", collections.join(", "))?;
+                }
+                if !info.extra.is_empty() {
+                    writeln!(f, "  Extra info keys:")?;
+                    for (key, value) in &info.extra {
+                        writeln!(f, "    {key}: {value}")?;
+                    }
+                }
 
                 writeln!(
                     f,
@@ -457,10 +1234,8 @@ impl Display for Torrent {
 
                 let total_length = if let Some(files) = &info.files {
                     files.iter().map(|f| f.length).sum()
-                } else if let Some(length) = info.length {
-                    length
                 } else {
-                    0
+                    info.length.unwrap_or_default()
                 };
                 writeln!(
                     f,
@@ -469,15 +1244,7 @@ impl Display for Torrent {
                     human_size(total_length)
                 )?;
 
-                writeln!(
-                    f,
-                    "  Pieces: {}",
-                    if info.piece_length > 0 {
-                        (total_length + info.piece_length - 1) / info.piece_length
-                    } else {
-                        0
-                    }
-                )?;
+                writeln!(f, "  Pieces: {}", self.piece_count())?;
 
                 if let Some(files) = &info.files {
                     writeln!(
@@ -516,3 +1283,124 @@ impl Display for Torrent {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PIECES_ROOT: [u8; 32] = [7; 32];
+
+    /// Info dict of a hybrid single-file torrent with a BEP 38 `similar` list, as
+    /// `before_private` + `private` + `before_source` + `source` + `e`.
+    fn hybrid_info(private: &[u8], source: &[u8]) -> Vec<u8> {
+        let mut info = b"d9:file treed5:a.bind0:d6:lengthi5e11:pieces root32:".to_vec();
+        info.extend_from_slice(&PIECES_ROOT);
+        info.extend_from_slice(b"eee6:lengthi5e12:meta versioni2e4:name5:a.bin");
+        info.extend_from_slice(b"12:piece lengthi16384e6:pieces20:");
+        info.extend_from_slice(&[9; 20]);
+        info.extend_from_slice(private);
+        info.extend_from_slice(b"7:similarl20:");
+        info.extend_from_slice(&[1; 20]);
+        info.extend_from_slice(b"e");
+        info.extend_from_slice(source);
+        info.extend_from_slice(b"e");
+        info
+    }
+
+    fn hybrid_torrent() -> Torrent {
+        let mut data = b"d8:announce10:http://t/a4:info".to_vec();
+        data.extend_from_slice(&hybrid_info(b"", b""));
+        data.extend_from_slice(b"e");
+        Torrent::from_bytes(&data).unwrap()
+    }
+
+    fn reencoded(torrent: &Torrent) -> Torrent {
+        let mut data = Vec::new();
+        torrent.encode(&mut data).unwrap();
+        Torrent::from_bytes(&data).unwrap()
+    }
+
+    #[test]
+    fn edits_patch_only_their_info_key() {
+        let mut torrent = hybrid_torrent();
+        let v1 = torrent.info_hash_v1();
+        let v2 = torrent.info_hash_v2();
+
+        torrent.set_source(Some(String::from("X"))).unwrap();
+        torrent.set_private(true).unwrap();
+        let edited = reencoded(&torrent);
+        assert_eq!(
+            edited.info_raw().unwrap(),
+            hybrid_info(b"7:privatei1e", b"6:source1:X")
+        );
+        assert_eq!(edited.get_info().unwrap().meta_version, Some(2));
+        assert_ne!(edited.info_hash_v1(), v1);
+        assert_ne!(edited.info_hash_v2(), v2);
+        assert!(edited.is_hybrid());
+
+        torrent.set_source(None).unwrap();
+        torrent.set_private(false).unwrap();
+        assert_eq!(
+            reencoded(&torrent).info_raw().unwrap(),
+            hybrid_info(b"", b"")
+        );
+        assert_eq!(torrent.info_hash_v2(), v2);
+    }
+
+    fn with_nodes(nodes: &[u8]) -> Torrent {
+        let mut data = b"d4:info".to_vec();
+        data.extend_from_slice(&hybrid_info(b"", b""));
+        data.extend_from_slice(b"5:nodes");
+        data.extend_from_slice(nodes);
+        data.extend_from_slice(b"e");
+        Torrent::from_bytes(&data).unwrap()
+    }
+
+    #[test]
+    fn bad_nodes_are_skipped_or_kept() {
+        let torrent = with_nodes(b"ll1:ai1eel1:b1:xel2:\xff\xfei2eel1:ci70000eel1:di6881eee");
+        assert_eq!(
+            torrent.nodes(),
+            [(String::from("a"), 1), (String::from("d"), 6881)]
+        );
+
+        let torrent = reencoded(&with_nodes(b"7:a.b:123"));
+        assert!(torrent.nodes().is_empty());
+        let mut data = Vec::new();
+        torrent.encode(&mut data).unwrap();
+        let kept = b"5:nodes7:a.b:123";
+        assert!(data.windows(kept.len()).any(|w| w == kept));
+    }
+
+    #[test]
+    fn symlink_entries_round_trip() {
+        let mut info = b"d5:filesld4:attr1:l6:lengthi0e4:pathl4:linke12:symlink pathl3:sub5:a.bineed6:lengthi5e4:pathl3:sub5:a.bineee".to_vec();
+        info.extend_from_slice(b"4:name1:t12:piece lengthi16384e6:pieces20:");
+        info.extend_from_slice(&[9; 20]);
+        info.extend_from_slice(b"e");
+        let mut data = b"d4:info".to_vec();
+        data.extend_from_slice(&info);
+        data.extend_from_slice(b"e");
+        let torrent = Torrent::from_bytes(&data).unwrap();
+        let files = torrent.get_info().unwrap().files.clone().unwrap();
+        assert_eq!(
+            files[0].symlink,
+            Some(vec![String::from("sub"), String::from("a.bin")])
+        );
+        assert!(!files[0].padding && files[1].symlink.is_none());
+        assert_eq!(reencoded(&torrent).info_raw().unwrap(), info);
+    }
+
+    #[test]
+    fn trackers_leave_the_info_dict_alone() {
+        let mut torrent = hybrid_torrent();
+        torrent.set_trackers(vec![vec![String::from("http://other/a")]]);
+        torrent.set_source(Some(String::from("OTHER"))).unwrap();
+        let copy = reencoded(&torrent);
+        assert_eq!(copy.trackers(), vec![vec![String::from("http://other/a")]]);
+        assert_eq!(
+            copy.info_raw().unwrap(),
+            hybrid_info(b"", b"6:source5:OTHER")
+        );
+    }
+}
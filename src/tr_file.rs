@@ -1,45 +1,204 @@
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Result as ioResult, Write};
 use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
 
-use natord::compare_ignore_case;
-
-use crate::bencode::{bencode_string, bencode_string_list, bencode_uint};
+use crate::bencode::{write_bytes, write_string, write_string_list, write_uint};
+use crate::tr_info::{MerkleHash, SortLocale};
 use crate::utils::human_size;
 
+const WINDOWS_RESERVED: [char; 7] = [':', '*', '?', '"', '<', '>', '|'];
+
+static PATH_SUBSTITUTIONS: OnceLock<Vec<(char, String)>> = OnceLock::new();
+
+/// Sets the character substitutions for torrent paths that cannot exist locally,
+/// must be called before any path is resolved.
+pub fn set_path_substitutions(substitutions: Vec<(char, String)>) {
+    let _ = PATH_SUBSTITUTIONS.set(substitutions);
+}
+
+fn path_substitutions() -> &'static [(char, String)] {
+    PATH_SUBSTITUTIONS.get_or_init(|| {
+        if cfg!(windows) {
+            WINDOWS_RESERVED
+                .iter()
+                .map(|&c| (c, String::from("_")))
+                .collect()
+        } else {
+            Vec::new()
+        }
+    })
+}
+
+/// Local form of a torrent path component, with substituted characters replaced.
+pub fn local_component(component: &str) -> Cow<'_, str> {
+    let substitutions = path_substitutions();
+    if !component
+        .chars()
+        .any(|c| substitutions.iter().any(|(from, _)| *from == c))
+    {
+        return Cow::Borrowed(component);
+    }
+    let mut local = String::with_capacity(component.len());
+    for c in component.chars() {
+        match substitutions.iter().find(|(from, _)| *from == c) {
+            Some((_, to)) => local.push_str(to),
+            None => local.push(c),
+        }
+    }
+    Cow::Owned(local)
+}
+
+/// One file of the content, `path` relative to the torrent name.
+#[derive(Clone)]
 pub struct TrFile {
     pub length: usize,
     pub path: Vec<String>,
+    pub padding: bool, // BEP 47 `attr` p: zeros aligning the next file, never on disk
+    /// BEP 47 `attr` l: a link stored as such, its target as path components relative to
+    /// the torrent root. Links have no data, `length` is 0.
+    pub symlink: Option<Vec<String>>,
 }
 
 impl TrFile {
-    fn bencode(&self) -> Vec<u8> {
-        let mut bcode: Vec<u8> = Vec::new();
-        bcode.push(b'd');
-        bcode.extend(bencode_string("length"));
-        bcode.extend(bencode_uint(self.length));
-        bcode.extend(bencode_string("path"));
-        bcode.extend(bencode_string_list(&self.path));
-        bcode.push(b'e');
-        bcode
+    /// Padding file filling the piece after a file of `length` bytes, hybrid torrents
+    /// start every file on a piece boundary.
+    pub fn padding_after(length: usize, piece_length: usize) -> Option<TrFile> {
+        let rest = length % piece_length;
+        (rest != 0).then(|| {
+            let pad = piece_length - rest;
+            TrFile {
+                length: pad,
+                path: vec![String::from(".pad"), pad.to_string()],
+                padding: true,
+                symlink: None,
+            }
+        })
+    }
+
+    fn encode<W: Write>(&self, out: &mut W) -> ioResult<()> {
+        out.write_all(b"d")?;
+        if self.padding {
+            write_string(out, "attr")?;
+            write_string(out, "p")?;
+        } else if self.symlink.is_some() {
+            write_string(out, "attr")?;
+            write_string(out, "l")?;
+        }
+        write_string(out, "length")?;
+        write_uint(out, self.length)?;
+        write_string(out, "path")?;
+        write_string_list(out, &self.path)?;
+        if let Some(target) = &self.symlink {
+            write_string(out, "symlink path")?;
+            write_string_list(out, target)?;
+        }
+        out.write_all(b"e")
     }
 
+    /// Local path of the file, the substituted form is used when the path as written in
+    /// the torrent does not exist. Padding files get an empty path and are read as zeros.
     pub fn join_full_path(&self, base_path: &Path) -> PathBuf {
+        if self.padding {
+            return PathBuf::new();
+        }
         let mut full_path = base_path.to_path_buf();
         for segment in &self.path {
             full_path.push(segment);
         }
-        full_path
+        match self.local_path() {
+            Some(local_path) if !full_path.exists() => base_path.join(local_path),
+            _ => full_path,
+        }
+    }
+
+    /// Relative path with substitutions applied, `None` if no component needs one.
+    pub fn local_path(&self) -> Option<PathBuf> {
+        let components: Vec<Cow<'_, str>> = self.path.iter().map(|c| local_component(c)).collect();
+        if components.iter().all(|c| matches!(c, Cow::Borrowed(_))) {
+            return None;
+        }
+        Some(components.iter().map(|c| c.as_ref()).collect())
     }
 }
 
-pub fn bencode_file_list(list: &[TrFile]) -> Vec<u8> {
-    let mut bcode: Vec<u8> = Vec::new();
-    bcode.push(b'l');
+pub fn write_file_list<W: Write>(out: &mut W, list: &[TrFile]) -> ioResult<()> {
+    out.write_all(b"l")?;
     for item in list {
-        bcode.extend(item.bencode());
+        item.encode(out)?;
     }
-    bcode.push(b'e');
-    bcode
+    out.write_all(b"e")
+}
+
+/// Writes the BEP 52 `file tree`: nested dicts down to each file, whose dict holds its
+/// `length` and `pieces root` under an empty key. The single file of a single-file
+/// torrent is listed under `name`, padding files are left out.
+pub fn write_file_tree<W: Write>(
+    out: &mut W,
+    list: &[TrFile],
+    name: &str,
+    pieces_roots: &[Option<MerkleHash>],
+) -> ioResult<()> {
+    enum Entry<'a> {
+        Dir(BTreeMap<&'a str, Entry<'a>>),
+        File(usize),
+    }
+
+    fn write_entry<W: Write>(
+        out: &mut W,
+        entry: &Entry,
+        list: &[TrFile],
+        pieces_roots: &[Option<MerkleHash>],
+    ) -> ioResult<()> {
+        out.write_all(b"d")?;
+        match entry {
+            Entry::Dir(children) => {
+                for (name, child) in children {
+                    write_string(out, name)?;
+                    write_entry(out, child, list, pieces_roots)?;
+                }
+            }
+            Entry::File(index) => {
+                write_string(out, "")?;
+                out.write_all(b"d")?;
+                write_string(out, "length")?;
+                write_uint(out, list[*index].length)?;
+                if let Some(root) = &pieces_roots[*index] {
+                    write_string(out, "pieces root")?;
+                    write_bytes(out, root)?;
+                }
+                out.write_all(b"e")?;
+            }
+        }
+        out.write_all(b"e")
+    }
+
+    let mut root = BTreeMap::new();
+    for (index, tr_file) in list.iter().enumerate().filter(|(_, f)| !f.padding) {
+        let path: Vec<&str> = if tr_file.path.is_empty() {
+            vec![name]
+        } else {
+            tr_file.path.iter().map(String::as_str).collect()
+        };
+        let (file_name, dirs) = path.split_last().expect("path is not empty");
+        let mut dir = &mut root;
+        for segment in dirs {
+            dir = match dir
+                .entry(*segment)
+                .or_insert_with(|| Entry::Dir(BTreeMap::new()))
+            {
+                Entry::Dir(children) => children,
+                Entry::File(_) => {
+                    return Err(std::io::Error::other(format!(
+                        "'{segment}' is both a file and a directory"
+                    )));
+                }
+            };
+        }
+        dir.insert(file_name, Entry::File(index));
+    }
+    write_entry(out, &Entry::Dir(root), list, pieces_roots)
 }
 
 #[derive(Debug)]
@@ -93,18 +252,25 @@ impl Node {
         root
     }
 
-    pub fn print_tree(&self) {
+    pub fn write_tree<W: Write>(&self, out: &mut W, locale: SortLocale) -> ioResult<()> {
         let mut names: Vec<&String> = self.children.keys().collect();
-        names.sort_by(|a, b| compare_ignore_case(a, b));
+        names.sort_by(|a, b| locale.compare(a, b));
 
         for (idx, name) in names.iter().enumerate() {
             let last = idx == names.len() - 1;
             let child = self.children.get(*name).unwrap();
-            child.print_branch("", last);
+            child.write_branch(out, "", last, locale)?;
         }
+        Ok(())
     }
 
-    fn print_branch(&self, prefix: &str, is_last: bool) {
+    fn write_branch<W: Write>(
+        &self,
+        out: &mut W,
+        prefix: &str,
+        is_last: bool,
+        locale: SortLocale,
+    ) -> ioResult<()> {
         let (connector, child_prefix) = if is_last {
             ("└── ", "    ")
         } else {
@@ -112,25 +278,74 @@ impl Node {
         };
 
         match self.length {
-            Some(sz) => println!(
+            Some(sz) => writeln!(
+                out,
                 "{}{}{} ({} [{}])",
                 prefix,
                 connector,
                 self.name,
                 sz,
                 human_size(sz)
-            ),
-            None => println!("{}{}{}", prefix, connector, self.name),
+            )?,
+            None => writeln!(out, "{}{}{}", prefix, connector, self.name)?,
         }
 
         let mut names: Vec<&String> = self.children.keys().collect();
-        names.sort_by(|a, b| compare_ignore_case(a, b));
+        names.sort_by(|a, b| locale.compare(a, b));
 
         let new_prefix = format!("{prefix}{child_prefix}");
         for (idx, name) in names.iter().enumerate() {
             let last = idx == names.len() - 1;
             let child = self.children.get(*name).unwrap();
-            child.print_branch(&new_prefix, last);
+            child.write_branch(out, &new_prefix, last, locale)?;
         }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &[&str], length: usize) -> TrFile {
+        TrFile {
+            length,
+            path: path.iter().map(|s| s.to_string()).collect(),
+            padding: false,
+            symlink: None,
+        }
+    }
+
+    #[test]
+    fn file_tree_nests_dirs_and_skips_padding() {
+        let mut pad = file(&[".pad", "3"], 3);
+        pad.padding = true;
+        let list = [file(&["b", "x"], 5), pad, file(&["a"], 0)];
+        let mut out = Vec::new();
+        write_file_tree(&mut out, &list, "t", &[Some([7; 32]), None, None]).unwrap();
+        let mut expected = b"d1:ad0:d6:lengthi0eee1:bd1:xd0:d6:lengthi5e11:pieces root32:".to_vec();
+        expected.extend_from_slice(&[7; 32]);
+        expected.extend_from_slice(b"eeee");
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn single_file_tree_uses_the_name() {
+        let mut out = Vec::new();
+        write_file_tree(&mut out, &[file(&[], 5)], "solo.bin", &[Some([7; 32])]).unwrap();
+        let mut expected = b"d8:solo.bind0:d6:lengthi5e11:pieces root32:".to_vec();
+        expected.extend_from_slice(&[7; 32]);
+        expected.extend_from_slice(b"eee");
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn padding_fills_up_to_a_piece_boundary() {
+        let pad = TrFile::padding_after(40000, 16384).unwrap();
+        assert_eq!(pad.length, 9152);
+        assert_eq!(pad.path, [".pad", "9152"]);
+        assert!(pad.padding);
+        assert!(TrFile::padding_after(32768, 16384).is_none());
+        assert!(TrFile::padding_after(0, 16384).is_none());
     }
 }
@@ -1,22 +1,218 @@
-use std::cell::RefCell;
+use std::borrow::Cow;
 use std::cmp;
-use std::collections::{HashMap, HashSet, hash_map::Entry};
-use std::fs::{File, metadata};
-use std::io::{Read, Seek, SeekFrom};
-use std::path::{MAIN_SEPARATOR, Path};
+use std::collections::{BTreeMap, HashMap, HashSet, hash_map::Entry};
+use std::ffi::OsStr;
+use std::fs::{File, Metadata, metadata};
+use std::io::{self, Error as ioError, Read, Result as ioResult, Seek, SeekFrom, Write};
+use std::path::{MAIN_SEPARATOR, Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use indicatif::ProgressBar;
 use natord::compare_ignore_case;
-use rayon::{ThreadPoolBuilder, prelude::*};
+use rayon::{ThreadPool, ThreadPoolBuilder, prelude::*};
+use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
+use sha2::Sha256;
 use walkdir::WalkDir;
 
-use crate::bencode::{bencode_bytes, bencode_string, bencode_uint};
-use crate::tr_file::{TrFile, bencode_file_list};
-use crate::utils::{TrError, TrResult, finish_progress_bar, human_size, make_progress_bar};
+use crate::MIN_PIECE_SIZE;
+use crate::bencode::{
+    ExtraKeys, write_bytes, write_extra_before, write_string, write_string_list, write_uint,
+};
+use crate::crash;
+use crate::glob::Glob;
+use crate::numa;
+use crate::pause::{PauseListener, time_up, wait_if_paused};
+use crate::perf::PerfStats;
+use crate::piece_buffer::PieceBufferPool;
+use crate::piece_cache::PieceCache;
+use crate::throttle::acquire_slot;
+use crate::tr_file::{TrFile, local_component, write_file_list, write_file_tree};
+use crate::utils::{Progress, TrError, TrResult, human_size};
+use crate::warnings::{WarningKind, warn};
 
-const SHA1_HASH_SIZE: usize = 20;
+pub const SHA1_HASH_SIZE: usize = 20;
+pub const SHA256_HASH_SIZE: usize = 32;
+const DEF_READ_BUFFER: usize = 4 << 20; // 4 MiB
+const V2_BLOCK_SIZE: usize = 16 << 10; // BEP 52 merkle tree leaves
+const AUTO_MAX_PIECES: usize = 2000; // the automatic piece size gives 1000 to 2000 pieces
+const AUTO_MAX_PIECE_SIZE: u8 = 24; // larger pieces trip client compatibility warnings
 
+/// Info dict keys with a field in `TrInfo` or written by v2 torrents, all others are extra.
+pub const KNOWN_INFO_KEYS: [&str; 10] = [
+    "collections",
+    "file tree",
+    "files",
+    "length",
+    "meta version",
+    "name",
+    "piece length",
+    "pieces",
+    "private",
+    "source",
+];
+
+/// OS litter left out with `--skip-junk`, compared case-insensitively.
+const JUNK_NAMES: [&str; 9] = [
+    ".DS_Store",
+    ".AppleDouble",
+    ".Spotlight-V100",
+    ".Trashes",
+    "Thumbs.db",
+    "ehthumbs.db",
+    "desktop.ini",
+    "@eaDir",
+    "$RECYCLE.BIN",
+];
+
+pub type PieceHash = [u8; SHA1_HASH_SIZE];
+pub type MerkleHash = [u8; SHA256_HASH_SIZE];
+
+/// Name comparator for sorted walk modes and tree printing. `None` in `TrConfig` keeps
+/// the historical choice: byte order for `Alphabetical`, natural for the breadth-first modes.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortLocale {
+    C,       // byte order
+    Natural, // case-insensitive, digit runs compared as numbers
+    Unicode, // case-insensitive by Unicode lowercase, byte order on ties
+}
+
+impl FromStr for SortLocale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "c" => Ok(SortLocale::C),
+            "natural" => Ok(SortLocale::Natural),
+            "unicode" => Ok(SortLocale::Unicode),
+            _ => Err(format!(
+                "Invalid sort locale '{s}', expected c, natural or unicode"
+            )),
+        }
+    }
+}
+
+impl SortLocale {
+    pub fn compare(&self, a: &str, b: &str) -> cmp::Ordering {
+        match self {
+            SortLocale::C => a.cmp(b),
+            SortLocale::Natural => compare_ignore_case(a, b),
+            SortLocale::Unicode => a
+                .to_lowercase()
+                .cmp(&b.to_lowercase())
+                .then_with(|| a.cmp(b)),
+        }
+    }
+
+    fn compare_paths(&self, a: &[String], b: &[String]) -> cmp::Ordering {
+        a.iter()
+            .zip(b)
+            .map(|(seg_a, seg_b)| self.compare(seg_a, seg_b))
+            .find(|cmp_res| *cmp_res != cmp::Ordering::Equal)
+            .unwrap_or_else(|| a.len().cmp(&b.len()))
+    }
+}
+
+/// What to do with symlinks and junctions in the target.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LinkPolicy {
+    /// Walks through links and embeds what they point to, the only mode of earlier versions.
+    Follow,
+    /// Follows links inside the target, leaves out links resolving outside it and files
+    /// already reached through another path.
+    Skip,
+    /// Stores each link as a BEP 47 symlink entry instead of its data; v1 only.
+    Store,
+}
+
+impl FromStr for LinkPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "follow" | "embed" => Ok(LinkPolicy::Follow),
+            "skip" => Ok(LinkPolicy::Skip),
+            "store" => Ok(LinkPolicy::Store),
+            _ => Err(format!(
+                "Invalid link policy '{s}', expected follow, skip or store"
+            )),
+        }
+    }
+}
+
+/// What to do with cloud placeholders, files whose data is only fetched on access.
+#[derive(Clone, Copy)]
+pub enum OfflinePolicy {
+    Hydrate, // read each placeholder once before hashing, so its data is local
+    Skip,
+    Abort,
+}
+
+impl FromStr for OfflinePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hydrate" => Ok(OfflinePolicy::Hydrate),
+            "skip" => Ok(OfflinePolicy::Skip),
+            "abort" => Ok(OfflinePolicy::Abort),
+            _ => Err(format!(
+                "Invalid offline files policy '{s}', expected hydrate, skip or abort"
+            )),
+        }
+    }
+}
+
+/// Hashes a created torrent carries.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum MetaVersion {
+    V1,     // SHA-1 pieces over the concatenated files
+    V2,     // BEP 52 SHA-256 merkle tree per file
+    Hybrid, // both, files padded to piece boundaries so the two agree
+}
+
+impl FromStr for MetaVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "1" => Ok(MetaVersion::V1),
+            "2" => Ok(MetaVersion::V2),
+            "hybrid" => Ok(MetaVersion::Hybrid),
+            _ => Err(format!(
+                "Invalid meta version '{s}', expected 1, 2 or hybrid"
+            )),
+        }
+    }
+}
+
+/// Order in which verify reads pieces, to reach likely problems early in a long run.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum VerifyOrder {
+    Torrent,     // piece order
+    SizeDesc,    // pieces of the largest files first
+    Path,        // by the path of a piece's first file
+    RecentFirst, // pieces of the most recently modified files first
+}
+
+impl FromStr for VerifyOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "torrent" => Ok(VerifyOrder::Torrent),
+            "size-desc" => Ok(VerifyOrder::SizeDesc),
+            "path" => Ok(VerifyOrder::Path),
+            "recent-first" => Ok(VerifyOrder::RecentFirst),
+            _ => Err(format!(
+                "Invalid verify order '{s}', expected torrent, size-desc, path or recent-first"
+            )),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub enum WalkMode {
     Default,
     Alphabetical,
@@ -25,12 +221,72 @@ pub enum WalkMode {
     FileSize,
 }
 
+#[derive(Clone)]
 pub struct TrConfig {
     pub piece_length: usize,
+    /// Ignore `piece_length` and pick one from the content size with `auto_piece_size`.
+    pub auto_piece_size: bool,
+    pub meta_version: MetaVersion,
+    /// Follow every v1 file but the last with a BEP 47 padding file up to the next piece
+    /// boundary, as hybrid torrents always do, so no piece spans two files.
+    pub pad_files: bool,
     pub private: bool,
     pub n_jobs: usize,
+    pub read_buffer: Option<usize>,
     pub walk_mode: WalkMode,
+    pub sort_locale: Option<SortLocale>,
+    pub stable_order_check: bool,
+    pub file_order: Option<Vec<Vec<String>>>,
+    pub link_policy: LinkPolicy,
+    pub offline_files: OfflinePolicy,
+    pub output_path: Option<PathBuf>,
+    pub exclude_torrents: bool,
+    /// Leave out the files and directories of `JUNK_NAMES` and AppleDouble `._*` files.
+    pub skip_junk: bool,
+    /// Files and directories left out of a directory target.
+    pub exclude: Vec<Glob>,
+    /// If not empty, only the files matching one of these are kept, after `exclude`.
+    pub include: Vec<Glob>,
+    /// Files of a directory target smaller or larger than these, in bytes, are left out.
+    pub min_file_size: Option<usize>,
+    pub max_file_size: Option<usize>,
+    pub piece_cache: Option<String>,
     pub source: Option<String>,
+    pub collections: Vec<String>,
+    pub info_keys: ExtraKeys,
+    /// Let `p` on the terminal and SIGUSR1 pause hashing, see `PauseListener`. This takes
+    /// over the controlling terminal and installs process-wide signal handlers, so only the
+    /// binary turns it on.
+    pub pause_keys: bool,
+}
+
+/// How `TrInfo::verify` reads the content.
+pub struct VerifyOptions<'a> {
+    pub n_jobs: usize,
+    pub read_buffer: Option<usize>,
+    /// Pieces to check, all if `None`.
+    pub selected: Option<&'a [bool]>,
+    pub order: VerifyOrder,
+    /// Reads failing pieces a second time and passes those that match then.
+    pub paranoid: bool,
+    pub quiet: bool,
+    /// As `TrConfig::pause_keys`.
+    pub pause_keys: bool,
+}
+
+impl Default for VerifyOptions<'_> {
+    /// One thread, every piece in torrent order, no progress output and no pause keys.
+    fn default() -> Self {
+        VerifyOptions {
+            n_jobs: 1,
+            read_buffer: None,
+            selected: None,
+            order: VerifyOrder::Torrent,
+            paranoid: false,
+            quiet: true,
+            pause_keys: false,
+        }
+    }
 }
 
 struct FileHashInfo {
@@ -39,24 +295,215 @@ struct FileHashInfo {
     length: usize,
 }
 
+pub struct CompareInfo {
+    pub both_ok: usize,
+    pub a_only: Vec<usize>,
+    pub b_only: Vec<usize>,
+    pub neither_same: Vec<usize>,
+    pub neither_diverge: Vec<usize>,
+}
+
+/// Outcome of `migrate_check`: pieces of the new copy that pass, and for the failing
+/// ones, how the old copy compares.
+pub struct MigrateInfo {
+    pub new_ok: usize,
+    pub old_only: Vec<usize>,
+    pub both_bad_same: Vec<usize>,
+    pub both_bad_diverge: Vec<usize>,
+}
+
+/// Why a file failed verification.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FailReason {
+    Missing,
+    SizeMismatch,
+    HashMismatch,
+}
+
+impl FailReason {
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            FailReason::Missing => "missing",
+            FailReason::SizeMismatch => "size mismatch",
+            FailReason::HashMismatch => "hash mismatch",
+        }
+    }
+}
+
+/// A file that failed verification, `path` relative to the torrent name.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FailedFile {
+    pub path: String,
+    pub length: usize,
+    pub reason: FailReason,
+}
+
+/// Outcome of `verify`, failed files in file order.
+pub struct VerifySummary {
+    pub total_pieces: usize,
+    pub failed_pieces: usize,
+    /// Pieces left out of the selection, `None` without one.
+    pub skipped_pieces: Option<usize>,
+    /// Non-padding files.
+    pub total_files: usize,
+    /// Files of which no piece was read because all of them were left out of the
+    /// selection; trusted rather than passed. `None` without a selection.
+    pub skipped_files: Option<usize>,
+    pub failed_files: Vec<FailedFile>,
+    /// Indices of the failed pieces, sorted.
+    pub failed_indices: Vec<usize>,
+    /// Pieces left unread because `--max-duration` ran out, sorted.
+    pub unchecked: Vec<usize>,
+    /// Pieces that failed and then passed when read again with `paranoid`, sorted.
+    pub passed_on_retry: Vec<usize>,
+}
+
+impl VerifySummary {
+    /// Prints the result as text: piece and file counts and the failed files.
+    pub fn print(&self) {
+        println!("Verification Result:");
+
+        let failed_piece_count = self.failed_pieces;
+        let skipped_piece_count = self.skipped_pieces.unwrap_or(0);
+        let unchecked_piece_count = self.unchecked.len();
+        let total_pieces = self.total_pieces;
+        let passed_piece_count =
+            total_pieces - failed_piece_count - skipped_piece_count - unchecked_piece_count;
+        let total_files = self.total_files;
+        let failed_file_count = self.failed_files.len();
+        let skipped_file_count = self.skipped_files.unwrap_or(0);
+        let passed_file_count = total_files - failed_file_count - skipped_file_count;
+
+        let mut pieces_line = format!(
+            "Pieces: {total_pieces:8} total = {passed_piece_count:8} passed + {failed_piece_count:8} failed"
+        );
+        if self.skipped_pieces.is_some() {
+            pieces_line += &format!(" + {skipped_piece_count:8} skipped");
+        }
+        if unchecked_piece_count > 0 {
+            pieces_line += &format!(" + {unchecked_piece_count:8} unchecked");
+        }
+        println!("{pieces_line}");
+        let mut files_line = format!(
+            "Files:  {total_files:8} total = {passed_file_count:8} passed + {failed_file_count:8} failed"
+        );
+        if self.skipped_files.is_some() {
+            files_line += &format!(" + {skipped_file_count:8} skipped");
+        }
+        println!("{files_line}");
+        if unchecked_piece_count > 0 {
+            println!(
+                "Time limit reached, {unchecked_piece_count} pieces were not checked and their files counted as passed."
+            );
+        }
+        if !self.passed_on_retry.is_empty() {
+            println!(
+                "\n{} pieces failed, then passed when read again (flaky storage?):",
+                self.passed_on_retry.len()
+            );
+            for piece in &self.passed_on_retry {
+                println!("- piece {piece} [passed on retry]");
+            }
+        }
+
+        if self.failed_files.is_empty() {
+            println!("All files are OK.");
+        } else {
+            println!("\nSome files failed verification:");
+            for file in &self.failed_files {
+                let known_issue = match file.reason {
+                    FailReason::HashMismatch => String::new(),
+                    reason => format!(" [{}]", reason.label()),
+                };
+                println!(
+                    "- {} ({} [{}]){known_issue}",
+                    file.path,
+                    file.length,
+                    human_size(file.length)
+                );
+            }
+        }
+    }
+
+    /// `VerificationFailed` if any piece failed, for callers that only need the verdict.
+    pub fn ensure_passed(&self) -> TrResult<()> {
+        if self.failed_pieces > 0 || !self.failed_files.is_empty() {
+            return Err(TrError::VerificationFailed {
+                files: self.failed_files.len(),
+                pieces: self.failed_pieces,
+            });
+        }
+        Ok(())
+    }
+}
+
 struct FailedInfo {
     files: HashSet<usize>,
-    files_known: HashSet<usize>,
+    /// Files that are missing or of the wrong size, so their pieces were not read.
+    files_known: HashMap<usize, FailReason>,
     pieces: HashSet<usize>,
+    skipped: usize,
+    /// Files whose pieces were all skipped.
+    skipped_files: usize,
+    unchecked: Vec<usize>,
+    passed_on_retry: Vec<usize>,
+}
+
+/// BEP 52 hashes of a created torrent, per file in file order: the `pieces root`
+/// (`None` for empty and padding files) and the piece layer (`None` for files of one
+/// piece or less).
+#[derive(Clone)]
+pub struct V2Hashes {
+    pub pieces_roots: Vec<Option<MerkleHash>>,
+    pub piece_layers: Vec<Option<Vec<u8>>>,
+}
+
+impl V2Hashes {
+    /// Hashes of the final sizes, for estimates before hashing. Roots are numbered so
+    /// every file keeps its own entry in `piece layers`.
+    fn placeholder(tr_files: &[TrFile], piece_length: usize) -> Self {
+        V2Hashes {
+            pieces_roots: tr_files
+                .iter()
+                .enumerate()
+                .map(|(i, f)| {
+                    let mut root = [0u8; SHA256_HASH_SIZE];
+                    root[..size_of::<usize>()].copy_from_slice(&i.to_le_bytes());
+                    (f.length > 0 && !f.padding).then_some(root)
+                })
+                .collect(),
+            piece_layers: tr_files
+                .iter()
+                .map(|f| {
+                    (f.length > piece_length && !f.padding)
+                        .then(|| vec![0u8; f.length.div_ceil(piece_length) * SHA256_HASH_SIZE])
+                })
+                .collect(),
+        }
+    }
 }
 
+/// The info dictionary, with the walk, hash and verify logic for its content.
 pub struct TrInfo {
+    pub collections: Option<Vec<String>>,
     pub files: Option<Vec<TrFile>>,
+    /// Length of a single-file torrent. Ignored if `files` is set too, which some creators
+    /// write; `files` takes precedence as in libtorrent.
     pub length: Option<usize>,
     pub name: Option<String>,
     pub piece_length: usize,
     pub pieces: Vec<u8>,
     pub private: bool,
     pub source: Option<String>,
+    pub meta_version: Option<usize>,
+    pub v2: Option<V2Hashes>,
+    pub extra: ExtraKeys,
 }
 
 impl TrInfo {
-    pub fn new(target_path: String, tr_config: &TrConfig, quiet: bool) -> TrResult<TrInfo> {
+    /// Enumerates the target without hashing, `pieces` is filled with zeros of the final size.
+    pub fn preflight(target_path: &str, tr_config: &TrConfig) -> TrResult<TrInfo> {
         let base_path = Path::new(&target_path);
         let name = base_path
             .file_name()
@@ -71,24 +518,142 @@ impl TrInfo {
 
         if base_metadata.is_file() {
             single_file = true;
+            // skipping the only file leaves nothing to create
+            let policy = match tr_config.offline_files {
+                OfflinePolicy::Skip => OfflinePolicy::Abort,
+                policy => policy,
+            };
+            offline_check(base_path, name, &base_metadata, policy)?;
             tr_files.push(TrFile {
                 length: base_metadata.len() as usize,
                 path: Vec::new(),
+                padding: false,
+                symlink: None,
             });
         } else if base_metadata.is_dir() {
+            let real_base = base_path.canonicalize()?;
+            let mut resolved: Vec<(PathBuf, bool)> = Vec::new();
+            let mut direct_paths: HashSet<PathBuf> = HashSet::new();
+            let relative_of = |path: &Path| {
+                let relative = path.strip_prefix(base_path).unwrap_or(path);
+                relative.to_string_lossy().replace(MAIN_SEPARATOR, "/")
+            };
+            let excluded = |path: &Path| {
+                if tr_config.skip_junk && path.file_name().is_some_and(is_junk) {
+                    return true;
+                }
+                let relative = relative_of(path);
+                tr_config.exclude.iter().any(|glob| glob.matches(&relative))
+            };
+            let included = |path: &Path| {
+                let relative = relative_of(path);
+                tr_config.include.is_empty()
+                    || tr_config.include.iter().any(|glob| glob.matches(&relative))
+            };
+            let store_links = tr_config.link_policy == LinkPolicy::Store;
+            if store_links && tr_config.meta_version != MetaVersion::V1 {
+                return Err(TrError::InvalidPath(String::from(
+                    "--link-policy store needs --meta-version 1, v2 torrents have no symlink entries",
+                )));
+            }
             for entry in WalkDir::new(base_path)
-                .follow_links(true)
+                .follow_links(!store_links)
                 .into_iter()
-                .filter_map(|e| e.ok())
+                // excluded directories are not walked at all
+                .filter_entry(|e| {
+                    if e.depth() == 0 || !e.file_type().is_dir() {
+                        return true;
+                    }
+                    if excluded(e.path()) {
+                        return false;
+                    }
+                    if tr_config.link_policy != LinkPolicy::Skip
+                        || !(e.path_is_symlink() || is_link(e.path()))
+                    {
+                        return true;
+                    }
+                    // nothing under a directory link leaving the target would be kept
+                    match e.path().canonicalize() {
+                        Ok(real_path) if real_path.starts_with(&real_base) => true,
+                        real_path => {
+                            warn(
+                                WarningKind::Symlink,
+                                format!(
+                                    "'{}' resolves outside the target ('{}'), skipped.",
+                                    relative_of(e.path()),
+                                    real_path.map_or_else(
+                                        |_| String::from("dangling"),
+                                        |path| path.display().to_string()
+                                    )
+                                ),
+                            );
+                            false
+                        }
+                    }
+                })
+                .filter_map(|e| match e {
+                    Ok(entry) => Some(entry),
+                    // a symlink or junction to a directory above it would be walked forever
+                    Err(e) => {
+                        if let (Some(path), Some(ancestor)) = (e.path(), e.loop_ancestor()) {
+                            warn(
+                                WarningKind::Symlink,
+                                format!(
+                                    "'{}' links back to '{}', not followed.",
+                                    path.display(),
+                                    ancestor.display()
+                                ),
+                            );
+                        }
+                        None
+                    }
+                })
             {
-                if entry.file_type().is_file() {
-                    let entry_metadata = metadata(entry.path())?;
-                    let relative_path = entry
+                if store_links && (entry.path_is_symlink() || is_link(entry.path())) {
+                    if entry.depth() == 0 {
+                        continue;
+                    }
+                    if excluded(entry.path()) || !included(entry.path()) {
+                        continue;
+                    }
+                    let relative = relative_of(entry.path());
+                    let target = entry
                         .path()
-                        .strip_prefix(base_path)
-                        .map_err(|_| {
-                            TrError::InvalidPath(String::from("Failed to create relative path"))
-                        })?
+                        .canonicalize()
+                        .ok()
+                        .and_then(|real_path| {
+                            let target = real_path.strip_prefix(&real_base).ok()?;
+                            target
+                                .components()
+                                .map(|c| c.as_os_str().to_str().map(str::to_owned))
+                                .collect::<Option<Vec<String>>>()
+                        })
+                        .filter(|target| !target.is_empty());
+                    let Some(target) = target else {
+                        warn(
+                            WarningKind::Symlink,
+                            format!(
+                                "'{relative}' is dangling or points outside the target, not stored."
+                            ),
+                        );
+                        continue;
+                    };
+                    resolved.push((real_base.join(&relative), true));
+                    tr_files.push(TrFile {
+                        length: 0,
+                        path: relative.split('/').map(str::to_owned).collect(),
+                        padding: false,
+                        symlink: Some(target),
+                    });
+                } else if entry.file_type().is_file() {
+                    let entry_metadata = metadata(entry.path())?;
+                    let relative = entry.path().strip_prefix(base_path).map_err(|_| {
+                        TrError::InvalidPath(format!(
+                            "'{}' is not inside the target",
+                            entry.path().display()
+                        ))
+                    })?;
+                    let relative_path: Vec<String> = relative
                         .to_str()
                         .ok_or_else(|| {
                             TrError::InvalidPath(String::from("Path contains invalid UTF-8"))
@@ -97,37 +662,129 @@ impl TrInfo {
                         .map(str::to_owned)
                         .collect();
 
+                    let real_path = entry.path().canonicalize()?;
+                    if tr_config.output_path.as_ref() == Some(&real_path) {
+                        warn(
+                            WarningKind::SkippedFile,
+                            format!(
+                                "Output torrent '{}' is inside the target, excluded.",
+                                relative.display()
+                            ),
+                        );
+                        continue;
+                    }
+                    if tr_config.exclude_torrents
+                        && relative
+                            .extension()
+                            .is_some_and(|ext| ext.eq_ignore_ascii_case("torrent"))
+                    {
+                        continue;
+                    }
+                    if excluded(entry.path()) || !included(entry.path()) {
+                        continue;
+                    }
+                    // a placeholder left out by size is neither hydrated nor an error
+                    let length = entry_metadata.len() as usize;
+                    if tr_config.min_file_size.is_some_and(|min| length < min)
+                        || tr_config.max_file_size.is_some_and(|max| length > max)
+                    {
+                        continue;
+                    }
+                    let shown = relative.to_string_lossy();
+                    if !offline_check(
+                        entry.path(),
+                        &shown,
+                        &entry_metadata,
+                        tr_config.offline_files,
+                    )? {
+                        continue;
+                    }
+                    let direct = real_path == real_base.join(relative);
+                    if direct {
+                        direct_paths.insert(real_path.clone());
+                    }
+                    resolved.push((real_path, direct));
                     tr_files.push(TrFile {
-                        length: entry_metadata.len() as usize,
+                        length,
                         path: relative_path,
+                        padding: false,
+                        symlink: None,
                     });
                 }
             }
+            let filtered = !tr_config.exclude.is_empty()
+                || !tr_config.include.is_empty()
+                || tr_config.min_file_size.is_some()
+                || tr_config.max_file_size.is_some();
+            if tr_files.is_empty() && filtered {
+                return Err(TrError::InvalidPath(format!(
+                    "No files left in {target_path} after the exclude, include and size filters"
+                )));
+            }
+
+            // a file reached directly wins over links to it, otherwise the first one walked
+            let mut seen: HashSet<PathBuf> = HashSet::new();
+            let mut kept = Vec::with_capacity(tr_files.len());
+            for (tr_file, (real_path, direct)) in tr_files.into_iter().zip(resolved) {
+                let issue = if !real_path.starts_with(&real_base) {
+                    Some("resolves outside the target")
+                } else if !direct
+                    && (seen.contains(&real_path) || direct_paths.contains(&real_path))
+                {
+                    Some("duplicates a file already included")
+                } else {
+                    None
+                };
+                if let Some(issue) = issue {
+                    let action = match tr_config.link_policy {
+                        LinkPolicy::Follow | LinkPolicy::Store => "embedded",
+                        LinkPolicy::Skip => "skipped",
+                    };
+                    warn(
+                        WarningKind::Symlink,
+                        format!(
+                            "'{}' {issue} ('{}'), {action}.",
+                            tr_file.path.join("/"),
+                            real_path.display()
+                        ),
+                    );
+                    if let LinkPolicy::Skip = tr_config.link_policy {
+                        continue;
+                    }
+                }
+                seen.insert(real_path);
+                kept.push(tr_file);
+            }
+            tr_files = kept;
         } else {
             return Err(TrError::InvalidPath(String::from(
                 "Target path is neither a file nor a directory",
             )));
         }
 
-        match tr_config.walk_mode {
-            WalkMode::Default => {}
+        type FileOrder = Box<dyn Fn(&TrFile, &TrFile) -> cmp::Ordering>;
+        let primary: Option<FileOrder> = match tr_config.walk_mode {
+            WalkMode::Default => None,
             WalkMode::Alphabetical => {
-                tr_files.sort_by(|a, b| a.path.cmp(&b.path));
+                let locale = tr_config.sort_locale.unwrap_or(SortLocale::C);
+                Some(Box::new(move |a, b| locale.compare_paths(&a.path, &b.path)))
             }
             WalkMode::BreadthFirstAlphabetical => {
-                tr_files.sort_by(|a, b| {
+                let locale = tr_config.sort_locale.unwrap_or(SortLocale::Natural);
+                Some(Box::new(move |a, b| {
                     a.path
                         .iter()
                         .zip(b.path.iter())
                         .find_map(|(seg_a, seg_b)| {
-                            let cmp_res = compare_ignore_case(seg_a, seg_b);
+                            let cmp_res = locale.compare(seg_a, seg_b);
                             (cmp_res != cmp::Ordering::Equal).then_some(cmp_res)
                         })
                         .unwrap_or_else(|| a.path.len().cmp(&b.path.len()))
-                });
+                }))
             }
             WalkMode::BreadthFirstLevel => {
-                tr_files.sort_by(|a, b| {
+                let locale = tr_config.sort_locale.unwrap_or(SortLocale::Natural);
+                Some(Box::new(move |a, b| {
                     a.path
                         .iter()
                         .zip(b.path.iter())
@@ -137,28 +794,116 @@ impl TrInfo {
                                 (true, false) => Some(cmp::Ordering::Less),
                                 (false, true) => Some(cmp::Ordering::Greater),
                                 _ => {
-                                    let cmp_res = compare_ignore_case(seg_a, seg_b);
+                                    let cmp_res = locale.compare(seg_a, seg_b);
                                     (cmp_res != cmp::Ordering::Equal).then_some(cmp_res)
                                 }
                             }
                         })
                         .unwrap_or_else(|| a.path.len().cmp(&b.path.len()))
-                });
+                }))
             }
-            WalkMode::FileSize => {
-                tr_files.sort_by(|a, b| b.length.cmp(&a.length));
+            // largest first, then by path, so equal sizes are no ambiguity
+            WalkMode::FileSize => Some(Box::new(|a, b| {
+                b.length.cmp(&a.length).then_with(|| a.path.cmp(&b.path))
+            })),
+        };
+
+        if let Some(file_order) = tr_config.file_order.as_ref().filter(|_| !single_file) {
+            let positions: HashMap<&[String], usize> = file_order
+                .iter()
+                .enumerate()
+                .map(|(i, path)| (path.as_slice(), i))
+                .collect();
+            let mut keyed = Vec::with_capacity(tr_files.len());
+            for tr_file in tr_files {
+                match positions.get(tr_file.path.as_slice()) {
+                    Some(&i) => keyed.push((i, tr_file)),
+                    None => {
+                        return Err(TrError::InvalidPath(format!(
+                            "'{}' is not listed in the file order",
+                            tr_file.path.join("/")
+                        )));
+                    }
+                }
+            }
+            if keyed.len() != file_order.len() {
+                return Err(TrError::InvalidPath(format!(
+                    "File order lists {} files, target has {}",
+                    file_order.len(),
+                    keyed.len()
+                )));
+            }
+            keyed.sort_by_key(|(i, _)| *i);
+            tr_files = keyed.into_iter().map(|(_, tr_file)| tr_file).collect();
+        } else if let Some(primary) = primary {
+            // byte order of the paths breaks ties, so the result never depends on the walk order
+            tr_files.sort_by(|a, b| primary(a, b).then_with(|| a.path.cmp(&b.path)));
+            if tr_config.stable_order_check
+                && let Some(pair) = tr_files
+                    .windows(2)
+                    .find(|pair| primary(&pair[0], &pair[1]) == cmp::Ordering::Equal)
+            {
+                return Err(TrError::InvalidPath(format!(
+                    "Ambiguous file order, '{}' and '{}' compare equal",
+                    pair[0].path.join("/"),
+                    pair[1].path.join("/")
+                )));
             }
+        } else if tr_config.stable_order_check
+            // v2 and hybrid file lists are sorted below; the traversal order is only
+            // reproducible where the filesystem lists names sorted
+            && tr_config.meta_version == MetaVersion::V1
+            && let Some(pair) = tr_files.windows(2).find(|pair| pair[0].path > pair[1].path)
+        {
+            return Err(TrError::InvalidPath(format!(
+                "Unstable file order, the directory traversal lists '{}' before '{}'; use a sorted walk mode",
+                pair[0].path.join("/"),
+                pair[1].path.join("/")
+            )));
         }
 
-        let pieces = hash_tr_files(
-            base_path,
-            &tr_files,
-            tr_config.piece_length,
-            tr_config.n_jobs,
-            quiet,
-        )?;
+        let piece_length = if tr_config.auto_piece_size {
+            1usize << auto_piece_size(tr_files.iter().map(|f| f.length).sum())
+        } else {
+            tr_config.piece_length
+        };
+
+        let hybrid = tr_config.meta_version == MetaVersion::Hybrid;
+        let padded_v1 = tr_config.pad_files && tr_config.meta_version == MetaVersion::V1;
+        if (hybrid || padded_v1) && !single_file {
+            // clients require the v1 file list of hybrid torrents in file tree order,
+            // padded after every file but the last
+            if hybrid {
+                tr_files.sort_by(|a, b| a.path.cmp(&b.path));
+            }
+            let last = tr_files.len().saturating_sub(1);
+            tr_files = tr_files
+                .into_iter()
+                .enumerate()
+                .flat_map(|(i, tr_file)| {
+                    let padding =
+                        TrFile::padding_after(tr_file.length, piece_length).filter(|_| i != last);
+                    [Some(tr_file), padding]
+                })
+                .flatten()
+                .collect();
+        }
+
+        let total_size: usize = tr_files.iter().map(|f| f.length).sum();
+        let v1_pieces = || vec![0u8; total_size.div_ceil(piece_length) * SHA1_HASH_SIZE];
+        let v2_hashes = || Some(V2Hashes::placeholder(&tr_files, piece_length));
+        let (pieces, v2) = match tr_config.meta_version {
+            MetaVersion::V1 => (v1_pieces(), None),
+            MetaVersion::V2 => (Vec::new(), v2_hashes()),
+            MetaVersion::Hybrid => (v1_pieces(), v2_hashes()),
+        };
 
         Ok(TrInfo {
+            collections: if tr_config.collections.is_empty() {
+                None
+            } else {
+                Some(tr_config.collections.clone())
+            },
             files: if !single_file { Some(tr_files) } else { None },
             length: if single_file {
                 Some(base_metadata.len() as usize)
@@ -166,83 +911,444 @@ impl TrInfo {
                 None
             },
             name: Some(name.to_string()),
-            piece_length: tr_config.piece_length,
+            piece_length,
             pieces,
             private: tr_config.private,
             source: tr_config.source.clone(),
+            meta_version: v2.is_some().then_some(2),
+            v2,
+            extra: tr_config.info_keys.clone(),
         })
     }
 
-    pub fn verify(&self, target_path: String, n_jobs: usize, quiet: bool) -> TrResult<()> {
-        let base_path = Path::new(&target_path);
-        let tr_files = match self.files {
-            Some(ref files) => files,
-            None => &vec![TrFile {
+    /// Hashes the files of a preflighted info under `target_path`. `cache` only serves
+    /// v1 torrents, v2 and hybrid ones are hashed in full with a warning.
+    pub fn hash_pieces(
+        &mut self,
+        target_path: &str,
+        tr_config: &TrConfig,
+        cache: Option<&mut PieceCache>,
+        quiet: bool,
+    ) -> TrResult<PerfStats> {
+        let f_path_list: Vec<PathBuf> = self
+            .tr_files()?
+            .iter()
+            .map(|tr_file| tr_file.join_full_path(Path::new(target_path)))
+            .collect();
+        self.hash_pieces_from(&f_path_list, tr_config, cache, quiet)
+    }
+
+    /// Hashes the files read from `f_path_list`, one path per file in file order, for
+    /// content that does not live under a single target.
+    pub fn hash_pieces_from(
+        &mut self,
+        f_path_list: &[PathBuf],
+        tr_config: &TrConfig,
+        cache: Option<&mut PieceCache>,
+        quiet: bool,
+    ) -> TrResult<PerfStats> {
+        if self.v2.is_some() {
+            if cache.is_some() {
+                warn(
+                    WarningKind::Config,
+                    "The piece cache holds v1 hashes only, every piece of a v2 or hybrid torrent is hashed.",
+                );
+            }
+            let (v2, pieces, perf) = hash_v2_files(
+                f_path_list,
+                &self.tr_files()?,
+                self.piece_length,
+                self.has_v1(),
+                tr_config,
+                quiet,
+            )?;
+            self.v2 = Some(v2);
+            if let Some(pieces) = pieces {
+                self.pieces = pieces;
+            }
+            return Ok(perf);
+        }
+        let (pieces, perf) = hash_tr_files(
+            f_path_list,
+            &self.tr_files()?,
+            self.piece_length,
+            tr_config,
+            cache,
+            quiet,
+        )?;
+        self.pieces = pieces;
+        Ok(perf)
+    }
+
+    /// Seeds `cache` with the hashes of pieces whose files still exist under `target_path`
+    /// with the same size and were last modified before the second `since` (a creation
+    /// date) began. Returns the number seeded.
+    pub fn seed_piece_cache(
+        &self,
+        target_path: &str,
+        since: Option<i64>,
+        cache: &mut PieceCache,
+    ) -> TrResult<usize> {
+        let base_path = Path::new(target_path);
+        let tr_files = self.tr_files()?;
+        let identities: Vec<Option<String>> = tr_files
+            .iter()
+            .map(|tr_file| {
+                let full_path = tr_file.join_full_path(base_path);
+                if tr_file.padding {
+                    return PieceCache::file_identity(&full_path);
+                }
+                let meta = metadata(&full_path).ok()?;
+                if meta.len() as usize != tr_file.length {
+                    return None;
+                }
+                if let Some(since) = since {
+                    // a file written within the creation second may be newer than the torrent
+                    let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+                    if mtime >= Duration::from_secs(u64::try_from(since).ok()?) {
+                        return None;
+                    }
+                }
+                PieceCache::file_identity(&full_path)
+            })
+            .collect();
+        let piece_file_info = calc_piece_file_info(&tr_files, self.piece_length);
+        let mut seeded = 0;
+        for (key, hash) in piece_keys(&piece_file_info, &identities)
+            .into_iter()
+            .zip(split_hash_pieces(&self.pieces))
+        {
+            if let Some(key) = key {
+                cache.insert(key, *hash);
+                seeded += 1;
+            }
+        }
+        Ok(seeded)
+    }
+
+    /// File list as hashed, a single-file torrent yields one entry with an empty path.
+    pub fn tr_files(&self) -> TrResult<Cow<'_, [TrFile]>> {
+        match self.files {
+            Some(ref files) => Ok(Cow::Borrowed(files)),
+            None => Ok(Cow::Owned(vec![TrFile {
                 length: self
                     .length
                     .ok_or_else(|| TrError::MissingField(String::from("length")))?,
                 path: Vec::new(),
-            }],
+                padding: false,
+                symlink: None,
+            }])),
+        }
+    }
+
+    pub fn total_size(&self) -> usize {
+        match &self.files {
+            Some(files) => files.iter().map(|f| f.length).sum(),
+            None => self.length.unwrap_or_default(),
+        }
+    }
+
+    /// Whether the info dict has v1 `pieces`, false for v2-only torrents.
+    fn has_v1(&self) -> bool {
+        self.meta_version != Some(2) || !self.pieces.is_empty()
+    }
+
+    /// Number of pieces, in v2-only torrents every file starts a new piece.
+    pub fn piece_count(&self) -> usize {
+        if self.piece_length == 0 {
+            return 0;
+        }
+        if self.has_v1() {
+            return self.total_size().div_ceil(self.piece_length);
+        }
+        self.tr_files()
+            .map(|files| {
+                files
+                    .iter()
+                    .map(|f| f.length.div_ceil(self.piece_length))
+                    .sum()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Rejects a read info dict whose sizes cannot describe its files, so later piece
+    /// arithmetic never divides by zero, overflows or indexes past `pieces`.
+    pub fn check_layout(&self) -> TrResult<()> {
+        if !self.pieces.len().is_multiple_of(SHA1_HASH_SIZE) {
+            return Err(TrError::InvalidTorrent(format!(
+                "pieces is {} bytes, not a multiple of {SHA1_HASH_SIZE}",
+                self.pieces.len()
+            )));
+        }
+        let total = match &self.files {
+            Some(files) => files
+                .iter()
+                .try_fold(0usize, |sum, f| sum.checked_add(f.length))
+                .ok_or_else(|| TrError::InvalidTorrent(String::from("file lengths overflow")))?,
+            None => self.length.unwrap_or_default(),
         };
+        if !self.pieces.is_empty() {
+            check_piece_count(
+                self.pieces.len() / SHA1_HASH_SIZE,
+                total.div_ceil(self.piece_length),
+            )?;
+        }
+        Ok(())
+    }
 
-        let piece_slices: Vec<[u8; SHA1_HASH_SIZE]> = split_hash_pieces(&self.pieces);
+    /// Checks that there is one v1 piece hash per piece of the files before pieces are
+    /// mapped to file regions. `check_layout` catches a mismatch in a torrent read from a
+    /// file, this also covers info dicts built or changed in code.
+    fn check_verifiable(&self) -> TrResult<()> {
+        if !self.has_v1() {
+            return Err(TrError::InvalidTorrent(String::from(
+                "v2-only torrent has no v1 piece hashes to verify with",
+            )));
+        }
+        if !self.pieces.len().is_multiple_of(SHA1_HASH_SIZE) {
+            return Err(TrError::InvalidTorrent(format!(
+                "pieces is {} bytes, not a multiple of {SHA1_HASH_SIZE}: the metadata is truncated or damaged",
+                self.pieces.len()
+            )));
+        }
+        check_piece_count(
+            self.pieces.len() / SHA1_HASH_SIZE,
+            self.total_size().div_ceil(self.piece_length),
+        )
+    }
 
-        let failed_info = verify_tr_files(
-            &piece_slices,
-            tr_files,
+    /// Bencoded `piece layers` dict of a created v2 torrent, keyed by `pieces root`.
+    pub fn piece_layers(&self) -> Option<Vec<u8>> {
+        let v2 = self.v2.as_ref()?;
+        // identical files share a root and a layer, the dict holds it once
+        let layers: BTreeMap<&MerkleHash, &Vec<u8>> = v2
+            .pieces_roots
+            .iter()
+            .zip(&v2.piece_layers)
+            .filter_map(|(root, layer)| Some((root.as_ref()?, layer.as_ref()?)))
+            .collect();
+        let mut out = vec![b'd'];
+        for (root, layer) in layers {
+            write_bytes(&mut out, root).expect("writing to a Vec cannot fail");
+            write_bytes(&mut out, layer).expect("writing to a Vec cannot fail");
+        }
+        out.push(b'e');
+        Some(out)
+    }
+
+    /// Verifies the content under `target_path`, the pieces in `options.selected` only
+    /// if set.
+    pub fn verify(
+        &self,
+        target_path: &str,
+        options: &VerifyOptions,
+    ) -> TrResult<(VerifySummary, PerfStats)> {
+        self.check_verifiable()?;
+        let base_path = Path::new(target_path);
+        let tr_files = self.tr_files()?;
+        let selected = options.selected;
+
+        let piece_slices = split_hash_pieces(&self.pieces);
+        if let Some(selected) = selected
+            && selected.len() != piece_slices.len()
+        {
+            return Err(TrError::InvalidTorrent(format!(
+                "piece selection has {} pieces, torrent has {}",
+                selected.len(),
+                piece_slices.len()
+            )));
+        }
+
+        let (mut failed_info, perf) = verify_tr_files(
+            piece_slices,
+            &tr_files,
             base_path,
             self.piece_length,
-            n_jobs,
-            quiet,
+            options,
         )?;
 
-        println!("Verification Result:");
+        // padding files only fail along with the file before them
+        failed_info.files.retain(|&i| !tr_files[i].padding);
+        let mut failed_file_indices: Vec<usize> = failed_info.files.iter().cloned().collect();
+        failed_file_indices.sort();
+        let mut failed_files = Vec::with_capacity(failed_file_indices.len());
+        for file_index in failed_file_indices {
+            let tr_file = &tr_files[file_index];
+            let path = if tr_file.path.is_empty() {
+                self.name
+                    .as_ref()
+                    .ok_or_else(|| TrError::MissingField(String::from("name")))?
+                    .to_string()
+            } else {
+                tr_file.path.join("/")
+            };
+            failed_files.push(FailedFile {
+                path,
+                length: tr_file.length,
+                reason: failed_info
+                    .files_known
+                    .get(&file_index)
+                    .copied()
+                    .unwrap_or(FailReason::HashMismatch),
+            });
+        }
+        let mut failed_indices: Vec<usize> = failed_info.pieces.into_iter().collect();
+        failed_indices.sort();
+        failed_info.unchecked.sort();
+        failed_info.passed_on_retry.sort();
+        let summary = VerifySummary {
+            total_pieces: piece_slices.len(),
+            failed_pieces: failed_indices.len(),
+            skipped_pieces: selected.map(|_| failed_info.skipped),
+            total_files: tr_files.iter().filter(|f| !f.padding).count(),
+            skipped_files: selected.map(|_| failed_info.skipped_files),
+            failed_files,
+            failed_indices,
+            unchecked: failed_info.unchecked,
+            passed_on_retry: failed_info.passed_on_retry,
+        };
+        Ok((summary, perf))
+    }
 
-        let total_pieces = piece_slices.len();
-        let failed_piece_count = failed_info.pieces.len();
-        let passed_piece_count = total_pieces - failed_piece_count;
+    /// Checks two copies of the content against the pieces, piece by piece.
+    pub fn compare_data(
+        &self,
+        copy_a: &str,
+        copy_b: &str,
+        n_jobs: usize,
+        read_buffer: Option<usize>,
+        quiet: bool,
+        pause_keys: bool,
+    ) -> TrResult<CompareInfo> {
+        let buffers = PieceBufferPool::new(resolve_read_buffer(read_buffer, self.piece_length));
+        let tr_files = self.tr_files()?;
+        let piece_slices = split_hash_pieces(&self.pieces);
+        let piece_file_info = calc_piece_file_info(&tr_files, self.piece_length);
+        let path_list = |base: &str| -> Vec<PathBuf> {
+            tr_files
+                .iter()
+                .map(|tr_file| tr_file.join_full_path(Path::new(base)))
+                .collect()
+        };
+        let (a_paths, b_paths) = (path_list(copy_a), path_list(copy_b));
 
-        let total_files = tr_files.len();
-        let failed_file_count = failed_info.files.len();
-        let passed_file_count = total_files - failed_file_count;
+        let progress = Progress::new(piece_slices.len(), quiet);
+        let _pause_listener = pause_keys.then(|| PauseListener::start(&progress));
+        let pair_hashes: Vec<(Option<PieceHash>, Option<PieceHash>)> =
+            build_pool(n_jobs)?.install(|| {
+                piece_file_info
+                    .par_iter()
+                    .map(|piece| {
+                        let a = hash_piece(piece, &a_paths, &buffers, None).ok();
+                        let b = hash_piece(piece, &b_paths, &buffers, None).ok();
+                        progress.inc();
+                        (a, b)
+                    })
+                    .collect()
+            });
+        progress.finish();
 
-        println!(
-            "Pieces: {total_pieces:8} total = {passed_piece_count:8} passed + {failed_piece_count:8} failed"
-        );
-        println!(
-            "Files:  {total_files:8} total = {passed_file_count:8} passed + {failed_file_count:8} failed"
-        );
+        let mut compare_info = CompareInfo {
+            both_ok: 0,
+            a_only: Vec::new(),
+            b_only: Vec::new(),
+            neither_same: Vec::new(),
+            neither_diverge: Vec::new(),
+        };
+        for (i, (a, b)) in pair_hashes.into_iter().enumerate() {
+            let expected = piece_slices.get(i);
+            match (a.as_ref() == expected, b.as_ref() == expected) {
+                (true, true) => compare_info.both_ok += 1,
+                (true, false) => compare_info.a_only.push(i),
+                (false, true) => compare_info.b_only.push(i),
+                (false, false) if a.is_some() && a == b => compare_info.neither_same.push(i),
+                (false, false) => compare_info.neither_diverge.push(i),
+            }
+        }
+        Ok(compare_info)
+    }
 
-        if failed_info.files.is_empty() {
-            println!("All files are OK.");
-        } else {
-            println!("\nSome files failed verification:");
-            let mut failed_files_vec: Vec<usize> = failed_info.files.iter().cloned().collect();
-            failed_files_vec.sort();
-            for file_index in failed_files_vec {
-                let tr_file = &tr_files[file_index];
-                let rel_path = if tr_file.path.is_empty() {
-                    self.name
-                        .as_ref()
-                        .ok_or_else(|| TrError::MissingField(String::from("name")))?
-                        .to_string()
-                } else {
-                    tr_file.path.join("/")
-                };
-                let known_issue = if failed_info.files_known.contains(&file_index) {
-                    " [missing or size mismatch]"
-                } else {
-                    ""
-                };
-                println!(
-                    "- {} ({} [{}]){}",
-                    rel_path,
-                    tr_file.length,
-                    human_size(tr_file.length),
-                    known_issue
-                );
+    /// Verifies the `new` copy, reading the `old` one only for the pieces the new copy
+    /// fails, to tell damage from the migration apart from damage it carried over.
+    pub fn migrate_check(
+        &self,
+        old: &str,
+        new: &str,
+        n_jobs: usize,
+        read_buffer: Option<usize>,
+        quiet: bool,
+        pause_keys: bool,
+    ) -> TrResult<MigrateInfo> {
+        let buffers = PieceBufferPool::new(resolve_read_buffer(read_buffer, self.piece_length));
+        let tr_files = self.tr_files()?;
+        let piece_slices = split_hash_pieces(&self.pieces);
+        let piece_file_info = calc_piece_file_info(&tr_files, self.piece_length);
+        let path_list = |base: &str| -> Vec<PathBuf> {
+            tr_files
+                .iter()
+                .map(|tr_file| tr_file.join_full_path(Path::new(base)))
+                .collect()
+        };
+        let (old_paths, new_paths) = (path_list(old), path_list(new));
+
+        let progress = Progress::new(piece_slices.len(), quiet);
+        let _pause_listener = pause_keys.then(|| PauseListener::start(&progress));
+        // None when the new copy passes, else the hashes of the new and the old copy
+        type FailedPair = Option<(Option<PieceHash>, Option<PieceHash>)>;
+        let failed_pairs: Vec<FailedPair> = build_pool(n_jobs)?.install(|| {
+            piece_file_info
+                .par_iter()
+                .zip(piece_slices)
+                .map(|(piece, expected)| {
+                    let new_hash = hash_piece(piece, &new_paths, &buffers, None).ok();
+                    let pair = (new_hash.as_ref() != Some(expected))
+                        .then(|| (new_hash, hash_piece(piece, &old_paths, &buffers, None).ok()));
+                    progress.inc();
+                    pair
+                })
+                .collect()
+        });
+        progress.finish();
+
+        let mut migrate_info = MigrateInfo {
+            new_ok: 0,
+            old_only: Vec::new(),
+            both_bad_same: Vec::new(),
+            both_bad_diverge: Vec::new(),
+        };
+        for (i, pair) in failed_pairs.into_iter().enumerate() {
+            match pair {
+                None => migrate_info.new_ok += 1,
+                Some((_, Some(old))) if old == piece_slices[i] => migrate_info.old_only.push(i),
+                Some((new, old)) if old.is_some() && new == old => {
+                    migrate_info.both_bad_same.push(i)
+                }
+                Some(_) => migrate_info.both_bad_diverge.push(i),
             }
         }
+        Ok(migrate_info)
+    }
+
+    /// Checks that `target_path` exists and is named like the torrent, allowing for
+    /// path substitutions.
+    pub fn check_target(&self, target_path: &Path) -> TrResult<()> {
+        let name = target_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("");
+        let tr_name = self.get_name().unwrap_or(String::from("<unknown>"));
+        if name != tr_name && name != local_component(&tr_name) {
+            return Err(TrError::TargetMismatch {
+                target: name.to_string(),
+                torrent: tr_name,
+            });
+        }
+        if !target_path.exists() {
+            return Err(TrError::InvalidPath(format!(
+                "Target path '{}' does not exist",
+                target_path.display()
+            )));
+        }
         Ok(())
     }
 
@@ -252,102 +1358,303 @@ impl TrInfo {
             .ok_or_else(|| TrError::MissingField(String::from("name")))
     }
 
-    pub fn bencode(&self) -> Vec<u8> {
-        let mut bcode: Vec<u8> = Vec::new();
-        bcode.push(b'd');
-        if self.files.is_some() {
-            bcode.extend(bencode_string("files"));
-            bcode.extend(bencode_file_list(self.files.as_ref().unwrap()));
+    pub fn encode<W: Write>(&self, out: &mut W) -> ioResult<()> {
+        let mut extra = self.extra.iter().peekable();
+        out.write_all(b"d")?;
+        if let Some(collections) = &self.collections {
+            write_extra_before(out, &mut extra, Some("collections"))?;
+            write_string(out, "collections")?;
+            write_string_list(out, collections)?;
+        }
+        if let Some(v2) = &self.v2 {
+            write_extra_before(out, &mut extra, Some("file tree"))?;
+            write_string(out, "file tree")?;
+            let name = self.name.as_deref().unwrap_or_default();
+            let tr_files = self.tr_files().map_err(|e| ioError::other(e.to_string()))?;
+            write_file_tree(out, &tr_files, name, &v2.pieces_roots)?;
+        }
+        if let Some(files) = self.files.as_ref().filter(|_| self.has_v1()) {
+            write_extra_before(out, &mut extra, Some("files"))?;
+            write_string(out, "files")?;
+            write_file_list(out, files)?;
+        }
+        if let Some(length) = self
+            .length
+            .filter(|_| self.has_v1() && self.files.is_none())
+        {
+            write_extra_before(out, &mut extra, Some("length"))?;
+            write_string(out, "length")?;
+            write_uint(out, length)?;
         }
-        if self.length.is_some() {
-            bcode.extend(bencode_string("length"));
-            bcode.extend(bencode_uint(self.length.unwrap()));
+        if let Some(meta_version) = self.meta_version {
+            write_extra_before(out, &mut extra, Some("meta version"))?;
+            write_string(out, "meta version")?;
+            write_uint(out, meta_version)?;
         }
-        if self.name.is_some() {
-            bcode.extend(bencode_string("name"));
-            bcode.extend(bencode_string(self.name.as_ref().unwrap()));
+        if let Some(name) = &self.name {
+            write_extra_before(out, &mut extra, Some("name"))?;
+            write_string(out, "name")?;
+            write_string(out, name)?;
         }
-        bcode.extend(bencode_string("piece length"));
-        bcode.extend(bencode_uint(self.piece_length));
+        write_extra_before(out, &mut extra, Some("piece length"))?;
+        write_string(out, "piece length")?;
+        write_uint(out, self.piece_length)?;
         if !self.pieces.is_empty() {
-            bcode.extend(bencode_string("pieces"));
-            bcode.extend(bencode_bytes(&self.pieces));
+            write_extra_before(out, &mut extra, Some("pieces"))?;
+            write_string(out, "pieces")?;
+            write_bytes(out, &self.pieces)?;
         }
         if self.private {
-            bcode.extend(bencode_string("private"));
-            bcode.extend(bencode_uint(1));
+            write_extra_before(out, &mut extra, Some("private"))?;
+            write_string(out, "private")?;
+            write_uint(out, 1)?;
         }
-        if self.source.is_some() {
-            bcode.extend(bencode_string("source"));
-            bcode.extend(bencode_string(self.source.as_ref().unwrap()));
+        if let Some(source) = &self.source {
+            write_extra_before(out, &mut extra, Some("source"))?;
+            write_string(out, "source")?;
+            write_string(out, source)?;
         }
-        bcode.push(b'e');
-        bcode
+        write_extra_before(out, &mut extra, None)?;
+        out.write_all(b"e")
     }
 
+    /// The v1 info-hash of the info dict as encoded from these fields, hex encoded. For a
+    /// torrent read from a file, `Torrent::info_hash` hashes the stored bytes instead.
     pub fn hash(&self) -> String {
         let mut hasher = Sha1::new();
-        hasher.update(self.bencode());
+        self.encode(&mut hasher)
+            .expect("writing to a hasher cannot fail");
         let result = hasher.finalize();
         hex::encode(result)
     }
 }
 
+fn is_junk(name: &OsStr) -> bool {
+    let name = name.to_string_lossy();
+    name.starts_with("._")
+        || JUNK_NAMES
+            .iter()
+            .any(|junk| name.eq_ignore_ascii_case(junk))
+}
+
+/// Smallest piece size, as a power of two, giving at most `AUTO_MAX_PIECES` pieces for
+/// `total_size` bytes, capped at 16 MiB.
+pub fn auto_piece_size(total_size: usize) -> u8 {
+    (MIN_PIECE_SIZE..=AUTO_MAX_PIECE_SIZE)
+        .find(|&n| total_size.div_ceil(1usize << n) <= AUTO_MAX_PIECES)
+        .unwrap_or(AUTO_MAX_PIECE_SIZE)
+}
+
+/// Applies `policy` to the file at `path` if it is a cloud placeholder. Returns whether the
+/// file stays in the torrent.
+fn offline_check(
+    path: &Path,
+    shown: &str,
+    metadata: &Metadata,
+    policy: OfflinePolicy,
+) -> TrResult<bool> {
+    if !is_placeholder(metadata) {
+        return Ok(true);
+    }
+    match policy {
+        OfflinePolicy::Hydrate => {
+            io::copy(&mut File::open(path)?, &mut io::sink())?;
+            Ok(true)
+        }
+        OfflinePolicy::Skip => {
+            warn(
+                WarningKind::SkippedFile,
+                format!("'{shown}' is a cloud placeholder without local data, skipped."),
+            );
+            Ok(false)
+        }
+        OfflinePolicy::Abort => Err(TrError::InvalidPath(format!(
+            "'{shown}' is a cloud placeholder without local data, hashing it would read wrong pieces; use --offline-files hydrate or skip"
+        ))),
+    }
+}
+
+/// Whether a file is a placeholder of OneDrive or another cloud sync provider, whose data
+/// is only fetched on access.
+#[cfg(windows)]
+fn is_placeholder(metadata: &Metadata) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_OFFLINE: u32 = 0x1000;
+    const FILE_ATTRIBUTE_RECALL_ON_OPEN: u32 = 0x40000;
+    const FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS: u32 = 0x400000;
+    metadata.file_attributes()
+        & (FILE_ATTRIBUTE_OFFLINE
+            | FILE_ATTRIBUTE_RECALL_ON_OPEN
+            | FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS)
+        != 0
+}
+
+/// Whether a file is dataless, evicted by iCloud Drive or a File Provider extension.
+#[cfg(target_os = "macos")]
+fn is_placeholder(metadata: &Metadata) -> bool {
+    use std::os::macos::fs::MetadataExt;
+
+    const SF_DATALESS: u32 = 0x40000000;
+    metadata.st_flags() & SF_DATALESS != 0
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn is_placeholder(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// Whether a path is a symlink, or on Windows a junction or mount point, which are reparse
+/// points too but not symlinks to every API. Cloud placeholders are reparse points as
+/// well and are not links.
+#[cfg(windows)]
+fn is_link(path: &Path) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+    std::fs::symlink_metadata(path).is_ok_and(|metadata| {
+        metadata.file_type().is_symlink()
+            || (metadata.file_attributes() & FILE_ATTRIBUTE_REPARSE_POINT != 0
+                && metadata.is_dir()
+                && !is_placeholder(&metadata))
+    })
+}
+
+#[cfg(not(windows))]
+fn is_link(path: &Path) -> bool {
+    std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_symlink())
+}
+
 fn hash_tr_files(
-    base_path: &Path,
+    f_path_list: &[PathBuf],
     tr_files: &[TrFile],
     chunk_size: usize,
-    n_jobs: usize,
+    tr_config: &TrConfig,
+    cache: Option<&mut PieceCache>,
     quiet: bool,
-) -> TrResult<Vec<u8>> {
+) -> TrResult<(Vec<u8>, PerfStats)> {
     let piece_file_info = calc_piece_file_info(tr_files, chunk_size);
     let pieces_count = piece_file_info.len();
+    let (n_jobs, read_buffer) = (tr_config.n_jobs, tr_config.read_buffer);
+
+    let progress = Progress::new(pieces_count, quiet);
+    let _pause_listener = tr_config
+        .pause_keys
+        .then(|| PauseListener::start(&progress));
+
+    let Some(cache) = cache else {
+        let (piece_slices, perf) = hash_piece_file(
+            chunk_size,
+            &piece_file_info,
+            tr_files,
+            f_path_list,
+            &progress,
+            n_jobs,
+            read_buffer,
+        )?;
+        progress.finish();
+        return Ok((all_hashed(piece_slices)?.concat(), perf));
+    };
 
-    let pb = make_progress_bar(pieces_count, quiet);
+    let identities: Vec<Option<String>> = f_path_list
+        .iter()
+        .map(|f_path| PieceCache::file_identity(f_path))
+        .collect();
+    let keys = piece_keys(&piece_file_info, &identities);
+
+    let mut piece_slices: Vec<Option<PieceHash>> = keys
+        .iter()
+        .map(|key| key.as_ref().and_then(|key| cache.get(key)))
+        .collect();
+    let mut to_hash = Vec::new();
+    let mut to_hash_info = Vec::new();
+    for (i, piece) in piece_file_info.into_iter().enumerate() {
+        if piece_slices[i].is_some() {
+            progress.inc();
+        } else {
+            to_hash.push(i);
+            to_hash_info.push(piece);
+        }
+    }
+    let reused = pieces_count - to_hash.len();
 
-    let piece_slices = hash_piece_file(
+    let (hashed, mut perf) = hash_piece_file(
         chunk_size,
-        &piece_file_info,
+        &to_hash_info,
         tr_files,
-        base_path,
-        &pb,
+        f_path_list,
+        &progress,
         n_jobs,
+        read_buffer,
     )?;
-
-    let mut pieces = Vec::with_capacity(piece_slices.len() * SHA1_HASH_SIZE);
-    for slice in piece_slices {
-        pieces.extend_from_slice(&slice);
+    for (i, hash) in to_hash.into_iter().zip(all_hashed(hashed)?) {
+        if let Some(key) = keys[i] {
+            cache.insert(key, hash);
+        }
+        piece_slices[i] = Some(hash);
     }
 
-    finish_progress_bar(pb, pieces_count);
+    progress.finish();
+    perf.reused_pieces = Some(reused);
 
-    Ok(pieces)
+    Ok((
+        piece_slices
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .concat(),
+        perf,
+    ))
 }
 
+/// Pieces left out of `options.selected` are skipped and counted in `FailedInfo::skipped`,
+/// files with only skipped pieces in `FailedInfo::skipped_files`.
 fn verify_tr_files(
-    piece_slices: &[[u8; SHA1_HASH_SIZE]],
+    piece_slices: &[PieceHash],
     tr_files: &[TrFile],
     base_path: &Path,
     piece_length: usize,
-    n_jobs: usize,
-    quiet: bool,
-) -> TrResult<FailedInfo> {
+    options: &VerifyOptions,
+) -> TrResult<(FailedInfo, PerfStats)> {
+    let VerifyOptions {
+        n_jobs,
+        read_buffer,
+        selected,
+        order,
+        paranoid,
+        quiet,
+        pause_keys,
+    } = *options;
     let piece_file_info = calc_piece_file_info(tr_files, piece_length);
 
     let mut file_status_map: HashMap<String, bool> = HashMap::new();
     let mut failed_info = FailedInfo {
         files: HashSet::new(),
-        files_known: HashSet::new(),
+        files_known: HashMap::new(),
         pieces: HashSet::new(),
+        skipped: 0,
+        skipped_files: 0,
+        unchecked: Vec::new(),
+        passed_on_retry: Vec::new(),
     };
     let pieces_count = piece_slices.len();
 
-    let pb = make_progress_bar(pieces_count, quiet);
+    let progress = Progress::new(pieces_count, quiet);
+    let _pause_listener = pause_keys.then(|| PauseListener::start(&progress));
 
+    let mut skipped_pieces = HashSet::new();
     for (i, piece) in piece_file_info.iter().enumerate() {
+        if selected.is_some_and(|selected| !selected[i]) {
+            skipped_pieces.insert(i);
+            progress.inc();
+            continue;
+        }
         let mut files_ok: bool = true;
         for file_hash_info in piece {
             let tr_file = &tr_files[file_hash_info.file_index];
+            if tr_file.padding {
+                continue;
+            }
             let f_path = tr_file.join_full_path(base_path);
             let f_path_str = f_path
                 .to_str()
@@ -355,14 +1662,18 @@ fn verify_tr_files(
                 .to_string();
             match file_status_map.entry(f_path_str) {
                 Entry::Vacant(entry) => {
-                    let file_ok = metadata(&f_path)
-                        .ok()
-                        .is_some_and(|meta| meta.len() == tr_file.length as u64);
-                    if !file_ok {
-                        failed_info.files_known.insert(file_hash_info.file_index);
+                    let problem = match metadata(&f_path) {
+                        Ok(meta) if meta.len() == tr_file.length as u64 => None,
+                        Ok(meta) if meta.is_file() => Some(FailReason::SizeMismatch),
+                        _ => Some(FailReason::Missing),
+                    };
+                    if let Some(reason) = problem {
+                        failed_info
+                            .files_known
+                            .insert(file_hash_info.file_index, reason);
                         files_ok = false;
                     }
-                    entry.insert(file_ok);
+                    entry.insert(problem.is_none());
                 }
                 Entry::Occupied(entry) => {
                     if !*entry.get() {
@@ -376,53 +1687,183 @@ fn verify_tr_files(
             for file_hash_info in piece {
                 failed_info.files.insert(file_hash_info.file_index);
             }
-            if let Some(ref pb) = pb {
-                pb.inc(1);
-            }
+            progress.inc();
             continue;
         }
     }
 
-    let pieces_to_check_count = pieces_count - failed_info.pieces.len();
+    failed_info.skipped = skipped_pieces.len();
+    let (mut read_files, mut unread_files) = (HashSet::new(), HashSet::new());
+    for (i, piece) in piece_file_info.iter().enumerate() {
+        let files = if skipped_pieces.contains(&i) {
+            &mut unread_files
+        } else {
+            &mut read_files
+        };
+        files.extend(piece.iter().map(|info| info.file_index));
+    }
+    failed_info.skipped_files = unread_files
+        .difference(&read_files)
+        .filter(|&&i| !tr_files[i].padding)
+        .count();
+    let pieces_to_check_count = pieces_count - failed_info.pieces.len() - failed_info.skipped;
     let mut pieces_to_check = Vec::with_capacity(pieces_to_check_count);
     let mut filtered_piece_file_info = Vec::with_capacity(pieces_to_check_count);
     for (i, piece_info) in piece_file_info.into_iter().enumerate() {
-        if !failed_info.pieces.contains(&i) {
+        if !failed_info.pieces.contains(&i) && !skipped_pieces.contains(&i) {
             pieces_to_check.push(i);
             filtered_piece_file_info.push(piece_info);
         }
     }
-    let piece_file_info = filtered_piece_file_info;
+    let mut piece_file_info = filtered_piece_file_info;
 
-    let calc_piece_slices = hash_piece_file(
+    let f_path_list: Vec<PathBuf> = tr_files
+        .iter()
+        .map(|tr_file| tr_file.join_full_path(base_path))
+        .collect();
+    if order != VerifyOrder::Torrent {
+        let permutation = order_pieces(order, &piece_file_info, tr_files, &f_path_list);
+        pieces_to_check = permutation.iter().map(|&i| pieces_to_check[i]).collect();
+        let mut slots: Vec<Option<Vec<FileHashInfo>>> =
+            piece_file_info.into_iter().map(Some).collect();
+        piece_file_info = permutation
+            .iter()
+            .filter_map(|&i| slots[i].take())
+            .collect();
+    }
+    let (calc_piece_slices, perf) = hash_piece_file(
         piece_length,
         &piece_file_info,
         tr_files,
-        base_path,
-        &pb,
+        &f_path_list,
+        &progress,
         n_jobs,
+        read_buffer,
     )?;
-    for (i, piece_calc_hash) in calc_piece_slices.iter().enumerate() {
-        if *piece_calc_hash != piece_slices[pieces_to_check[i]] {
-            failed_info.pieces.insert(pieces_to_check[i]);
-            for file_hash_info in &piece_file_info[i] {
-                failed_info.files.insert(file_hash_info.file_index);
+    let mut mismatched = Vec::new();
+    for (i, hash) in calc_piece_slices.iter().enumerate() {
+        match hash {
+            None => failed_info.unchecked.push(pieces_to_check[i]),
+            Some(hash) if *hash != piece_slices[pieces_to_check[i]] => mismatched.push(i),
+            Some(_) => {}
+        }
+    }
+    if paranoid && !mismatched.is_empty() {
+        // a second read tells a flaky USB or NFS read apart from data that is really bad
+        let buffers = PieceBufferPool::new(resolve_read_buffer(read_buffer, piece_length));
+        mismatched.retain(|&i| {
+            let passed = hash_piece(&piece_file_info[i], &f_path_list, &buffers, None)
+                .is_ok_and(|hash| hash == piece_slices[pieces_to_check[i]]);
+            if passed {
+                failed_info.passed_on_retry.push(pieces_to_check[i]);
             }
+            !passed
+        });
+    }
+    for i in mismatched {
+        failed_info.pieces.insert(pieces_to_check[i]);
+        for file_hash_info in &piece_file_info[i] {
+            failed_info.files.insert(file_hash_info.file_index);
         }
     }
 
-    finish_progress_bar(pb, pieces_count);
+    progress.finish();
 
-    Ok(failed_info)
+    Ok((failed_info, perf))
 }
 
-fn split_hash_pieces(piece: &[u8]) -> Vec<[u8; SHA1_HASH_SIZE]> {
-    let layer_count = piece.len() / SHA1_HASH_SIZE;
-    let mut slices: Vec<[u8; SHA1_HASH_SIZE]> = vec![[0u8; SHA1_HASH_SIZE]; layer_count];
-    for i in 0..layer_count {
-        slices[i].copy_from_slice(&piece[i * SHA1_HASH_SIZE..(i + 1) * SHA1_HASH_SIZE]);
+/// Permutation of `piece_file_info` in verify `order`; pieces sorting equal keep
+/// their order, so the pieces of one file are read front to back.
+fn order_pieces(
+    order: VerifyOrder,
+    piece_file_info: &[Vec<FileHashInfo>],
+    tr_files: &[TrFile],
+    f_path_list: &[PathBuf],
+) -> Vec<usize> {
+    let mut permutation: Vec<usize> = (0..piece_file_info.len()).collect();
+    match order {
+        VerifyOrder::Torrent => {}
+        VerifyOrder::SizeDesc => permutation.sort_by_key(|&i| {
+            cmp::Reverse(
+                piece_file_info[i]
+                    .iter()
+                    .map(|f| tr_files[f.file_index].length)
+                    .max(),
+            )
+        }),
+        VerifyOrder::Path => permutation.sort_by(|&a, &b| {
+            let path = |i: usize| {
+                piece_file_info[i]
+                    .first()
+                    .map(|f| &tr_files[f.file_index].path)
+            };
+            path(a).cmp(&path(b))
+        }),
+        VerifyOrder::RecentFirst => {
+            let modified: Vec<Option<SystemTime>> = f_path_list
+                .iter()
+                .map(|f_path| metadata(f_path).and_then(|meta| meta.modified()).ok())
+                .collect();
+            permutation.sort_by_key(|&i| {
+                cmp::Reverse(
+                    piece_file_info[i]
+                        .iter()
+                        .filter_map(|f| modified[f.file_index])
+                        .max(),
+                )
+            });
+        }
     }
-    slices
+    permutation
+}
+
+/// Hashes of pieces being created, which no time limit may cut short.
+fn all_hashed(hashes: Vec<Option<PieceHash>>) -> TrResult<Vec<PieceHash>> {
+    hashes
+        .into_iter()
+        .collect::<Option<Vec<PieceHash>>>()
+        .ok_or_else(|| TrError::ParseError(String::from("hashing stopped by the time limit")))
+}
+
+/// Piece cache keys, `None` for pieces touching a file without an identity.
+fn piece_keys(
+    piece_file_info: &[Vec<FileHashInfo>],
+    identities: &[Option<String>],
+) -> Vec<Option<PieceHash>> {
+    piece_file_info
+        .iter()
+        .map(|piece| {
+            let segments = piece
+                .iter()
+                .map(|info| {
+                    let identity = identities[info.file_index].as_deref()?;
+                    Some((identity, info.file_offset, info.length))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            Some(PieceCache::piece_key(segments.into_iter()))
+        })
+        .collect()
+}
+
+/// Fails unless there are as many piece hashes as pieces of content, telling truncated
+/// hashes from a file list that does not match them.
+fn check_piece_count(actual: usize, expected: usize) -> TrResult<()> {
+    let cause = match actual.cmp(&expected) {
+        cmp::Ordering::Equal => return Ok(()),
+        cmp::Ordering::Less => "the piece hashes are truncated, get the .torrent again",
+        cmp::Ordering::Greater => {
+            "the files cover less data than was hashed, the file list was changed or belongs to another data set"
+        }
+    };
+    Err(TrError::InvalidTorrent(format!(
+        "{actual} piece hashes for {expected} pieces of content: {cause}"
+    )))
+}
+
+/// The `pieces` blob viewed as hashes in place, a torrent with hundreds of MB of pieces
+/// is not copied.
+fn split_hash_pieces(pieces: &[u8]) -> &[PieceHash] {
+    pieces.as_chunks().0
 }
 
 fn calc_piece_file_info(tr_files: &[TrFile], piece_length: usize) -> Vec<Vec<FileHashInfo>> {
@@ -458,66 +1899,474 @@ fn calc_piece_file_info(tr_files: &[TrFile], piece_length: usize) -> Vec<Vec<Fil
     piece_file_info
 }
 
-thread_local! {
-    static FIXED_BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+/// Hashes every file into a BEP 52 merkle tree of 16 KiB blocks. Pieces never span
+/// files in v2, so each piece is read and reduced to its piece layer hash on its own.
+/// With `v1` the same reads also give the SHA-1 pieces of a hybrid torrent, whose
+/// padding files make every v1 piece one v2 piece followed by zeros.
+fn hash_v2_files(
+    f_path_list: &[PathBuf],
+    tr_files: &[TrFile],
+    piece_length: usize,
+    v1: bool,
+    tr_config: &TrConfig,
+    quiet: bool,
+) -> TrResult<(V2Hashes, Option<Vec<u8>>, PerfStats)> {
+    let (n_jobs, read_buffer) = (tr_config.n_jobs, tr_config.read_buffer);
+    let pieces: Vec<FileHashInfo> = tr_files
+        .iter()
+        .enumerate()
+        .filter(|(_, tr_file)| !tr_file.padding)
+        .flat_map(|(file_index, tr_file)| {
+            (0..tr_file.length)
+                .step_by(piece_length)
+                .map(move |file_offset| FileHashInfo {
+                    file_index,
+                    file_offset,
+                    length: cmp::min(piece_length, tr_file.length - file_offset),
+                })
+        })
+        .collect();
+    let blocks_per_piece = piece_length / V2_BLOCK_SIZE;
+
+    let progress = Progress::new(pieces.len(), quiet);
+    let buffers = PieceBufferPool::new(resolve_read_buffer(read_buffer, piece_length));
+    let _pause_listener = tr_config
+        .pause_keys
+        .then(|| PauseListener::start(&progress));
+    let perf = PerfStats::new(tr_files, f_path_list.to_vec());
+
+    let piece_hashes = build_pool(n_jobs)?.install(|| {
+        pieces
+            .par_iter()
+            .map(|piece| -> TrResult<(MerkleHash, Option<PieceHash>)> {
+                let mut leaves = Vec::with_capacity(blocks_per_piece);
+                let mut hasher = Sha256::new();
+                let mut filled = 0;
+                let mut v1_hasher = v1.then(Sha1::new);
+                read_piece(
+                    std::slice::from_ref(piece),
+                    f_path_list,
+                    &buffers,
+                    Some(&perf),
+                    |_, _, mut block| {
+                        if let Some(v1_hasher) = &mut v1_hasher {
+                            v1_hasher.update(block);
+                        }
+                        while !block.is_empty() {
+                            let n = cmp::min(block.len(), V2_BLOCK_SIZE - filled);
+                            hasher.update(&block[..n]);
+                            filled += n;
+                            block = &block[n..];
+                            if filled == V2_BLOCK_SIZE {
+                                leaves.push(hasher.finalize_reset().into());
+                                filled = 0;
+                            }
+                        }
+                    },
+                )?;
+                if filled > 0 {
+                    leaves.push(hasher.finalize().into());
+                }
+                let v1_hash = v1_hasher.map(|mut v1_hasher| {
+                    let file = &tr_files[piece.file_index];
+                    if piece.file_offset + piece.length == file.length
+                        && let Some(padding) =
+                            tr_files.get(piece.file_index + 1).filter(|f| f.padding)
+                    {
+                        v1_hasher.update(vec![0u8; padding.length]);
+                    }
+                    v1_hasher.finalize().into()
+                });
+                // the tree of a file of one piece or less is only as wide as it needs to be
+                let width = if tr_files[piece.file_index].length <= piece_length {
+                    leaves.len().next_power_of_two()
+                } else {
+                    blocks_per_piece
+                };
+                progress.inc();
+                Ok((merkle_root(leaves, width, [0u8; SHA256_HASH_SIZE]), v1_hash))
+            })
+            .collect::<TrResult<Vec<_>>>()
+    })?;
+    progress.finish();
+    let (piece_hashes, v1_hashes): (Vec<MerkleHash>, Vec<Option<PieceHash>>) =
+        piece_hashes.into_iter().unzip();
+    let v1_pieces = v1.then(|| v1_hashes.into_iter().flatten().collect::<Vec<_>>().concat());
+
+    // pieces past the end of a file count as all-zero blocks
+    let pad_piece = merkle_root(Vec::new(), blocks_per_piece, [0u8; SHA256_HASH_SIZE]);
+    let mut piece_hashes = piece_hashes.into_iter();
+    let mut v2 = V2Hashes {
+        pieces_roots: Vec::with_capacity(tr_files.len()),
+        piece_layers: Vec::with_capacity(tr_files.len()),
+    };
+    for tr_file in tr_files {
+        let count = if tr_file.padding {
+            0
+        } else {
+            tr_file.length.div_ceil(piece_length)
+        };
+        let layer: Vec<MerkleHash> = piece_hashes.by_ref().take(count).collect();
+        let (root, layer) = match count {
+            0 => (None, None),
+            1 => (Some(layer[0]), None),
+            _ => (
+                Some(merkle_root(
+                    layer.clone(),
+                    count.next_power_of_two(),
+                    pad_piece,
+                )),
+                Some(layer.concat()),
+            ),
+        };
+        v2.pieces_roots.push(root);
+        v2.piece_layers.push(layer);
+    }
+    Ok((v2, v1_pieces, perf))
+}
+
+/// Root of a binary SHA-256 tree over `layer`, padded with `pad` to `width` nodes.
+fn merkle_root(mut layer: Vec<MerkleHash>, width: usize, pad: MerkleHash) -> MerkleHash {
+    layer.resize(width, pad);
+    while layer.len() > 1 {
+        layer = layer
+            .chunks_exact(2)
+            .map(|pair| {
+                let mut hasher = Sha256::new();
+                hasher.update(pair[0]);
+                hasher.update(pair[1]);
+                hasher.finalize().into()
+            })
+            .collect();
+    }
+    layer[0]
+}
+
+fn build_pool(n_jobs: usize) -> TrResult<ThreadPool> {
+    // a panic in a hashing thread is reported with the job that started the pool
+    let context = crash::context();
+    let pin = numa::pin_threads();
+    ThreadPoolBuilder::new()
+        .num_threads(n_jobs)
+        .start_handler(move |index| {
+            crash::set_context(context.clone());
+            if pin {
+                numa::pin_current_thread(index);
+            }
+        })
+        .build()
+        .map_err(|e| TrError::ParseError(format!("Failed to create thread pool: {e}")))
+}
+
+/// Reads whole pieces up to 4 MiB at once, larger pieces are read in 4 MiB chunks.
+fn resolve_read_buffer(read_buffer: Option<usize>, piece_length: usize) -> usize {
+    read_buffer
+        .unwrap_or(DEF_READ_BUFFER)
+        .min(piece_length)
+        .max(1)
+}
+
+fn hash_piece(
+    piece: &[FileHashInfo],
+    f_path_list: &[PathBuf],
+    buffers: &PieceBufferPool,
+    perf: Option<&PerfStats>,
+) -> TrResult<[u8; SHA1_HASH_SIZE]> {
+    let mut hasher = Sha1::new();
+    read_piece(piece, f_path_list, buffers, perf, |_, _, block| {
+        hasher.update(block)
+    })?;
+
+    let calc_hash = hasher.finalize();
+    let mut hash_arr = [0u8; SHA1_HASH_SIZE];
+    hash_arr.copy_from_slice(&calc_hash);
+    Ok(hash_arr)
+}
+
+/// Reads the bytes of one piece once, handing each block to `on_block` together with
+/// its file index and offset in that file. Every digest of the piece is fed from here,
+/// so adding one does not add a pass over the data. Padding files, given with an empty
+/// path, are read as zeros.
+fn read_piece(
+    piece: &[FileHashInfo],
+    f_path_list: &[PathBuf],
+    buffers: &PieceBufferPool,
+    perf: Option<&PerfStats>,
+    mut on_block: impl FnMut(usize, usize, &[u8]),
+) -> TrResult<()> {
+    wait_if_paused();
+    let _slot = acquire_slot();
+    let mut buf = buffers.get();
+
+    for file_hash_info in piece {
+        let f_path = &f_path_list[file_hash_info.file_index];
+        if f_path.as_os_str().is_empty() {
+            let mut remaining = file_hash_info.length;
+            while remaining > 0 {
+                let chunk = cmp::min(remaining, buf.len());
+                buf[..chunk].fill(0);
+                on_block(
+                    file_hash_info.file_index,
+                    file_hash_info.file_offset + file_hash_info.length - remaining,
+                    &buf[..chunk],
+                );
+                remaining -= chunk;
+            }
+            continue;
+        }
+        let start = Instant::now();
+        let mut f = File::open(f_path)?;
+        f.seek(SeekFrom::Start(file_hash_info.file_offset as u64))?;
+        let mut read_time = start.elapsed();
+
+        let mut remaining = file_hash_info.length;
+        while remaining > 0 {
+            let chunk = cmp::min(remaining, buf.len());
+            let start = Instant::now();
+            let n = f.read(&mut buf[..chunk])?;
+            read_time += start.elapsed();
+            if n == 0 {
+                break;
+            }
+            on_block(
+                file_hash_info.file_index,
+                file_hash_info.file_offset + file_hash_info.length - remaining,
+                &buf[..n],
+            );
+            remaining -= n;
+        }
+        if let Some(perf) = perf {
+            perf.record(
+                file_hash_info.file_index,
+                file_hash_info.length - remaining,
+                read_time,
+            );
+        }
+    }
+    Ok(())
 }
 
 fn hash_piece_file(
     piece_length: usize,
     piece_file_info: &[Vec<FileHashInfo>],
     tr_files: &[TrFile],
-    base_path: &Path,
-    pb: &Option<ProgressBar>,
+    f_path_list: &[PathBuf],
+    progress: &Progress,
     n_jobs: usize,
-) -> TrResult<Vec<[u8; SHA1_HASH_SIZE]>> {
-    let f_path_list: Vec<_> = tr_files
-        .iter()
-        .map(|tr_file| tr_file.join_full_path(base_path))
-        .collect();
+    read_buffer: Option<usize>,
+) -> TrResult<(Vec<Option<PieceHash>>, PerfStats)> {
+    let buffers = PieceBufferPool::new(resolve_read_buffer(read_buffer, piece_length));
 
-    let results: Result<Vec<[u8; SHA1_HASH_SIZE]>, TrError> = {
-        let pool = ThreadPoolBuilder::new()
-            .num_threads(n_jobs)
-            .build()
-            .map_err(|e| TrError::ParseError(format!("Failed to create thread pool: {e}")))?;
+    let perf = PerfStats::new(tr_files, f_path_list.to_vec());
 
-        pool.install(|| {
-            piece_file_info
-                .par_iter()
-                .map(|piece| -> TrResult<[u8; SHA1_HASH_SIZE]> {
-                    let mut hasher = Sha1::new();
+    let mut hashes = build_pool(n_jobs)?.install(|| {
+        piece_file_info
+            .iter()
+            .enumerate()
+            // handed out one by one in list order, so the front of the list is read first
+            .par_bridge()
+            .map(|(i, piece)| -> TrResult<(usize, Option<PieceHash>)> {
+                if time_up() {
+                    return Ok((i, None));
+                }
+                let hash_arr = hash_piece(piece, f_path_list, &buffers, Some(&perf))?;
+                progress.inc();
+                Ok((i, Some(hash_arr)))
+            })
+            .collect::<TrResult<Vec<(usize, Option<PieceHash>)>>>()
+    })?;
+    hashes.sort_unstable_by_key(|(i, _)| *i);
+    Ok((hashes.into_iter().map(|(_, hash)| hash).collect(), perf))
+}
 
-                    FIXED_BUFFER.with(|buf_cell| -> TrResult<()> {
-                        let mut buf = buf_cell.borrow_mut();
-                        if buf.capacity() < piece_length {
-                            buf.resize(piece_length, 0);
-                        }
+#[cfg(test)]
+mod tests {
+    use std::fs::{create_dir_all, remove_dir_all, write};
 
-                        for file_hash_info in piece {
-                            let f_path = &f_path_list[file_hash_info.file_index];
-                            let mut f = File::open(f_path)?;
-                            f.seek(SeekFrom::Start(file_hash_info.file_offset as u64))?;
+    use super::*;
+    use crate::torrent::TorrentBuilder;
 
-                            let buf_slice = &mut buf[..file_hash_info.length];
-                            let n = f.read(buf_slice)?;
-                            hasher.update(&buf_slice[..n]);
-                        }
-                        Ok(())
-                    })?;
+    /// Fresh directory under the system temp dir, removed on drop.
+    struct TempDir(PathBuf);
 
-                    let calc_hash = hasher.finalize();
-                    let mut hash_arr = [0u8; SHA1_HASH_SIZE];
-                    hash_arr.copy_from_slice(&calc_hash);
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("tur-{name}-{}", std::process::id()));
+            let _ = remove_dir_all(&dir);
+            create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
 
-                    if let Some(pb) = pb {
-                        pb.inc(1);
-                    }
+        fn path(&self, name: &str) -> String {
+            self.0.join(name).to_string_lossy().into_owned()
+        }
+    }
 
-                    Ok(hash_arr)
-                })
-                .collect()
-        })
-    };
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = remove_dir_all(&self.0);
+        }
+    }
+
+    fn content(len: usize, seed: u8) -> Vec<u8> {
+        (0..len).map(|i| (i % 251) as u8 ^ seed).collect()
+    }
 
-    results
+    fn sha256_hex(data: &[u8]) -> String {
+        hex::encode(Sha256::digest(data))
+    }
+
+    fn roots(info: &TrInfo) -> Vec<Option<String>> {
+        info.v2
+            .as_ref()
+            .unwrap()
+            .pieces_roots
+            .iter()
+            .map(|root| root.map(hex::encode))
+            .collect()
+    }
+
+    /// Known answers below come from an independent BEP 52 implementation over the
+    /// same content, hashing 16 KiB blocks with the tree padded by zero leaves.
+    const ROOT_A: &str = "9bdc8654cfb9e9c52188016ba993ba06782647690c4ce6d99c9139cdb2915ad9";
+    const ROOT_B: &str = "1ae29001020bb3ec7297fb4055b75834ef214c201ba7f825216a42f1a36e2580";
+    const ROOT_D: &str = "dd1d4019e5a26840c2c07892ba17047042577e829541744a5dd610aef5d71a9b";
+    const LAYERS: &str = "73b2f56691e7b36ef45b40ab51a3f74d9458b3a66f56169f4420e5a4ddfe7edf";
+
+    /// `kat/` with a part block, three blocks of a 64 KiB piece, an empty file and a file
+    /// of three pieces, whose piece layer is padded to four.
+    fn kat_dir(dir: &TempDir) -> String {
+        let kat = dir.path("kat");
+        create_dir_all(dir.path("kat/sub")).unwrap();
+        write(dir.path("kat/a.bin"), content(1000, 1)).unwrap();
+        write(dir.path("kat/b.bin"), content(3 * V2_BLOCK_SIZE - 10, 2)).unwrap();
+        write(dir.path("kat/c.bin"), b"").unwrap();
+        write(
+            dir.path("kat/sub/d.bin"),
+            content(2 * 65536 + V2_BLOCK_SIZE + 3, 3),
+        )
+        .unwrap();
+        kat
+    }
+
+    #[test]
+    fn merkle_root_pads_to_width() {
+        let leaf = |data: &[u8]| -> MerkleHash { Sha256::digest(data).into() };
+        let (a, b, c) = (leaf(b"a"), leaf(b"b"), leaf(b"c"));
+        assert_eq!(merkle_root(vec![a], 1, [0; 32]), a);
+        assert_eq!(
+            hex::encode(merkle_root(vec![a, b, c], 4, [0; 32])),
+            "d0a664079d491a97357efa1ce1eab5aeb566adef78a2b910e8d13e901e192832"
+        );
+    }
+
+    #[test]
+    fn v2_hashes_match_known_answers() {
+        let dir = TempDir::new("kat-v2");
+        let torrent = TorrentBuilder::new()
+            .piece_length(65536)
+            .meta_version(MetaVersion::V2)
+            .build(&kat_dir(&dir))
+            .unwrap();
+        let info = torrent.get_info().unwrap();
+        assert_eq!(
+            roots(info),
+            [Some(ROOT_A), Some(ROOT_B), None, Some(ROOT_D)].map(|r| r.map(String::from))
+        );
+        assert_eq!(sha256_hex(&info.piece_layers().unwrap()), LAYERS);
+        assert_eq!(
+            torrent.info_hash_v2().unwrap(),
+            "b0a2930aad2c048967943ee1773b110b382efc3dcc384bc5ba599c21afecdb09"
+        );
+        assert!(torrent.info_hash_v1().is_none());
+    }
+
+    #[test]
+    fn single_file_v2_is_listed_under_its_name() {
+        let dir = TempDir::new("kat-solo");
+        write(dir.path("solo.bin"), content(20000, 4)).unwrap();
+        let torrent = TorrentBuilder::new()
+            .piece_length(V2_BLOCK_SIZE)
+            .meta_version(MetaVersion::V2)
+            .build(&dir.path("solo.bin"))
+            .unwrap();
+        let info = torrent.get_info().unwrap();
+        assert_eq!(
+            roots(info),
+            [Some(String::from(
+                "cda55141bafc4adc2953c74ca44dc6a44b31eda673981eed3cc31e2394065dea"
+            ))]
+        );
+        assert_eq!(
+            hex::encode(info.v2.as_ref().unwrap().piece_layers[0].as_ref().unwrap()),
+            "d61b922b01a422e0deaf3bfb70a825e6e14e83b1b84761f2aa764092cfb92d89\
+             2a43d62496ea3eb5634e3f3821144b822b07f7786f9d8f6bb9c0e3dd52975e9b"
+        );
+        assert_eq!(
+            torrent.info_hash_v2().unwrap(),
+            "8263f445176a6cb33345905fd2701625a036acc6d6bdaffe6cca06485cda6cce"
+        );
+    }
+
+    #[test]
+    fn hybrid_hashes_match_known_answers() {
+        let dir = TempDir::new("kat-hybrid");
+        let kat = kat_dir(&dir);
+        let torrent = TorrentBuilder::new()
+            .piece_length(65536)
+            .meta_version(MetaVersion::Hybrid)
+            .build(&kat)
+            .unwrap();
+        let info = torrent.get_info().unwrap();
+        let paths: Vec<String> = info
+            .tr_files()
+            .unwrap()
+            .iter()
+            .map(|f| f.path.join("/"))
+            .collect();
+        // no padding after the empty file, which ends on a piece boundary, or the last
+        assert_eq!(
+            paths,
+            [
+                "a.bin",
+                ".pad/64536",
+                "b.bin",
+                ".pad/16394",
+                "c.bin",
+                "sub/d.bin"
+            ]
+        );
+        assert_eq!(
+            roots(info),
+            [Some(ROOT_A), None, Some(ROOT_B), None, None, Some(ROOT_D)]
+                .map(|r| r.map(String::from))
+        );
+        assert_eq!(sha256_hex(&info.piece_layers().unwrap()), LAYERS);
+        assert_eq!(
+            torrent.info_hash_v1().unwrap(),
+            "ea2305f262ccbfde3fc1a0a875b4048709dcbfb7"
+        );
+        assert_eq!(
+            torrent.info_hash_v2().unwrap(),
+            "452594b7831a3a31938b162c456e23560fc34ace8187cfdaa7fcb1c4acb521ac"
+        );
+
+        // the one-pass v1 pieces are those of a padded v1 torrent in the same order
+        let v1 = TorrentBuilder::new()
+            .piece_length(65536)
+            .pad_files(true)
+            .walk_mode(WalkMode::Alphabetical)
+            .build(&kat)
+            .unwrap();
+        assert_eq!(v1.get_info().unwrap().pieces, info.pieces);
+    }
+
+    #[test]
+    fn auto_piece_size_keeps_at_most_2000_pieces() {
+        assert_eq!(auto_piece_size(0), MIN_PIECE_SIZE);
+        assert_eq!(auto_piece_size(2000 << 14), 14);
+        assert_eq!(auto_piece_size((2000 << 14) + 1), 15);
+        assert_eq!(auto_piece_size(2000 << 20), 20);
+        assert_eq!(auto_piece_size(1 << 40), AUTO_MAX_PIECE_SIZE);
+    }
 }
@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::fs::read_to_string;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use serde::{Deserialize, Serialize};
+
+use crate::magnet::{InfoHash, announce, announce_stopped, peer_id};
+use crate::utils::{TrError, TrResult, write_replacing};
+use crate::warnings::{WarningKind, warn};
+
+const PARALLEL_TRACKERS: usize = 8;
+
+/// Outcome of one announce. A tracker refusing the torrent still answered, so it is up,
+/// but it does not work for the torrent it was announced with.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TrackerHealth {
+    pub up: bool,
+    /// The tracker answered with a failure reason, such as an unregistered torrent.
+    #[serde(default)]
+    pub refused: bool,
+    /// Hex info-hash announced with, which a refusal applies to.
+    #[serde(default)]
+    pub info_hash: String,
+    /// The tracker's failure reason, or why it could not be reached.
+    pub message: Option<String>,
+    pub latency_ms: u64,
+    /// Unix timestamp of the announce.
+    pub checked: i64,
+}
+
+impl TrackerHealth {
+    /// Whether the tracker serves the torrent with the hex info-hash `info_hash`.
+    pub fn works_for(&self, info_hash: &str) -> bool {
+        self.up && !(self.refused && self.info_hash == info_hash)
+    }
+}
+
+/// Results of earlier runs by announce URL, so a tracker shared by many torrents is
+/// announced to once per TTL rather than once per torrent and run.
+#[derive(Default, Serialize, Deserialize)]
+pub struct HealthCache {
+    trackers: BTreeMap<String, TrackerHealth>,
+}
+
+impl HealthCache {
+    /// Reads the cache, or starts an empty one if there is none or it is unreadable.
+    pub fn load(path: &str) -> TrResult<Self> {
+        let content = match read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(HealthCache::default());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(serde_json::from_str(&content).unwrap_or_else(|e| {
+            warn(
+                WarningKind::Config,
+                format!("Invalid tracker cache '{path}', starting an empty one: {e}"),
+            );
+            HealthCache::default()
+        }))
+    }
+
+    /// The cached result for `url` if it is younger than `ttl`.
+    pub fn fresh(&self, url: &str, ttl: Duration) -> Option<&TrackerHealth> {
+        let health = self.trackers.get(url)?;
+        let age = now() - health.checked;
+        (0..ttl.as_secs() as i64).contains(&age).then_some(health)
+    }
+
+    pub fn insert(&mut self, url: String, health: TrackerHealth) {
+        self.trackers.insert(url, health);
+    }
+
+    /// Writes the cache without the results that are older than `ttl`.
+    pub fn save(&mut self, path: &str, ttl: Duration) -> TrResult<()> {
+        let oldest = now() - ttl.as_secs() as i64;
+        self.trackers.retain(|_, health| health.checked >= oldest);
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| TrError::EncodingError(e.to_string()))?;
+        write_replacing(path.as_ref(), json)
+    }
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Announces to every tracker once, `PARALLEL_TRACKERS` at a time, each with the
+/// info-hash of a torrent listing it. Results are in the order of `trackers`.
+/// Accepted announces are followed by `event=stopped`, so no peer is left behind.
+pub fn check(trackers: &[(String, InfoHash)]) -> Vec<TrackerHealth> {
+    let peer_id = peer_id();
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<TrackerHealth>>> = Mutex::new(vec![None; trackers.len()]);
+    thread::scope(|s| {
+        for _ in 0..PARALLEL_TRACKERS.min(trackers.len()) {
+            s.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some((url, info_hash)) = trackers.get(i) else {
+                        break;
+                    };
+                    let start = Instant::now();
+                    let result = announce(url, info_hash, &peer_id);
+                    let latency_ms = start.elapsed().as_millis() as u64;
+                    if result.is_ok() {
+                        let _ = announce_stopped(url, info_hash, &peer_id);
+                    }
+                    let refused = matches!(result, Err(TrError::TrackerRefused(_)));
+                    let health = TrackerHealth {
+                        up: result.is_ok() || refused,
+                        refused,
+                        info_hash: hex::encode(info_hash),
+                        message: result.err().map(|e| e.to_string()),
+                        latency_ms,
+                        checked: now(),
+                    };
+                    results.lock().unwrap_or_else(|e| e.into_inner())[i] = Some(health);
+                }
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .flatten()
+        .collect()
+}
@@ -1,8 +1,27 @@
-use indicatif::{ProgressBar, ProgressStyle};
+#[cfg(feature = "cli")]
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
 use std::fmt::{Display, Formatter, Result as fmtResult};
-use std::io::Error as ioError;
+use std::fs;
+use std::io::{Error as ioError, ErrorKind};
+use std::path::Path;
+#[cfg(feature = "cli")]
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+#[cfg(feature = "cli")]
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+#[cfg(feature = "cli")]
+use std::time::Instant;
 use std::{error, string};
 
+#[cfg(feature = "cli")]
+use crate::resources::Usage;
+
+static PROGRESS_MODE: OnceLock<ProgressMode> = OnceLock::new();
+static RESOURCE_REPORT: OnceLock<Duration> = OnceLock::new();
+static READ_ONLY: AtomicBool = AtomicBool::new(false);
+
 #[derive(Debug)]
 pub enum TrError {
     IO(ioError),
@@ -11,6 +30,20 @@ pub enum TrError {
     MissingField(String),
     ParseError(String),
     EncodingError(String),
+    /// Verification ran to the end and found damaged content.
+    VerificationFailed {
+        files: usize,
+        pieces: usize,
+    },
+    /// The target is not named like the torrent, so it is likely the wrong data.
+    TargetMismatch {
+        target: String,
+        torrent: String,
+    },
+    /// An output file exists and overwriting it was not asked for.
+    OutputExists(String),
+    /// A tracker answered an announce with a failure reason.
+    TrackerRefused(String),
 }
 
 impl Display for TrError {
@@ -22,6 +55,17 @@ impl Display for TrError {
             TrError::MissingField(field) => write!(f, "Missing field: {field}"),
             TrError::ParseError(msg) => write!(f, "Parse error: {msg}"),
             TrError::EncodingError(msg) => write!(f, "Encoding error: {msg}"),
+            TrError::VerificationFailed { files, pieces } => {
+                write!(f, "Verification failed: {pieces} pieces in {files} files")
+            }
+            TrError::TargetMismatch { target, torrent } => write!(
+                f,
+                "Target name '{target}' does not match torrent name '{torrent}'"
+            ),
+            TrError::OutputExists(path) => {
+                write!(f, "{path} already exists, use -f to overwrite")
+            }
+            TrError::TrackerRefused(reason) => write!(f, "Tracker refused: {reason}"),
         }
     }
 }
@@ -34,6 +78,13 @@ impl From<ioError> for TrError {
     }
 }
 
+#[cfg(feature = "cli")]
+impl From<dialoguer::Error> for TrError {
+    fn from(err: dialoguer::Error) -> Self {
+        TrError::IO(err.into())
+    }
+}
+
 impl From<string::FromUtf8Error> for TrError {
     fn from(err: string::FromUtf8Error) -> Self {
         TrError::EncodingError(format!("UTF-8 conversion error: {err}"))
@@ -75,27 +126,558 @@ pub fn human_size(bytes: usize) -> String {
     format!("{bytes} B")
 }
 
-pub fn make_progress_bar(total: usize, quiet: bool) -> Option<ProgressBar> {
-    if quiet {
-        None
+/// Checks a `--web-seed` or `--http-seed` URL, which clients fetch content from.
+pub fn parse_http_url(s: &str) -> Result<String, String> {
+    if s.starts_with("http://") || s.starts_with("https://") {
+        Ok(s.to_string())
     } else {
+        Err(format!("Invalid URL '{s}', expected an http or https URL"))
+    }
+}
+
+/// Parses a `--node` as `host:port`, `[addr]:port` for IPv6.
+pub fn parse_node(s: &str) -> Result<(String, u16), String> {
+    let invalid =
+        || format!("Invalid node '{s}', expected host:port such as router.bittorrent.com:6881");
+    let (host, port) = s.rsplit_once(':').ok_or_else(invalid)?;
+    let host = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    let port: u16 = port.parse().map_err(|_| invalid())?;
+    if host.is_empty() || port == 0 {
+        return Err(invalid());
+    }
+    Ok((host.to_string(), port))
+}
+
+/// Parses sizes like `65536`, `256K`, `4M`, `16MiB` or `1G` (binary units).
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num: usize = num
+        .parse()
+        .map_err(|_| format!("invalid size '{s}', expected e.g. 256K, 4M or 16MiB"))?;
+    let shift = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        _ => return Err(format!("invalid size unit in '{s}'")),
+    };
+    num.checked_mul(1usize << shift)
+        .filter(|&size| size > 0)
+        .ok_or_else(|| format!("invalid size '{s}'"))
+}
+
+/// Parses a piece size given as an exponent (`22`, numbers below 64) or as a size
+/// (`4M`, `256K`, `16MiB`) that is a power of two. Returns the exponent.
+pub fn parse_piece_size(s: &str) -> Result<u8, String> {
+    if let Ok(n) = s.trim().parse::<u8>()
+        && n < 64
+    {
+        return Ok(n);
+    }
+    let size = parse_size(s)?;
+    if !size.is_power_of_two() {
+        return Err(format!(
+            "Invalid piece size '{s}', expected a power of two such as 4M or an exponent such as 22"
+        ));
+    }
+    Ok(size.trailing_zeros() as u8)
+}
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// RFC 4648 base32 without padding, the form of info-hashes in older magnet links.
+pub fn base32_encode(bytes: &[u8]) -> String {
+    let (mut bits, mut n_bits) = (0u64, 0);
+    let mut out = String::with_capacity(bytes.len().div_ceil(5) * 8);
+    for &b in bytes {
+        bits = (bits << 8) | u64::from(b);
+        n_bits += 8;
+        while n_bits >= 5 {
+            n_bits -= 5;
+            out.push(BASE32_ALPHABET[((bits >> n_bits) & 31) as usize] as char);
+        }
+    }
+    if n_bits > 0 {
+        out.push(BASE32_ALPHABET[((bits << (5 - n_bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+/// Reverse of `base32_encode`, in either case; `None` for a character outside the
+/// alphabet. Bits left over after the last full byte are dropped.
+pub fn base32_decode(s: &str) -> Option<Vec<u8>> {
+    let (mut bits, mut n_bits) = (0u64, 0);
+    let mut out = Vec::with_capacity(s.len() * 5 / 8);
+    for c in s.bytes().map(|c| c.to_ascii_uppercase()) {
+        let value = BASE32_ALPHABET.iter().position(|&a| a == c)?;
+        bits = (bits << 5) | value as u64;
+        n_bits += 5;
+        if n_bits >= 8 {
+            n_bits -= 8;
+            out.push((bits >> n_bits) as u8);
+        }
+    }
+    Some(out)
+}
+
+/// Replaces `{key}` placeholders with their values, unknown placeholders are kept as-is.
+/// The template is read once, so a value holding `{name}` or the like is not filled in.
+pub fn fill_template(template: &str, vars: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        out.push_str(&rest[..open]);
+        rest = &rest[open..];
+        let value = rest.find('}').and_then(|close| {
+            let key = &rest[1..close];
+            vars.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| (value, close))
+        });
+        match value {
+            Some((value, close)) => {
+                out.push_str(value);
+                rest = &rest[close + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+pub fn url_encode(s: &str) -> String {
+    url_encode_bytes(s.as_bytes())
+}
+
+/// Percent-encodes raw bytes, e.g. an info-hash in a tracker announce.
+pub fn url_encode_bytes(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len());
+    for &b in bytes {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(b as char)
+            }
+            _ => out.push_str(&format!("%{b:02X}")),
+        }
+    }
+    out
+}
+
+/// Decodes `%XX` escapes and `+` as space, as in magnet link parameters. Invalid escapes
+/// are kept as they are.
+pub fn url_decode(s: &str) -> String {
+    String::from_utf8_lossy(&percent_decode(&s.replace('+', " "))).into_owned()
+}
+
+/// Decodes `%XX` escapes to bytes, leaving `+` alone as in the user info of a URL.
+/// Invalid escapes are kept as they are.
+pub fn percent_decode(s: &str) -> Vec<u8> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = match bytes[i] {
+            b'%' => bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match escaped {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+#[derive(Clone, Copy)]
+pub enum ProgressMode {
+    Classic,
+    Minimal,
+    Dots,
+    Every(Duration), // plain lines at an interval, no ANSI
+}
+
+/// Parses `classic`, `minimal`, `dots` or `every=<interval>` with an interval like
+/// `30s`, `5m`, `1h` or `500ms`.
+pub fn parse_progress(s: &str) -> Result<ProgressMode, String> {
+    match s {
+        "classic" => return Ok(ProgressMode::Classic),
+        "minimal" => return Ok(ProgressMode::Minimal),
+        "dots" => return Ok(ProgressMode::Dots),
+        _ => {}
+    }
+    let interval = s.strip_prefix("every=").ok_or_else(|| {
+        format!("invalid progress mode '{s}', expected classic, minimal, dots or every=30s")
+    })?;
+    Ok(ProgressMode::Every(parse_duration(interval)?))
+}
+
+/// Parses durations like `30s`, `5m`, `2h`, `7d`, `500ms` or `1h30m`; a bare number is seconds.
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    match parse_duration_or_zero(s)? {
+        Duration::ZERO => Err(format!("invalid duration '{s}'")),
+        duration => Ok(duration),
+    }
+}
+
+/// `parse_duration` that also accepts zero, for cache lifetimes.
+pub fn parse_duration_or_zero(s: &str) -> Result<Duration, String> {
+    let mut rest = s.trim();
+    if rest.is_empty() {
+        return Err(format!("invalid duration '{s}'"));
+    }
+    let mut ms: u64 = 0;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (num, tail) = rest.split_at(split);
+        let num: u64 = num.parse().map_err(|_| format!("invalid duration '{s}'"))?;
+        let unit_len = tail
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let factor = match unit {
+            "ms" => 1,
+            "" | "s" => 1000,
+            "m" => 60 * 1000,
+            "h" => 60 * 60 * 1000,
+            "d" => 24 * 60 * 60 * 1000,
+            _ => return Err(format!("invalid duration unit in '{s}'")),
+        };
+        ms = num
+            .checked_mul(factor)
+            .and_then(|part| ms.checked_add(part))
+            .ok_or_else(|| format!("invalid duration '{s}'"))?;
+        rest = tail;
+    }
+    Ok(Duration::from_millis(ms))
+}
+
+/// Makes every later `check_write` fail, for `--assert-readonly`.
+pub fn set_read_only() {
+    READ_ONLY.store(true, Ordering::Relaxed);
+}
+
+/// Called before any file is created, replaced or removed.
+pub fn check_write(path: &Path) -> TrResult<()> {
+    if READ_ONLY.load(Ordering::Relaxed) {
+        return Err(ioError::new(
+            ErrorKind::PermissionDenied,
+            format!(
+                "refusing to write '{}' with --assert-readonly",
+                path.display()
+            ),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Writes to a temporary file next to `path`, then renames it over `path`, so a crash
+/// or full disk midway leaves the previous file rather than a truncated one.
+pub fn write_replacing(path: &Path, contents: impl AsRef<[u8]>) -> TrResult<()> {
+    check_write(path)?;
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+pub fn set_progress_mode(mode: ProgressMode) {
+    let _ = PROGRESS_MODE.set(mode);
+}
+
+/// Reports resource usage at this interval while a progress bar runs.
+pub fn set_resource_report(interval: Duration) {
+    let _ = RESOURCE_REPORT.set(interval);
+}
+
+#[cfg(feature = "cli")]
+fn progress_mode() -> ProgressMode {
+    PROGRESS_MODE
+        .get()
+        .copied()
+        .unwrap_or(ProgressMode::Classic)
+}
+
+/// Piece progress shared by create, verify and compare, does nothing when quiet.
+#[derive(Clone)]
+pub struct Progress {
+    #[cfg(not(feature = "cli"))]
+    quiet: bool,
+    #[cfg(feature = "cli")]
+    bar: Option<ProgressBar>,
+    /// Only held, dropping the last clone stops the logger.
+    #[cfg(feature = "cli")]
+    _logger: Option<Arc<LoggerGuard>>,
+}
+
+/// Stops the `every=` logger thread and waits for it when the last `Progress` clone is
+/// dropped, so it also ends when hashing fails or is cancelled before `finish`.
+#[cfg(feature = "cli")]
+struct LoggerGuard {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "cli")]
+impl Drop for LoggerGuard {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Without the `cli` feature nothing is drawn, messages still go to stderr.
+#[cfg(not(feature = "cli"))]
+impl Progress {
+    pub fn new(_total: usize, quiet: bool) -> Self {
+        Progress { quiet }
+    }
+
+    pub fn lines(quiet: bool) -> Self {
+        Progress { quiet }
+    }
+
+    pub fn inc(&self) {}
+
+    pub fn println(&self, msg: &str) {
+        if !self.quiet {
+            eprintln!("{msg}");
+        }
+    }
+
+    pub fn finish(self) {}
+}
+
+#[cfg(feature = "cli")]
+impl Progress {
+    pub fn new(total: usize, quiet: bool) -> Self {
+        if quiet {
+            return Progress {
+                bar: None,
+                _logger: None,
+            };
+        }
+        let template = match progress_mode() {
+            ProgressMode::Classic => {
+                "{spinner:.green} [{bar:40.cyan/blue}] [{pos}/{len}] pieces ({percent}%, eta: {eta})"
+            }
+            ProgressMode::Minimal => "[{pos}/{len}] pieces ({percent}%, eta: {eta})",
+            ProgressMode::Dots => "{spinner:.green} {bar:40.green} {percent:>3}% eta {eta}",
+            ProgressMode::Every(interval) => {
+                let pb =
+                    ProgressBar::with_draw_target(Some(total as u64), ProgressDrawTarget::hidden());
+                let logger = spawn_progress_logger(pb.clone(), interval);
+                spawn_resource_logger(&pb);
+                return Progress {
+                    bar: Some(pb),
+                    _logger: Some(Arc::new(logger)),
+                };
+            }
+        };
+        let progress_chars = match progress_mode() {
+            ProgressMode::Dots => "●●·",
+            _ => "#>-",
+        };
         let pb = ProgressBar::new(total as u64);
         pb.set_style(
-            ProgressStyle::with_template("{spinner:.green} [{bar:40.cyan/blue}] [{pos}/{len}] pieces ({percent}%, eta: {eta})")
+            ProgressStyle::with_template(template)
                 .unwrap()
-                .progress_chars("#>-"),
+                .tick_chars("⠁⠂⠄⡀⢀⠠⠐⠈✓")
+                .progress_chars(progress_chars),
         );
-        Some(pb)
+        spawn_resource_logger(&pb);
+        Progress {
+            bar: Some(pb),
+            _logger: None,
+        }
+    }
+
+    /// No bar, `println` prints plain lines unless quiet, for messages spanning several
+    /// hashing runs.
+    pub fn lines(quiet: bool) -> Self {
+        Progress {
+            bar: (!quiet).then(ProgressBar::hidden),
+            _logger: None,
+        }
+    }
+
+    pub fn inc(&self) {
+        if let Some(pb) = &self.bar {
+            pb.inc(1);
+        }
+    }
+
+    /// Prints above the bar, or as a plain line when no bar is drawn; nothing when quiet.
+    pub fn println(&self, msg: &str) {
+        match &self.bar {
+            Some(pb) if !pb.is_hidden() => pb.println(msg),
+            Some(_) => eprintln!("{msg}"),
+            None => {}
+        }
     }
-}
 
-pub fn finish_progress_bar(pb: Option<ProgressBar>, pieces_count: usize) {
-    if let Some(pb) = pb {
+    /// Leaves the bar drawn at 100% and prints the elapsed time.
+    pub fn finish(self) {
+        let Some(pb) = &self.bar else {
+            return;
+        };
+        let pieces_count = pb.length().unwrap_or_default();
         let elapsed = pb.elapsed();
-        pb.finish_and_clear();
-        println!(
-            "\x1b[32m✓\x1b[0m [\x1b[36m########################################\x1b[0m] [{pieces_count}/{pieces_count}] pieces (100%, eta: 0s)"
-        );
-        println!("Processed {pieces_count} pieces in {elapsed:.2?}");
+        pb.set_position(pieces_count);
+        pb.finish();
+        eprintln!("Processed {pieces_count} pieces in {elapsed:.2?}");
+    }
+}
+
+/// Prints the resource usage above the bar per `set_resource_report` interval, until the
+/// bar is finished or dropped.
+#[cfg(feature = "cli")]
+fn spawn_resource_logger(pb: &ProgressBar) {
+    let Some(&interval) = RESOURCE_REPORT.get() else {
+        return;
+    };
+    let weak = pb.downgrade();
+    thread::spawn(move || {
+        let mut next = Instant::now() + interval;
+        while let Some(pb) = weak.upgrade().filter(|pb| !pb.is_finished()) {
+            drop(pb);
+            thread::sleep(Duration::from_millis(100).min(interval));
+            if Instant::now() < next {
+                continue;
+            }
+            next += interval;
+            let Some(pb) = weak.upgrade() else {
+                break;
+            };
+            let usage = Usage::sample().to_string();
+            if pb.is_hidden() {
+                eprintln!("{usage}");
+            } else {
+                pb.println(usage);
+            }
+        }
+    });
+}
+
+/// Prints one plain line per interval until the hidden bar is finished or the
+/// returned guard is dropped.
+#[cfg(feature = "cli")]
+fn spawn_progress_logger(pb: ProgressBar, interval: Duration) -> LoggerGuard {
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let handle = thread::spawn(move || {
+        let mut next = Instant::now() + interval;
+        let done = || pb.is_finished() || stopped.load(Ordering::Relaxed);
+        while !done() {
+            thread::sleep(Duration::from_millis(100).min(interval));
+            if Instant::now() < next || done() {
+                continue;
+            }
+            next += interval;
+            let pos = pb.position();
+            let len = pb.length().unwrap_or_default().max(1);
+            eprintln!(
+                "Progress: {pos}/{len} pieces ({}%), elapsed {}s, eta {}s",
+                pos * 100 / len,
+                pb.elapsed().as_secs(),
+                pb.eta().as_secs()
+            );
+        }
+    });
+    LoggerGuard {
+        stop,
+        handle: Some(handle),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars() -> [(&'static str, String); 2] {
+        [
+            ("name", String::from("Data")),
+            ("size", String::from("1.0 GiB")),
+        ]
+    }
+
+    #[test]
+    fn fill_template_fills_known_placeholders() {
+        let cases = [
+            ("{name}", "Data"),
+            ("{name} ({size})", "Data (1.0 GiB)"),
+            ("{name}{name}", "DataData"),
+            ("no placeholders", "no placeholders"),
+            ("", ""),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(fill_template(template, &vars()), expected, "{template}");
+        }
+    }
+
+    #[test]
+    fn fill_template_keeps_unknown_and_unclosed_braces() {
+        let cases = [
+            ("{date}", "{date}"),
+            ("{}", "{}"),
+            ("{name", "{name"),
+            ("}{name}{", "}Data{"),
+            ("{{name}}", "{Data}"),
+        ];
+        for (template, expected) in cases {
+            assert_eq!(fill_template(template, &vars()), expected, "{template}");
+        }
+    }
+
+    #[test]
+    fn fill_template_does_not_fill_values() {
+        let vars = [
+            ("name", String::from("{size}")),
+            ("size", String::from("1 KiB")),
+        ];
+        assert_eq!(fill_template("{name} {size}", &vars), "{size} 1 KiB");
+    }
+
+    #[test]
+    fn piece_size_is_an_exponent_or_a_power_of_two() {
+        let cases = [
+            ("22", 22),
+            (" 14 ", 14),
+            ("4M", 22),
+            ("256K", 18),
+            ("16MiB", 24),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(parse_piece_size(s), Ok(expected), "{s}");
+        }
+        // numbers from 64 up are sizes in bytes
+        assert_eq!(parse_piece_size("64"), Ok(6));
+        assert_eq!(parse_piece_size("65536"), Ok(16));
+        for s in ["3M", "100", "4X", "M", ""] {
+            assert!(parse_piece_size(s).is_err(), "{s}");
+        }
     }
 }
@@ -0,0 +1,76 @@
+use std::fmt::{Display, Formatter, Result as fmtResult};
+use std::sync::Mutex;
+
+static WARNINGS: Mutex<Vec<Warning>> = Mutex::new(Vec::new());
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum WarningKind {
+    Config,
+    SkippedFile,
+    Symlink,
+    Output,
+    Compatibility,
+    Data,
+    Network,
+}
+
+impl Display for WarningKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        let label = match self {
+            WarningKind::Config => "config",
+            WarningKind::SkippedFile => "skipped file",
+            WarningKind::Symlink => "symlink",
+            WarningKind::Output => "output",
+            WarningKind::Compatibility => "compatibility",
+            WarningKind::Data => "data",
+            WarningKind::Network => "network",
+        };
+        write!(f, "{label}")
+    }
+}
+
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmtResult {
+        write!(f, "W: [{}] {}", self.kind, self.message)
+    }
+}
+
+/// Records a warning to be shown by `print_summary` instead of printing it between
+/// progress output.
+pub fn warn(kind: WarningKind, message: impl Into<String>) {
+    WARNINGS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Warning {
+            kind,
+            message: message.into(),
+        });
+}
+
+/// Takes the warnings recorded so far, grouped by kind in the order they were raised.
+fn take() -> Vec<Warning> {
+    let mut warnings = std::mem::take(&mut *WARNINGS.lock().unwrap_or_else(|e| e.into_inner()));
+    warnings.sort_by_key(|w| w.kind);
+    warnings
+}
+
+/// Prints and clears the recorded warnings on stderr.
+pub fn print_summary() {
+    let warnings = take();
+    if warnings.is_empty() {
+        return;
+    }
+    eprintln!(
+        "{} warning{}:",
+        warnings.len(),
+        if warnings.len() == 1 { "" } else { "s" }
+    );
+    for warning in warnings {
+        eprintln!("{warning}");
+    }
+}
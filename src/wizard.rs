@@ -0,0 +1,263 @@
+use std::env;
+use std::path::Path;
+
+use dialoguer::{Confirm, Input, Select};
+use walkdir::WalkDir;
+
+use crate::torrent::Torrent;
+use crate::tr_info::auto_piece_size;
+use crate::utils::{TrResult, human_size};
+use crate::{Args, Config, MAX_PIECE_SIZE, MIN_PIECE_SIZE, choose_trackers, output_torrent_path};
+
+/// Asks for everything create mode needs and fills `args` in, trackers are chosen from
+/// the configured list.
+pub fn run(args: &mut Args, config: &mut Config) -> TrResult<()> {
+    let target: String = Input::new()
+        .with_prompt("Target file or directory")
+        .validate_with(|input: &String| -> Result<(), &str> {
+            if Path::new(input.trim()).exists() {
+                Ok(())
+            } else {
+                Err("path does not exist")
+            }
+        })
+        .interact_text()?;
+    let target = target.trim().trim_end_matches(['/', '\\']).to_string();
+
+    let (total_size, file_count) = WalkDir::new(&target)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .fold((0u64, 0usize), |(size, count), meta| {
+            (size + meta.len(), count + 1)
+        });
+    let suggested = auto_piece_size(total_size as usize);
+    println!(
+        "{file_count} files, {}; suggested piece size {suggested} [{}]",
+        human_size(total_size as usize),
+        human_size(1usize << suggested)
+    );
+    let piece_size: u8 = Input::new()
+        .with_prompt(format!(
+            "Piece size ({MIN_PIECE_SIZE}..={MAX_PIECE_SIZE}, 1 << n bytes)"
+        ))
+        .default(suggested)
+        .validate_with(|input: &u8| -> Result<(), &str> {
+            if (MIN_PIECE_SIZE..=MAX_PIECE_SIZE).contains(input) {
+                Ok(())
+            } else {
+                Err("piece size must be between 14 and 27")
+            }
+        })
+        .interact_text()?;
+
+    config.tracker_list = choose_trackers(std::mem::take(&mut config.tracker_list))?;
+
+    let private = Confirm::new()
+        .with_prompt("Private torrent")
+        .default(config.private)
+        .interact()?;
+
+    // asked here rather than failing after hashing, a declined overwrite asks again
+    let output = loop {
+        let output: String = Input::new()
+            .with_prompt("Output torrent, a bare name is placed next to the target")
+            .default(format!("{target}.torrent"))
+            .validate_with(|input: &String| -> Result<(), &str> {
+                if input.trim().ends_with(".torrent") {
+                    Ok(())
+                } else {
+                    Err("output must end with .torrent")
+                }
+            })
+            .interact_text()?;
+        let output = output.trim().to_string();
+        let torrent_path = output_torrent_path(&target, &output);
+        if args.force || !Path::new(&torrent_path).exists() {
+            break output;
+        }
+        if Confirm::new()
+            .with_prompt(format!("{torrent_path} exists, overwrite"))
+            .default(false)
+            .interact()?
+        {
+            args.force = true;
+            break output;
+        }
+    };
+
+    args.input = vec![target];
+    args.piece_size = Some(piece_size);
+    args.private = private;
+    args.output = Some(output);
+    Ok(())
+}
+
+/// True when every argument is an existing absolute path, which is how Explorer starts
+/// the exe for files dropped onto it.
+pub fn launched_by_drop(args: &Args) -> bool {
+    args.command.is_none()
+        && !args.input.is_empty()
+        && env::args().count() == args.input.len() + 1
+        && args
+            .input
+            .iter()
+            .all(|input| Path::new(input).is_absolute() && Path::new(input).exists())
+}
+
+/// Offers the modes that fit the dropped paths and sets `args` up for the chosen one.
+/// Returns false when the user picks exit.
+pub fn drop_menu(args: &mut Args) -> TrResult<bool> {
+    let torrent = args.input.iter().find(|input| input.ends_with(".torrent"));
+    let dropped_data = args
+        .input
+        .iter()
+        .find(|input| !input.ends_with(".torrent"))
+        .cloned();
+    // without dropped data, look for it next to the torrent as a client saves it
+    let data = dropped_data.clone().or_else(|| {
+        let torrent = torrent?;
+        let name = Torrent::read_torrent(torrent.clone())
+            .ok()?
+            .get_info()?
+            .get_name()
+            .ok()?;
+        let data = Path::new(torrent).parent()?.join(name);
+        data.exists().then(|| data.to_string_lossy().into_owned())
+    });
+
+    let mut actions: Vec<(&str, Vec<String>, bool)> = Vec::new();
+    if let (Some(torrent), Some(data)) = (torrent, &data) {
+        actions.push(("Verify", vec![torrent.clone(), data.clone()], false));
+    }
+    if let Some(torrent) = torrent {
+        actions.push(("Show info", vec![torrent.clone()], false));
+        actions.push(("Show file tree", vec![torrent.clone()], true));
+    }
+    if let Some(data) = dropped_data {
+        actions.push(("Create torrent", vec![data], false));
+    }
+    let labels: Vec<&str> = actions
+        .iter()
+        .map(|(label, _, _)| *label)
+        .chain(["Exit"])
+        .collect();
+    let choice = Select::new()
+        .with_prompt("What do you want to do")
+        .items(&labels)
+        .default(0)
+        .interact()?;
+
+    let Some((_, input, print_tree)) = actions.into_iter().nth(choice) else {
+        return Ok(false);
+    };
+    args.input = input;
+    args.print_tree = print_tree;
+    Ok(true)
+}
+
+/// Interactive tracker editor: shows the tiers with their indices and applies moves,
+/// deletions and additions until the user saves. Returns `None` when quitting without saving.
+pub fn edit_tiers(mut tiers: Vec<Vec<String>>) -> TrResult<Option<Vec<Vec<String>>>> {
+    const ACTIONS: [&str; 6] = [
+        "Move a tier",
+        "Delete a tier",
+        "Delete a URL",
+        "Add a URL",
+        "Save",
+        "Quit without saving",
+    ];
+    loop {
+        eprintln!("\nTrackers:");
+        if tiers.is_empty() {
+            eprintln!("  (none)");
+        }
+        for (i, tier) in tiers.iter().enumerate() {
+            eprintln!("  Tier {i}: {}", tier.join(", "));
+        }
+        let tier_labels: Vec<String> = tiers
+            .iter()
+            .enumerate()
+            .map(|(i, tier)| format!("Tier {i}: {}", tier.join(", ")))
+            .collect();
+
+        match Select::new().items(ACTIONS).default(0).interact()? {
+            0 | 1 if tiers.is_empty() => eprintln!("No tiers."),
+            0 => {
+                let from = Select::new()
+                    .with_prompt("Tier to move")
+                    .items(&tier_labels)
+                    .default(0)
+                    .interact()?;
+                let positions: Vec<String> = (0..tiers.len()).map(|i| i.to_string()).collect();
+                let to = Select::new()
+                    .with_prompt("New position")
+                    .items(&positions)
+                    .default(from)
+                    .interact()?;
+                let tier = tiers.remove(from);
+                tiers.insert(to, tier);
+            }
+            1 => {
+                let i = Select::new()
+                    .with_prompt("Tier to delete")
+                    .items(&tier_labels)
+                    .default(0)
+                    .interact()?;
+                tiers.remove(i);
+            }
+            2 => {
+                let urls: Vec<(usize, usize)> = tiers
+                    .iter()
+                    .enumerate()
+                    .flat_map(|(i, tier)| (0..tier.len()).map(move |j| (i, j)))
+                    .collect();
+                if urls.is_empty() {
+                    eprintln!("No URLs.");
+                    continue;
+                }
+                let labels: Vec<String> = urls
+                    .iter()
+                    .map(|&(i, j)| format!("Tier {i}: {}", tiers[i][j]))
+                    .collect();
+                let choice = Select::new()
+                    .with_prompt("URL to delete")
+                    .items(&labels)
+                    .default(0)
+                    .interact()?;
+                let (i, j) = urls[choice];
+                tiers[i].remove(j);
+                tiers.retain(|tier| !tier.is_empty());
+            }
+            3 => {
+                let url: String = Input::new()
+                    .with_prompt("Announce URL")
+                    .validate_with(|input: &String| -> Result<(), &str> {
+                        if input.trim().is_empty() {
+                            Err("URL is empty")
+                        } else {
+                            Ok(())
+                        }
+                    })
+                    .interact_text()?;
+                let targets: Vec<String> = tier_labels
+                    .iter()
+                    .cloned()
+                    .chain([String::from("New tier at the end")])
+                    .collect();
+                let i = Select::new()
+                    .with_prompt("Add to")
+                    .items(&targets)
+                    .default(tiers.len())
+                    .interact()?;
+                match tiers.get_mut(i) {
+                    Some(tier) => tier.push(url.trim().to_string()),
+                    None => tiers.push(vec![url.trim().to_string()]),
+                }
+            }
+            4 => return Ok(Some(tiers)),
+            _ => return Ok(None),
+        }
+    }
+}